@@ -0,0 +1,130 @@
+// Brick material with section planes. Fragments on the outside of any plane
+// are discarded; back faces seen through the cut are painted with the cap
+// color so sliced bricks read as solid. See `clip.rs`. Construction playback
+// discards faces outside their reveal range too, see `construct.rs`.
+//
+// The same file is the material's deferred and plain prepass shader. The
+// plain prepass draws the shadow maps, so it discards what the main pass
+// does; it only runs while the material may discard (see
+// `sync_discarding_materials`).
+
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+    pbr_types::PbrInput,
+}
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+    pbr_prepass_functions::calculate_motion_vector,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+// must match `ClipUniform` and `MAX_CLIP_PLANES`
+struct ClipPlanes {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+}
+
+// must match `RevealUniform`
+struct Reveal {
+    shown: u32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(100) var<uniform> clip: ClipPlanes;
+@group(#{MATERIAL_BIND_GROUP}) @binding(101) var<uniform> reveal: Reveal;
+
+// Everything the section planes cut away, and the faces construction
+// playback hasn't got to or has covered again.
+fn discard_hidden(in: VertexOutput) {
+    // kept where dot(normal, p) + w >= 0, same as `Frustum`
+    for (var i = 0u; i < clip.count; i++) {
+        let plane = clip.planes[i];
+        if dot(plane.xyz, in.world_position.xyz) + plane.w < 0.0 {
+            discard;
+        }
+    }
+
+#ifdef VERTEX_UVS_B
+    // (place of the face's brick, place of the first brick covering it),
+    // split as in `bvh::reveal_halves`; whole numbers, rounded back after
+    // interpolation
+    let high = vec2<u32>(round(in.uv));
+    let low = vec2<u32>(round(in.uv_b));
+    let range = (high << vec2<u32>(12u)) | low;
+    if reveal.shown <= range.x || reveal.shown > range.y {
+        discard;
+    }
+#endif
+}
+
+fn brick_pbr_input(in: VertexOutput, is_front: bool) -> PbrInput {
+    discard_hidden(in);
+
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+    if clip.count > 0u && !is_front {
+        pbr_input.material.base_color = clip.cap_color;
+        pbr_input.material.emissive = vec4<f32>(0.0);
+    }
+    return pbr_input;
+}
+
+#ifdef PREPASS_PIPELINE
+#ifdef DEFERRED_PREPASS
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    return deferred_output(in, brick_pbr_input(in, is_front));
+}
+#else
+// Shadow maps and the depth prepass: only the discard matters, the rest is
+// what bevy's own prepass shader writes.
+#ifdef PREPASS_FRAGMENT
+@fragment
+fn fragment(in: VertexOutput) -> FragmentOutput {
+    discard_hidden(in);
+
+    var out: FragmentOutput;
+#ifdef UNCLIPPED_DEPTH_ORTHO_EMULATION
+    out.frag_depth = in.unclipped_depth;
+#endif
+#ifdef NORMAL_PREPASS
+    out.normal = vec4<f32>(normalize(in.world_normal) * 0.5 + vec3<f32>(0.5), 1.0);
+#endif
+#ifdef MOTION_VECTOR_PREPASS
+    out.motion_vector = calculate_motion_vector(in.world_position, in.previous_world_position);
+#endif
+    return out;
+}
+#else
+@fragment
+fn fragment(in: VertexOutput) {
+    discard_hidden(in);
+}
+#endif
+#endif
+#else
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    let pbr_input = brick_pbr_input(in, is_front);
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+    return out;
+}
+#endif
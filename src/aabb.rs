@@ -1,4 +1,4 @@
-use bevy::math::{IVec3, Ray3d};
+use bevy::math::{IVec3, Mat4, Ray3d, Vec3, Vec4};
 use brickadia::{save::{SaveData, Brick}, util::get_axis_size};
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -36,7 +36,9 @@ impl AABB {
         true
     }
 
-    pub fn intersects(&self, ray: Ray3d) -> bool {
+    // Distance along the ray to where it enters the box (0 when it starts
+    // inside), or `None` on a miss.
+    pub fn ray_distance(&self, ray: Ray3d) -> Option<f32> {
         let center = self.center.as_vec3();
         let halfwidths = self.halfwidths.as_vec3();
 
@@ -50,11 +52,113 @@ impl AABB {
         let tmin = t1.min(t2).max(t3.min(t4).max(t5.min(t6)));
         let tmax = t1.max(t2).min(t3.max(t4).min(t5.max(t6)));
 
-        tmax >= 0.0 && tmin <= tmax
+        (tmax >= 0.0 && tmin <= tmax).then_some(tmin.max(0.0))
     }
 
     pub fn volume(&self) -> i64 {
         let size = self.halfwidths * 2;
         size.x as i64 * size.y as i64 * size.z as i64
     }
-}
\ No newline at end of file
+
+    pub fn min(&self) -> IVec3 {
+        self.center - self.halfwidths
+    }
+
+    pub fn max(&self) -> IVec3 {
+        self.center + self.halfwidths
+    }
+
+    // Rounds the center down and grows the halfwidths to match, so an odd
+    // extent still covers both bounds.
+    pub fn from_min_max(min: IVec3, max: IVec3) -> Self {
+        let center = (min + max).div_euclid(IVec3::splat(2));
+        let halfwidths = (max - center).max(center - min);
+        Self { center, halfwidths }
+    }
+
+    pub fn union(&self, other: &AABB) -> AABB {
+        AABB::from_min_max(self.min().min(other.min()), self.max().max(other.max()))
+    }
+
+    // Bounds of a whole set of bricks, `None` when empty.
+    pub fn bounds(aabbs: &[AABB]) -> Option<AABB> {
+        let first = aabbs.first()?;
+        let (min, max) = aabbs.iter().fold((first.min(), first.max()), |(min, max), aabb| {
+            (min.min(aabb.min()), max.max(aabb.max()))
+        });
+        Some(AABB::from_min_max(min, max))
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        let min = self.min().as_vec3();
+        let max = self.max().as_vec3();
+        point.cmpge(min).all() && point.cmple(max).all()
+    }
+
+    // Squared distance from `point` to the nearest point of the box, 0 inside.
+    pub fn distance_squared(&self, point: Vec3) -> f32 {
+        let closest = point.clamp(self.min().as_vec3(), self.max().as_vec3());
+        closest.distance_squared(point)
+    }
+}
+
+// Convex volume bounded by planes `(normal, d)` with normals pointing inward,
+// so a point is inside when `normal.dot(p) + d >= 0` for every plane.
+#[derive(Debug, Default, Clone)]
+pub struct Frustum {
+    pub planes: Vec<Vec4>,
+}
+
+impl Frustum {
+    pub fn new(planes: Vec<Vec4>) -> Self {
+        Self { planes }
+    }
+
+    // Gribb/Hartmann extraction of the six planes from a camera's
+    // clip-from-world matrix (Bevy's reversed-Z, depth 0..1).
+    pub fn from_clip_from_world(clip_from_world: Mat4) -> Self {
+        let row = |i: usize| clip_from_world.row(i);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ];
+        // an infinite far plane comes out with a zero normal; it culls nothing
+        Self {
+            planes: planes.into_iter()
+                .filter(|p| p.truncate().length_squared() > f32::EPSILON)
+                .map(|p| p / p.truncate().length())
+                .collect(),
+        }
+    }
+
+    // Conservative: true unless the box is entirely behind one plane, so a box
+    // straddling a corner outside the frustum can still pass.
+    pub fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        let center = aabb.center.as_vec3();
+        let halfwidths = aabb.halfwidths.as_vec3();
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let radius = halfwidths.dot(normal.abs());
+            normal.dot(center) + plane.w >= -radius
+        })
+    }
+
+    // True only when the whole box is inside every plane.
+    pub fn contains_aabb(&self, aabb: &AABB) -> bool {
+        let center = aabb.center.as_vec3();
+        let halfwidths = aabb.halfwidths.as_vec3();
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let radius = halfwidths.dot(normal.abs());
+            normal.dot(center) + plane.w >= radius
+        })
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(point) + plane.w >= 0.0)
+    }
+}
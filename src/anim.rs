@@ -0,0 +1,406 @@
+use std::{f32::consts::PI, path::{Path, PathBuf}};
+
+use bevy::prelude::*;
+
+use crate::{
+    bookmark::{Bookmark, Bookmarks},
+    cam::{ease, CameraPose, CameraTween, IsoCamera},
+    console::{AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand},
+    construct::{BuildOrder, Construction},
+    job::RenderJob,
+    lit::Sun,
+    screenshot::{ScreenshotSettings, ShotMetadata, ShotOutput},
+    sky::Lighting,
+    SaveBVH,
+};
+
+// Rendered animations, written as numbered PNG frames for a video encoder: a
+// turntable around the build, a day of sun, a flight through the saved
+// camera views, or the build going up brick by brick. `stills` isn't a
+// motion: it writes one still per saved view, named after it, with the
+// view's lighting. A frame's camera, sun
+// and playback position come from its index alone, never from `Time`, so the
+// result is the same however long each frame takes to go through the F3
+// off-screen camera. `/animate` renders from the current view; `brs_iso
+// animate` loads a save, frames it, renders and quits.
+
+const DEFAULT_FPS: u32 = 30;
+const DEFAULT_SECONDS: f32 = 12.0;
+const DEFAULT_SIZE: UVec2 = UVec2::new(1920, 1080);
+// Frames aren't tiled, so they stay under wgpu's default texture limit.
+const MAX_FRAME_DIM: u32 = 8192;
+// Frame files are numbered with five digits.
+const MAX_FRAMES: u32 = 99_999;
+// Where the sun is at noon in a sun sweep, about 53° up. It rises along +X
+// and sets along -X.
+const NOON: Vec3 = Vec3::new(0.0, 0.8, 0.6);
+
+const ANIMATION_USAGE: &str = "animation: <turntable|sun|views|construction|stills> [<seconds>s] [<fps>fps] [<width>x<height>] [<directory>]";
+
+pub struct AnimPlugin;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationKind {
+    // One full turn around the middle of the build.
+    Turntable,
+    // Sunrise to sunset over a still camera.
+    SunSweep,
+    // Through every camera bookmark in order.
+    Views,
+    // Construction playback from nothing to the whole build, in the playback
+    // order if one is picked, else save order.
+    Construction,
+    // Each camera bookmark once, as is; takes no time.
+    Stills,
+}
+
+impl AnimationKind {
+    // in declaration order, for `name`
+    pub const NAMES: [&'static str; 5] = ["turntable", "sun", "views", "construction", "stills"];
+    pub const ALL: [AnimationKind; 5] = [AnimationKind::Turntable, AnimationKind::SunSweep, AnimationKind::Views, AnimationKind::Construction, AnimationKind::Stills];
+
+    pub fn name(self) -> &'static str {
+        AnimationKind::NAMES[self as usize]
+    }
+
+    pub fn parse(name: &str) -> Option<AnimationKind> {
+        AnimationKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    // How many saved views it needs.
+    fn views_needed(self) -> usize {
+        match self {
+            AnimationKind::Views => 2,
+            AnimationKind::Stills => 1,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Message, Debug, Clone)]
+pub struct AnimationRequest {
+    pub kind: AnimationKind,
+    pub fps: u32,
+    pub seconds: f32,
+    pub size: UVec2,
+    // `None` makes a new `<kind>-<n>` folder in the screenshot directory
+    pub directory: Option<PathBuf>,
+}
+
+impl AnimationRequest {
+    // `turntable`, `sun 20s 60fps` or `views 3840x2160 frames/`; anything
+    // left out gets 12 seconds at 30 fps, 1920x1080.
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let (kind, rest) = args.split_first().ok_or(ANIMATION_USAGE)?;
+        let kind = AnimationKind::parse(kind).ok_or_else(|| format!("unknown animation: {kind}"))?;
+        let mut request = Self { kind, fps: DEFAULT_FPS, seconds: DEFAULT_SECONDS, size: DEFAULT_SIZE, directory: None };
+
+        for arg in rest {
+            if let Some(fps) = arg.strip_suffix("fps").and_then(|fps| fps.parse::<u32>().ok()).filter(|&fps| fps > 0) {
+                request.fps = fps;
+            } else if let Some(seconds) = arg.strip_suffix('s').and_then(|s| s.parse::<f32>().ok()).filter(|&s| s > 0.0 && s.is_finite()) {
+                request.seconds = seconds;
+            } else if let Some(size) = parse_size(arg) {
+                request.size = size;
+            } else if request.directory.is_none() {
+                request.directory = Some(PathBuf::from(arg));
+            } else {
+                return Err(ANIMATION_USAGE.into());
+            }
+        }
+        request.checked()
+    }
+
+    // Itself, if it's one that can be rendered.
+    fn checked(self) -> Result<Self, String> {
+        if self.fps == 0 {
+            return Err("fps must be at least 1".into());
+        }
+        if !(self.seconds > 0.0 && self.seconds.is_finite()) {
+            return Err("seconds must be more than 0".into());
+        }
+        if self.size.max_element() > MAX_FRAME_DIM {
+            return Err(format!("frames must be at most {MAX_FRAME_DIM} pixels a side"));
+        }
+        if self.frames() > MAX_FRAMES {
+            return Err(format!("at most {MAX_FRAMES} frames, not {}", self.frames()));
+        }
+        Ok(self)
+    }
+
+    pub fn frames(&self) -> u32 {
+        ((self.seconds * self.fps as f32).round() as u32).max(1)
+    }
+}
+
+fn parse_size(arg: &str) -> Option<UVec2> {
+    let (width, height) = arg.split_once('x')?;
+    let size = UVec2::new(width.parse().ok()?, height.parse().ok()?);
+    (size.min_element() > 0).then_some(size)
+}
+
+struct Animation {
+    kind: AnimationKind,
+    size: UVec2,
+    frames: u32,
+    frame: u32,
+    directory: PathBuf,
+    // where the camera and sun were when it started
+    start: CameraPose,
+    sun: Vec3,
+    views: Vec<Bookmark>,
+    // the capture camera is up
+    begun: bool,
+}
+
+impl Animation {
+    // The camera and sun for `frame`.
+    fn pose(&self, frame: u32) -> (CameraPose, Vec3) {
+        match self.kind {
+            AnimationKind::Turntable => {
+                // the last frame stops one step short of the first, so it loops
+                let turn = 360.0 * frame as f32 / self.frames as f32;
+                (CameraPose { horizontal_angle: self.start.horizontal_angle + turn, ..self.start }, self.sun)
+            },
+            AnimationKind::SunSweep => {
+                let day = PI * self.progress(frame);
+                let position = Vec3::X * day.cos() + NOON * day.sin();
+                (self.start, -position)
+            },
+            AnimationKind::Views => {
+                let along = self.progress(frame) * (self.views.len() - 1) as f32;
+                let i = (along as usize).min(self.views.len() - 2);
+                let (from, to) = (&self.views[i], &self.views[i + 1]);
+                let e = ease(along - i as f32);
+                (from.pose.lerp(to.pose, e), from.sun.lerp(to.sun, e).normalize_or(to.sun))
+            },
+            // `pose_animation` moves the playback instead
+            AnimationKind::Construction => (self.start, self.sun),
+            AnimationKind::Stills => {
+                let view = &self.views[frame as usize];
+                (view.pose, view.sun)
+            },
+        }
+    }
+
+    // 0 on the first frame to 1 on the last.
+    fn progress(&self, frame: u32) -> f32 {
+        if self.frames > 1 { frame as f32 / (self.frames - 1) as f32 } else { 0.0 }
+    }
+}
+
+// The animation being rendered. The F3 capture sequence picks it up, takes
+// one frame at a time and steps it on.
+#[derive(Resource, Default)]
+pub struct AnimationRender {
+    active: Option<Animation>,
+    counter: u32,
+    // frames and folder of one that just finished, to report
+    done: Option<(u32, PathBuf)>,
+}
+
+impl AnimationRender {
+    // The frame size of an animation waiting for the capture camera.
+    pub fn waiting(&self) -> Option<UVec2> {
+        self.active.as_ref().filter(|animation| !animation.begun).map(|animation| animation.size)
+    }
+
+    pub fn begin(&mut self) {
+        if let Some(animation) = &mut self.active {
+            animation.begun = true;
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.active.as_ref().is_some_and(|animation| animation.begun)
+    }
+
+    // Where the current frame goes.
+    pub fn frame_output(&self, settings: &ScreenshotSettings, metadata: Option<ShotMetadata>) -> Option<ShotOutput> {
+        let animation = self.active.as_ref().filter(|animation| animation.begun)?;
+        let file = match animation.kind {
+            AnimationKind::Stills => {
+                let name = &animation.views[animation.frame as usize].name;
+                let name = name.replace(|c: char| !c.is_alphanumeric() && c != '-' && c != '_', "_");
+                format!("{:02}-{name}.png", animation.frame + 1)
+            },
+            _ => format!("{:05}.png", animation.frame),
+        };
+        let path = animation.directory.join(file);
+        Some(settings.output("frame", animation.frame, metadata).at(path))
+    }
+
+    // On to the next frame; false once the last is captured.
+    pub fn advance(&mut self) -> bool {
+        let Some(animation) = &mut self.active else { return false; };
+        animation.frame += 1;
+        if animation.frame < animation.frames {
+            return true;
+        }
+        self.done = self.active.take().map(|animation| (animation.frames, animation.directory));
+        false
+    }
+
+    // Finish after the frame in flight.
+    pub fn stop(&mut self) -> bool {
+        let Some(animation) = &mut self.active else { return false; };
+        animation.frames = animation.frames.min(animation.frame + 1);
+        true
+    }
+}
+
+impl Plugin for AnimPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_message::<AnimationRequest>()
+            .init_resource::<AnimationRender>()
+            .add_systems(Update, (start_animation, report_animation))
+            .add_console_command(
+                ConsoleCommand::new("/animate", "PNG frames of a turntable, sun, views or construction, or stills of every view (12s, 30fps, 1920x1080)")
+                    .arg(Arg::choice("animation", &AnimationKind::NAMES))
+                    .arg(Arg::float("seconds").unit("s").optional())
+                    .arg(Arg::int("fps").unit("fps").optional())
+                    .arg(Arg::size("size").optional())
+                    .arg(Arg::path("directory").optional())
+                    .local_only(),
+                animate_command,
+            )
+            .add_console_command(ConsoleCommand::new("/animate stop", "end an animation render early"), animate_stop_command);
+    }
+}
+
+fn animate_command(
+    In(args): In<CommandArgs>,
+    bookmarks: Res<Bookmarks>,
+    mut animations: MessageWriter<AnimationRequest>,
+) -> CommandResult {
+    let name = args.word("animation").unwrap_or_default();
+    let request = AnimationRequest {
+        kind: AnimationKind::parse(name).ok_or_else(|| format!("unknown animation: {name}"))?,
+        fps: args.int("fps").map_or(DEFAULT_FPS, |fps| u32::try_from(fps).unwrap_or(0)),
+        seconds: args.float("seconds").unwrap_or(DEFAULT_SECONDS),
+        size: args.size("size").unwrap_or(DEFAULT_SIZE),
+        directory: args.path("directory").map(Path::to_path_buf),
+    }.checked()?;
+    let needed = request.kind.views_needed();
+    if bookmarks.views.len() < needed {
+        return Err(format!("save at least {needed} view{} first (/view save <name>)", if needed == 1 { "" } else { "s" }));
+    }
+    let frames = if request.kind == AnimationKind::Stills { bookmarks.views.len() as u32 } else { request.frames() };
+    let message = format!("rendering {frames} frames of {} (/animate stop ends it)", request.kind.name());
+    animations.write(request);
+    Ok(message)
+}
+
+fn animate_stop_command(
+    In(_): In<CommandArgs>,
+    mut render: ResMut<AnimationRender>,
+) -> CommandResult {
+    if render.stop() {
+        Ok("stopping after this frame".into())
+    } else {
+        Err("no animation rendering".into())
+    }
+}
+
+fn start_animation(
+    mut commands: Commands,
+    mut requests: MessageReader<AnimationRequest>,
+    mut render: ResMut<AnimationRender>,
+    cam_query: Query<(Entity, &IsoCamera, &Projection)>,
+    sun_query: Query<&Transform, With<Sun>>,
+    bvh_query: Query<&SaveBVH>,
+    bookmarks: Res<Bookmarks>,
+    settings: Res<ScreenshotSettings>,
+    mut construction: ResMut<Construction>,
+    job: Option<Res<RenderJob>>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    let Some(request) = requests.read().last().cloned() else { return; };
+    if render.active.is_some() {
+        warn!("Already rendering an animation");
+        return;
+    }
+    let Ok((entity, cam, projection)) = cam_query.single() else { return; };
+    if bookmarks.views.len() < request.kind.views_needed() {
+        error!("A {} animation needs at least {} saved views, not {}", request.kind.name(), request.kind.views_needed(), bookmarks.views.len());
+        if job.is_some() {
+            exit.write(AppExit::error());
+        }
+        return;
+    }
+
+    if request.kind == AnimationKind::Construction && construction.order.is_none() {
+        construction.order = Some(BuildOrder::Save);
+    }
+
+    let mut start = CameraPose::current(cam, projection);
+    if request.kind == AnimationKind::Turntable {
+        // swing round the middle of the build, like `/orbit`
+        if let Some(save_bvh) = bvh_query.iter().next() {
+            start.target = save_bvh.com;
+        }
+    }
+    let directory = request.directory.clone().unwrap_or_else(|| {
+        settings.directory.join(format!("{}-{}", request.kind.name(), render.counter))
+    });
+    render.counter += 1;
+    let frames = if request.kind == AnimationKind::Stills { bookmarks.views.len() as u32 } else { request.frames() };
+    info!("Rendering {} {} frames at {}x{} to {}", frames, request.kind.name(), request.size.x, request.size.y, directory.display());
+
+    // nothing else moves the camera while frames are posed
+    commands.entity(entity).remove::<CameraTween>();
+    render.active = Some(Animation {
+        kind: request.kind,
+        size: request.size,
+        frames,
+        frame: 0,
+        directory,
+        start,
+        sun: sun_query.iter().next().map_or(Vec3::NEG_Y, |transform| *transform.forward()),
+        views: bookmarks.views.clone(),
+        begun: false,
+    });
+}
+
+// Pose the live camera and sun for the current frame, every frame, so
+// nothing else gets a say while one is rendering. Runs in the camera chain
+// after anything that moves the camera; the capture camera copies it.
+pub fn pose_animation(
+    render: Res<AnimationRender>,
+    mut cam_query: Query<(&mut IsoCamera, &mut Projection)>,
+    mut sun_query: Query<&mut Transform, With<Sun>>,
+    mut construction: ResMut<Construction>,
+    mut lighting: ResMut<Lighting>,
+) {
+    let Some(animation) = &render.active else { return; };
+    if animation.kind == AnimationKind::Construction {
+        construction.playing = false;
+        construction.seek(animation.progress(animation.frame));
+    }
+    if animation.kind == AnimationKind::Stills {
+        let saved = animation.views.get(animation.frame as usize).and_then(|view| view.lighting);
+        if let Some(saved) = saved.filter(|saved| *saved != *lighting) {
+            *lighting = saved;
+        }
+    }
+    let (pose, sun) = animation.pose(animation.frame);
+    for (mut cam, mut projection) in &mut cam_query {
+        pose.apply(&mut cam, &mut projection);
+    }
+    for mut transform in &mut sun_query {
+        transform.look_to(sun, Vec3::Y);
+    }
+}
+
+fn report_animation(
+    mut render: ResMut<AnimationRender>,
+    job: Option<Res<RenderJob>>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    let Some((frames, directory)) = render.done.take() else { return; };
+    info!("Rendered {frames} frames to {}", directory.display());
+    if job.is_some() {
+        exit.write(AppExit::Success);
+    }
+}
@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 
+use crate::clip::{BrickMaterial, ClipExtension};
+
 #[derive(Resource, Default)]
 pub struct SceneAssets {
     pub materials: Materials,
@@ -8,14 +10,36 @@ pub struct SceneAssets {
 
 #[derive(Default)]
 pub struct Materials {
-    pub plastic: Handle<StandardMaterial>,
-    pub glow: Handle<StandardMaterial>,
-    pub glass: Handle<StandardMaterial>,
-    pub metal: Handle<StandardMaterial>,
+    pub plastic: Handle<BrickMaterial>,
+    pub glow: Handle<BrickMaterial>,
+    pub glass: Handle<BrickMaterial>,
+    pub metal: Handle<BrickMaterial>,
     pub water: Handle<StandardMaterial>,
     pub ground: Handle<StandardMaterial>,
 }
 
+impl Materials {
+    // Chunk meshes are grouped into four material slots; see `bvh::material_slots`.
+    pub fn for_slot(&self, slot: usize) -> Handle<BrickMaterial> {
+        match slot {
+            1 => self.glow.clone(),
+            2 => self.glass.clone(),
+            3 => self.metal.clone(),
+            _ => self.plastic.clone(),
+        }
+    }
+
+    pub fn bricks(&self) -> [&Handle<BrickMaterial>; 4] {
+        [&self.plastic, &self.glow, &self.glass, &self.metal]
+    }
+
+    // The brick materials that draw their back faces while a clip is on and
+    // are masked while anything discards; see `clip::sync_clip_materials`.
+    pub fn opaque_bricks(&self) -> [&Handle<BrickMaterial>; 3] {
+        [&self.plastic, &self.glow, &self.metal]
+    }
+}
+
 #[derive(Default)]
 pub struct Sounds {
     pub startup: Handle<AudioSource>,
@@ -35,32 +59,33 @@ impl Plugin for AssetLoaderPlugin {
 fn load_assets(
     mut scene_assets: ResMut<SceneAssets>, 
     asset_server: Res<AssetServer>, 
-    mut materials: ResMut<Assets<StandardMaterial>>) 
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut brick_materials: ResMut<Assets<BrickMaterial>>)
 {
     *scene_assets = SceneAssets {
         materials: Materials {
-            plastic: materials.add(StandardMaterial {
+            plastic: brick_materials.add(brick_material(StandardMaterial {
                 base_color: Color::srgb(0.7, 0.7, 0.7),
                 perceptual_roughness: 0.8,
                 ..default()
-            }),
-            glow: materials.add(StandardMaterial {
+            })),
+            glow: brick_materials.add(brick_material(StandardMaterial {
                 base_color: Color::srgb(1.0, 1.0, 1.0),
                 perceptual_roughness: 0.8,
                 ..default()
-            }),
-            glass: materials.add(StandardMaterial {
+            })),
+            glass: brick_materials.add(brick_material(StandardMaterial {
                 base_color: Color::srgba(0.7, 0.7, 0.7, 0.9),
                 perceptual_roughness: 0.8,
                 alpha_mode: AlphaMode::Premultiplied,
                 ..default()
-            }),
-            metal: materials.add(StandardMaterial {
+            })),
+            metal: brick_materials.add(brick_material(StandardMaterial {
                 base_color: Color::srgb(0.7, 0.7, 0.7),
                 perceptual_roughness: 0.3,
                 metallic: 1.0,
                 ..default()
-            }),
+            })),
             water: materials.add(StandardMaterial {
                 base_color: Color::srgba(0.0, 0.2, 0.4, 0.6),
                 alpha_mode: AlphaMode::Premultiplied,
@@ -83,3 +108,7 @@ fn load_assets(
         }
     }
 }
+
+fn brick_material(base: StandardMaterial) -> BrickMaterial {
+    BrickMaterial { base, extension: ClipExtension::default() }
+}
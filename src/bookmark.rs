@@ -0,0 +1,286 @@
+use std::{error::Error, fs, io, path::{Path, PathBuf}};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cam::{CameraPose, CameraTween, IsoCamera},
+    console::{AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand},
+    controls::{Action, Actions},
+    lit::Sun,
+    sky::Lighting,
+    state::GameState,
+    SaveBVH,
+};
+
+// Named camera views, kept per build in a `<save>.views.json` sidecar next
+// to the save. Each one stores the camera pose and the sun direction, in the
+// renderer's Y-up axes, and the lighting. The recall keys (1-9 unless
+// rebound) jump to the first nine; `/view` manages them.
+// The sidecar only depends on the save path, so `brs_iso animate <save>
+// stills` can render every view of a build from the command line.
+
+const SIDECAR_SUFFIX: &str = ".views.json";
+// `/view save` and `/view delete` would shadow views named these.
+const RESERVED_NAMES: [&str; 2] = ["save", "delete"];
+
+pub struct BookmarkPlugin;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredBookmark", into = "StoredBookmark")]
+pub struct Bookmark {
+    pub name: String,
+    pub pose: CameraPose,
+    // the way the sun shines, as its transform's forward
+    pub sun: Vec3,
+    // missing from views saved before there were lighting presets
+    pub lighting: Option<Lighting>,
+}
+
+// A view as the sidecar spells it: the pose flattened, vectors as arrays.
+#[derive(Serialize, Deserialize)]
+struct StoredBookmark {
+    name: String,
+    target: [f32; 3],
+    horizontal_angle: f32,
+    vertical_angle: f32,
+    scale: f32,
+    sun: [f32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lighting: Option<Lighting>,
+}
+
+impl From<Bookmark> for StoredBookmark {
+    fn from(bookmark: Bookmark) -> Self {
+        let pose = bookmark.pose;
+        Self {
+            name: bookmark.name,
+            target: pose.target.to_array(),
+            horizontal_angle: pose.horizontal_angle,
+            vertical_angle: pose.vertical_angle,
+            scale: pose.scale,
+            sun: bookmark.sun.to_array(),
+            lighting: bookmark.lighting,
+        }
+    }
+}
+
+impl From<StoredBookmark> for Bookmark {
+    fn from(stored: StoredBookmark) -> Self {
+        Self {
+            name: stored.name,
+            pose: CameraPose {
+                target: Vec3::from_array(stored.target),
+                horizontal_angle: stored.horizontal_angle,
+                vertical_angle: stored.vertical_angle,
+                scale: stored.scale,
+            },
+            sun: Vec3::from_array(stored.sun),
+            lighting: stored.lighting,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sidecar {
+    views: Vec<Bookmark>,
+}
+
+// The loaded build's bookmarks, in the order they were saved.
+#[derive(Resource, Default)]
+pub struct Bookmarks {
+    save: Option<PathBuf>,
+    pub views: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.views.iter().position(|view| view.name == name)
+    }
+
+    // Adds or replaces `bookmark` and writes the sidecar.
+    pub fn put(&mut self, bookmark: Bookmark) -> Result<(), String> {
+        match self.find(&bookmark.name) {
+            Some(i) => self.views[i] = bookmark,
+            None => self.views.push(bookmark),
+        }
+        self.store()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        let i = self.find(name).ok_or_else(|| format!("no view named {name}"))?;
+        self.views.remove(i);
+        self.store()
+    }
+
+    fn store(&self) -> Result<(), String> {
+        let save = self.save.as_deref().ok_or("no build loaded")?;
+        store(save, &self.views).map_err(|e| format!("couldn't write {}: {e}", sidecar_path(save).display()))
+    }
+}
+
+// Jump to a bookmark by index; sent by the recall keys and `/view <name>`.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct RecallView(pub usize);
+
+impl Plugin for BookmarkPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Bookmarks>()
+            .add_message::<RecallView>()
+            .add_systems(Update, (load_bookmarks, recall_keys, recall_views).chain())
+            .add_console_command(ConsoleCommand::new("/view", "list camera bookmarks, or recall one (keys 1-9)").arg(Arg::word("name", &[]).optional()), view_command)
+            .add_console_command(ConsoleCommand::new("/view save", "bookmark the camera, sun and lighting").arg(Arg::word("name", &[])), view_save_command)
+            .add_console_command(ConsoleCommand::new("/view delete", "forget a bookmark").arg(Arg::word("name", &[])), view_delete_command);
+    }
+}
+
+fn view_command(
+    In(args): In<CommandArgs>,
+    bookmarks: Res<Bookmarks>,
+    mut recall: MessageWriter<RecallView>,
+) -> CommandResult {
+    let Some(name) = args.word("name") else {
+        if bookmarks.views.is_empty() {
+            return Ok("no views saved (/view save <name>)".into());
+        }
+        let names: Vec<String> = bookmarks.views.iter().enumerate()
+            .map(|(i, bookmark)| if i < 9 { format!("{} {}", i + 1, bookmark.name) } else { bookmark.name.clone() })
+            .collect();
+        return Ok(names.join(", "));
+    };
+    let i = bookmarks.find(name).ok_or_else(|| format!("no view named {name}"))?;
+    recall.write(RecallView(i));
+    Ok(format!("view {name}"))
+}
+
+fn view_save_command(
+    In(args): In<CommandArgs>,
+    mut bookmarks: ResMut<Bookmarks>,
+    cam_query: Query<(&IsoCamera, &Projection)>,
+    sun_query: Query<&Transform, With<Sun>>,
+    lighting: Res<Lighting>,
+) -> CommandResult {
+    let name = args.word("name").unwrap_or_default();
+    if RESERVED_NAMES.contains(&name) {
+        return Err(format!("a view can't be called {name}; /view {name} is a command"));
+    }
+    let (cam, projection) = cam_query.single().map_err(|_| "no camera")?;
+    let sun = sun_query.single().map_or(Vec3::NEG_Y, |transform| *transform.forward());
+    bookmarks.put(Bookmark { name: name.to_string(), pose: CameraPose::current(cam, projection), sun, lighting: Some(*lighting) })?;
+    Ok(format!("saved view {name}"))
+}
+
+fn view_delete_command(
+    In(args): In<CommandArgs>,
+    mut bookmarks: ResMut<Bookmarks>,
+) -> CommandResult {
+    let name = args.word("name").unwrap_or_default();
+    bookmarks.remove(name)?;
+    Ok(format!("deleted view {name}"))
+}
+
+pub fn sidecar_path(save: &Path) -> PathBuf {
+    let mut path = save.as_os_str().to_owned();
+    path.push(SIDECAR_SUFFIX);
+    PathBuf::from(path)
+}
+
+// A save without a sidecar just has no bookmarks yet.
+pub fn load(save: &Path) -> Result<Vec<Bookmark>, Box<dyn Error>> {
+    let text = match fs::read_to_string(sidecar_path(save)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let sidecar: Sidecar = serde_json::from_str(&text)?;
+    Ok(sidecar.views)
+}
+
+pub fn store(save: &Path, views: &[Bookmark]) -> Result<(), Box<dyn Error>> {
+    let sidecar = Sidecar { views: views.to_vec() };
+    fs::write(sidecar_path(save), serde_json::to_string_pretty(&sidecar)?)?;
+    Ok(())
+}
+
+fn load_bookmarks(
+    bvh_query: Query<Ref<SaveBVH>>,
+    mut bookmarks: ResMut<Bookmarks>,
+) {
+    let Ok(save_bvh) = bvh_query.single() else {
+        if bookmarks.save.is_some() {
+            *bookmarks = Bookmarks::default();
+        }
+        return;
+    };
+    if !save_bvh.is_added() {
+        return;
+    }
+    let views = load(&save_bvh.path).unwrap_or_else(|e| {
+        warn!("Ignoring camera bookmarks for {}: {e}", save_bvh.path.display());
+        Vec::new()
+    });
+    info!("Loaded {} camera bookmarks", views.len());
+    *bookmarks = Bookmarks { save: Some(save_bvh.path.clone()), views };
+}
+
+fn recall_keys(
+    actions: Actions,
+    game_state: Res<GameState>,
+    mut recall: MessageWriter<RecallView>,
+) {
+    if !game_state.input_listening() {
+        return;
+    }
+    if let Some(i) = Action::RECALL_VIEWS.iter().position(|&action| actions.just_pressed(action)) {
+        recall.write(RecallView(i));
+    }
+}
+
+fn recall_views(
+    mut commands: Commands,
+    mut requests: MessageReader<RecallView>,
+    bookmarks: Res<Bookmarks>,
+    cam_query: Query<Entity, With<IsoCamera>>,
+    mut sun_query: Query<&mut Transform, With<Sun>>,
+    mut lighting: ResMut<Lighting>,
+) {
+    let Some(RecallView(i)) = requests.read().last().copied() else { return; };
+    let Some(bookmark) = bookmarks.views.get(i) else { return; };
+
+    if let Ok(entity) = cam_query.single() {
+        commands.entity(entity).insert(CameraTween::to(bookmark.pose));
+    }
+    for mut transform in &mut sun_query {
+        transform.look_to(bookmark.sun, Vec3::Y);
+    }
+    if let Some(saved) = bookmark.lighting {
+        *lighting = saved;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_round_trip_through_the_sidecar_format() {
+        let view = Bookmark {
+            name: "front".into(),
+            pose: CameraPose { target: Vec3::new(1.0, 2.0, 3.0), horizontal_angle: 45.0, vertical_angle: 30.0, scale: 2.5 },
+            sun: Vec3::new(0.0, -1.0, 0.0),
+            lighting: Some(Lighting::default()),
+        };
+        let text = serde_json::to_string(&Sidecar { views: vec![view.clone()] }).unwrap();
+        assert!(text.contains("\"target\":[1.0,2.0,3.0]"));
+        assert_eq!(serde_json::from_str::<Sidecar>(&text).unwrap().views, [view]);
+    }
+
+    #[test]
+    fn views_saved_before_lighting_load_without_it() {
+        let text = r#"{"name":"old","target":[0,0,0],"horizontal_angle":45,"vertical_angle":30,"scale":1,"sun":[0,-1,0]}"#;
+        let view: Bookmark = serde_json::from_str(text).unwrap();
+        assert_eq!(view.lighting, None);
+        assert!(!serde_json::to_string(&view).unwrap().contains("lighting"));
+    }
+}
@@ -7,8 +7,13 @@
 //! transformed into world space using the owning entity's location and
 //! rotation. The renderer is an integer voxel grid and can only represent the
 //! 24 axis-aligned orientations, so off-axis grid rotations are snapped to the
-//! nearest one. Components (lights, etc.) are still not translated, so
-//! brick-driven lights will not appear.
+//! nearest one. Point and spot light components are read; other components
+//! and brick owners are not.
+//!
+//! The reverse direction is also supported for `.brz`: `write_brz_prefab`
+//! builds a `brdb` World from a `SaveData` so the CLI can convert legacy
+//! `.brs` saves. Bricks, their owners and their point and spot lights are
+//! carried over; `brz_losses` lists the other components, which are not.
 
 use std::{collections::HashMap, path::Path};
 
@@ -23,9 +28,13 @@ use brdb::{
     fs::BrFs,
     schema::{BrdbSchemaGlobalData, BrdbStruct},
     AsBrdbValue, BrFsReader, BrReader, Brdb, BrickType, Brz, Direction as BrdbDirection, Entity,
-    IntoReader, Quat4f, Rotation as BrdbRotation, CHUNK_HALF,
+    IntoReader, Quat4f, Rotation as BrdbRotation, World, CHUNK_HALF,
+    assets::components::{PointLight, SpotLight},
+    BrdbComponent, Guid, Owner,
 };
 
+use crate::utils::brick_color;
+
 type DynError = Box<dyn std::error::Error>;
 
 /// Load a `.brdb` World file.
@@ -38,6 +47,42 @@ pub fn load_brz_prefab(path: &Path) -> Result<SaveData, DynError> {
     build_save(&Brz::open(path)?.into_reader())
 }
 
+/// Component types `write_brz_prefab` carries over.
+const LIGHT_COMPONENTS: [&str; 2] = ["BCD_PointLight", "BCD_SpotLight"];
+
+/// Write a `.brz` Prefab containing every brick of `save`, with its owner and
+/// lights.
+pub fn write_brz_prefab(save: &SaveData, path: &Path) -> Result<(), DynError> {
+    let mut world = World::new();
+    world.owners = save.header2.brick_owners.iter()
+        .map(|owner| Owner {
+            id: Guid::from(owner.id.as_u128()),
+            name: owner.name.clone(),
+            display_name: owner.name.clone(),
+            ..Default::default()
+        })
+        .collect();
+    for (i, brick) in save.bricks.iter().enumerate() {
+        world.bricks.push(unconvert_brick(save, brick).map_err(|e| format!("brick {i}: {e}"))?);
+    }
+    world.write_brz(path)?;
+    Ok(())
+}
+
+/// What `write_brz_prefab` would leave out of `save`: one line per component
+/// type it can't write, with how many bricks carry one.
+pub fn brz_losses(save: &SaveData) -> Vec<String> {
+    let mut dropped: Vec<(&str, usize)> = save.components.iter()
+        .filter(|(name, _)| !LIGHT_COMPONENTS.contains(&name.as_str()))
+        .map(|(name, component)| (name.as_str(), component.brick_indices.len()))
+        .filter(|&(_, bricks)| bricks > 0)
+        .collect();
+    dropped.sort_unstable();
+    dropped.into_iter()
+        .map(|(name, bricks)| format!("dropping {name} on {bricks} bricks; .brz output only carries lights"))
+        .collect()
+}
+
 /// Interns a name into `names`, returning its index.
 fn intern(name: String, names: &mut Vec<String>, lookup: &mut HashMap<String, u32>) -> u32 {
     if let Some(&i) = lookup.get(&name) {
@@ -420,3 +465,111 @@ fn convert_rotation(r: &BrdbRotation) -> Rotation {
         BrdbRotation::Deg270 => Rotation::Deg270,
     }
 }
+
+/// Inverse of `convert_brick`: resolve the save's asset, material and palette
+/// indices back into the names and colors a `brdb` brick stores inline, and
+/// its owner and lights into the World's.
+fn unconvert_brick(save: &SaveData, b: &Brick) -> Result<brdb::Brick, String> {
+    let asset_name = save.header2.brick_assets
+        .get(b.asset_name_index as usize)
+        .cloned()
+        .ok_or_else(|| format!("asset index {} is past the save's {} assets", b.asset_name_index, save.header2.brick_assets.len()))?;
+    let asset = match b.size {
+        Size::Empty => BrickType::Basic(asset_name.into()),
+        Size::Procedural(x, y, z) => BrickType::Procedural {
+            asset: asset_name.into(),
+            size: (x as u16, y as u16, z as u16).into(),
+        },
+    };
+    let material = save.header2.materials
+        .get(b.material_index as usize)
+        .cloned()
+        .unwrap_or_else(|| "BMC_Plastic".to_string());
+    let color = brick_color(save, b);
+    // owner 0 is public; the rest are 1-based, like `brick_owners`
+    let owner_index = (b.owner_index as usize)
+        .checked_sub(1)
+        .filter(|&i| i < save.header2.brick_owners.len());
+
+    Ok(brdb::Brick {
+        asset,
+        position: b.position.into(),
+        direction: unconvert_direction(b.direction),
+        rotation: unconvert_rotation(b.rotation),
+        collision: brdb::Collision {
+            player: b.collision.player,
+            weapon: b.collision.weapon,
+            interact: b.collision.interaction,
+            tool: b.collision.tool,
+        },
+        visible: b.visibility,
+        material: material.into(),
+        material_intensity: b.material_intensity as u8,
+        color: (color.r, color.g, color.b).into(),
+        owner_index,
+        components: unconvert_lights(b),
+        ..Default::default()
+    })
+}
+
+/// The brick's point and spot lights, from the same properties
+/// `point_light_props` and `spot_light_props` read them into.
+fn unconvert_lights(b: &Brick) -> Vec<Box<dyn BrdbComponent>> {
+    let mut lights: Vec<Box<dyn BrdbComponent>> = Vec::new();
+    let float = |props: &HashMap<String, UnrealType>, k: &str| match props.get(k) {
+        Some(UnrealType::Float(v)) => *v,
+        _ => 0.0,
+    };
+    let boolean = |props: &HashMap<String, UnrealType>, k: &str| matches!(props.get(k), Some(UnrealType::Boolean(true)));
+    let color = |props: &HashMap<String, UnrealType>| match props.get("Color") {
+        Some(UnrealType::Color(c)) => (c.r, c.g, c.b).into(),
+        _ => (255, 255, 255).into(),
+    };
+
+    if let Some(props) = b.components.get("BCD_PointLight") {
+        lights.push(Box::new(PointLight {
+            use_brick_color: boolean(props, "bUseBrickColor"),
+            color: color(props),
+            radius: float(props, "Radius"),
+            cast_shadows: boolean(props, "bCastShadows"),
+            brightness: float(props, "Brightness"),
+        }));
+    }
+    if let Some(props) = b.components.get("BCD_SpotLight") {
+        let (roll, pitch, yaw) = match props.get("Rotation") {
+            Some(UnrealType::Rotator(roll, pitch, yaw)) => (*roll, *pitch, *yaw),
+            _ => (0.0, 0.0, 0.0),
+        };
+        lights.push(Box::new(SpotLight {
+            use_brick_color: boolean(props, "bUseBrickColor"),
+            color: color(props),
+            radius: float(props, "Radius"),
+            cast_shadows: boolean(props, "bCastShadows"),
+            brightness: float(props, "Brightness"),
+            inner_cone_angle: float(props, "InnerConeAngle"),
+            outer_cone_angle: float(props, "OuterConeAngle"),
+            rotation: (roll, pitch, yaw).into(),
+        }));
+    }
+    lights
+}
+
+fn unconvert_direction(d: Direction) -> BrdbDirection {
+    match d {
+        Direction::XPositive => BrdbDirection::XPositive,
+        Direction::XNegative => BrdbDirection::XNegative,
+        Direction::YPositive => BrdbDirection::YPositive,
+        Direction::YNegative => BrdbDirection::YNegative,
+        Direction::ZPositive => BrdbDirection::ZPositive,
+        Direction::ZNegative => BrdbDirection::ZNegative,
+    }
+}
+
+fn unconvert_rotation(r: Rotation) -> BrdbRotation {
+    match r {
+        Rotation::Deg0 => BrdbRotation::Deg0,
+        Rotation::Deg90 => BrdbRotation::Deg90,
+        Rotation::Deg180 => BrdbRotation::Deg180,
+        Rotation::Deg270 => BrdbRotation::Deg270,
+    }
+}
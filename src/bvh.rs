@@ -1,11 +1,11 @@
-use std::{ops::{Index, Neg}, time::SystemTime};
+use std::{cmp::Reverse, collections::BinaryHeap, ops::{Index, Neg}, time::SystemTime};
 
-use bevy::{asset::RenderAssetUsages, math::I64Vec3, mesh::{Indices, MeshVertexAttribute, VertexAttributeValues}, platform::collections::HashMap, prelude::*, render::render_resource::{PrimitiveTopology, VertexFormat}};
+use bevy::{asset::RenderAssetUsages, math::I64Vec3, mesh::{Indices, MeshVertexAttribute, VertexAttributeValues}, platform::collections::{HashMap, HashSet}, prelude::*, render::render_resource::{PrimitiveTopology, VertexFormat}};
 use rayon::prelude::*;
 use brickadia::{save::{SaveData, Size, Brick, BrickColor}, util::{BRICK_SIZE_MAP, rotation::d2o}};
 use lazy_static::lazy_static;
 
-use crate::{faces::*, aabb::AABB, utils::cu8};
+use crate::{faces::*, aabb::{AABB, Frustum}, utils::{brick_color, cu8}};
 
 // Packed vertex attributes: 20 B/vertex instead of 40 B. These reuse the ids
 // of `Mesh::ATTRIBUTE_NORMAL`/`ATTRIBUTE_COLOR`, so the standard PBR pipeline
@@ -64,62 +64,391 @@ lazy_static! {
     ];
 }
 
-const CHUNK_SIZE: i32 = 2048;
+pub const CHUNK_SIZE: i32 = 2048;
+// `set_filter` rebuilds from scratch once more than 1/N of the bricks change.
+const FILTER_REBUILD_FRACTION: usize = 8;
 
 // Arena Tree Bounding Volume Hierarchy
 pub struct BVH {
     pub arena: Vec<BVHNode>,
+    // parent of each node, `NO_NODE` for the root
+    parents: Vec<usize>,
+    // leaf holding each brick, `NO_NODE` while the brick is out of the tree
+    leaf_of: Vec<usize>,
+    // Leaves `insert` found cheaper unsplit, with the size they may grow to
+    // before it tries again. Others split past `MAX_LEAF_BRICKS * 2`.
+    leaf_limits: HashMap<usize, usize>,
 }
 
+const NO_NODE: usize = usize::MAX;
+
+// How internal nodes choose where to split their bricks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BVHSplit {
+    // Median along the longest axis, one brick per leaf. Kept as the baseline
+    // for `brs_iso bench`.
+    Median,
+    // Binned surface-area heuristic with up to `MAX_LEAF_BRICKS` per leaf, or
+    // up to `MAX_SAH_LEAF_BRICKS` where splitting wouldn't pay.
+    Sah,
+}
+
+// SAH leaves stop splitting at this many bricks; testing a handful of boxes
+// in a leaf is cheaper than descending two more nodes.
+const MAX_LEAF_BRICKS: usize = 4;
+// Bigger nodes stay leaves too while SAH prices them cheaper than a split
+// (heavily overlapping boxes), up to this many bricks.
+const MAX_SAH_LEAF_BRICKS: usize = MAX_LEAF_BRICKS * 4;
+const SAH_BINS: usize = 16;
+// Cost of visiting a node relative to testing one brick's box.
+const SAH_TRAVERSAL_COST: f64 = 1.0;
+
 impl BVH {
-    fn new(mut indices: Vec<usize>, aabbs: &[AABB]) -> Self {
+    pub fn new(indices: Vec<usize>, aabbs: &[AABB]) -> Self {
+        Self::build(indices, aabbs, BVHSplit::Sah)
+    }
+
+    pub fn build(mut indices: Vec<usize>, aabbs: &[AABB], split: BVHSplit) -> Self {
         let mut bvh = Self {
-            arena: Vec::with_capacity(indices.len() * 2)
+            arena: Vec::with_capacity(indices.len() * 2),
+            parents: Vec::with_capacity(indices.len() * 2),
+            leaf_of: vec![NO_NODE; aabbs.len()],
+            leaf_limits: HashMap::default(),
         };
         if !indices.is_empty() {
-            bvh.top_down_bv_tree(&mut indices, aabbs);
+            bvh.top_down_bv_tree(&mut indices, aabbs, split, NO_NODE);
         }
         bvh
     }
 
-    fn top_down_bv_tree(&mut self, brick_indices: &mut [usize], aabbs: &[AABB]) -> usize {
+    fn top_down_bv_tree(&mut self, brick_indices: &mut [usize], aabbs: &[AABB], split: BVHSplit, parent: usize) -> usize {
         let i = self.arena.len();
-        if let [brick_index] = *brick_indices {
-            self.arena.push(BVHNode::Leaf { i: brick_index });
-        } else {
-            let (k, aabb) = partition_bricks(brick_indices, aabbs);
-            self.arena.push(BVHNode::Internal { aabb, left: 0, right: 0 });
+        let aabb = bounds_of(brick_indices, aabbs);
+        self.parents.push(parent);
 
-            let (left_bricks, right_bricks) = brick_indices.split_at_mut(k);
-            let left_idx = self.top_down_bv_tree(left_bricks, aabbs);
-            let right_idx = self.top_down_bv_tree(right_bricks, aabbs);
+        let k = match split {
+            BVHSplit::Median if brick_indices.len() > 1 => Some(median_split(brick_indices, aabbs, &aabb)),
+            BVHSplit::Sah if brick_indices.len() > MAX_LEAF_BRICKS => sah_split(brick_indices, aabbs, &aabb),
+            _ => None,
+        };
 
-            if let BVHNode::Internal { left, right, .. } = &mut self.arena[i] {
-                *left = left_idx;
-                *right = right_idx;
+        match k {
+            None => {
+                for &brick in brick_indices.iter() {
+                    self.leaf_of[brick] = i;
+                }
+                self.arena.push(BVHNode::Leaf { aabb, bricks: brick_indices.to_vec() });
+            },
+            Some(k) => {
+                self.arena.push(BVHNode::Internal { aabb, left: 0, right: 0 });
+
+                let (left_bricks, right_bricks) = brick_indices.split_at_mut(k);
+                let left_idx = self.top_down_bv_tree(left_bricks, aabbs, split, i);
+                let right_idx = self.top_down_bv_tree(right_bricks, aabbs, split, i);
+
+                if let BVHNode::Internal { left, right, .. } = &mut self.arena[i] {
+                    *left = left_idx;
+                    *right = right_idx;
+                }
             }
         }
         i
     }
 
-    pub fn intersection(&self, ray: Ray3d, aabbs: &Vec<AABB>) -> Option<usize> {
+    pub fn root_aabb(&self) -> Option<&AABB> {
+        self.arena.first().map(BVHNode::aabb)
+    }
+
+    pub fn contains(&self, brick: usize) -> bool {
+        self.leaf_of.get(brick).is_some_and(|&leaf| leaf != NO_NODE)
+    }
+
+    // Add a brick to the tree without rebuilding it: descend toward whichever
+    // child's box grows least, append to that leaf, and refit on the way back
+    // up. Leaves that overflow are split in place when SAH says it pays.
+    pub fn insert(&mut self, brick: usize, aabbs: &[AABB]) {
+        if self.leaf_of.len() < aabbs.len() {
+            self.leaf_of.resize(aabbs.len(), NO_NODE);
+        }
+        if self.contains(brick) {
+            return;
+        }
+        if self.arena.is_empty() {
+            self.top_down_bv_tree(&mut [brick], aabbs, BVHSplit::Sah, NO_NODE);
+            return;
+        }
+
+        let aabb = &aabbs[brick];
+        let growth = |node: &BVHNode| {
+            let bounds = node.aabb();
+            surface_area(bounds.union(aabb).halfwidths * 2) - surface_area(bounds.halfwidths * 2)
+        };
+        let mut node = 0;
+        while let BVHNode::Internal { left, right, .. } = &self.arena[node] {
+            let (left, right) = (*left, *right);
+            node = if growth(&self.arena[left]) <= growth(&self.arena[right]) { left } else { right };
+        }
+
+        let limit = self.leaf_limits.get(&node).copied().unwrap_or(MAX_LEAF_BRICKS * 2);
+        let BVHNode::Leaf { bricks, .. } = &mut self.arena[node] else { unreachable!() };
+        bricks.push(brick);
+        let overflowing = bricks.len() > limit;
+        self.leaf_of[brick] = node;
+
+        if overflowing {
+            self.split_leaf(node, aabbs);
+        }
+        self.refit(node, aabbs);
+    }
+
+    // Take a brick out of its leaf and shrink the boxes above it. Returns
+    // false when the brick wasn't in the tree.
+    pub fn remove(&mut self, brick: usize, aabbs: &[AABB]) -> bool {
+        if !self.contains(brick) {
+            return false;
+        }
+        let leaf = self.leaf_of[brick];
+        if let BVHNode::Leaf { bricks, .. } = &mut self.arena[leaf] {
+            bricks.retain(|&b| b != brick);
+        }
+        self.leaf_of[brick] = NO_NODE;
+        self.refit(leaf, aabbs);
+        true
+    }
+
+    // Turn an overfull leaf into an internal node over two SAH subtrees, in
+    // the leaf's own slot so the parent's child index stays valid. When SAH
+    // would rather keep the leaf, it's left alone until it has doubled.
+    fn split_leaf(&mut self, node: usize, aabbs: &[AABB]) {
+        let BVHNode::Leaf { bricks, .. } = &mut self.arena[node] else { return; };
+        let aabb = bounds_of(bricks, aabbs);
+        let Some(k) = sah_split(bricks, aabbs, &aabb) else {
+            self.leaf_limits.insert(node, bricks.len() * 2);
+            return;
+        };
+        let mut bricks = std::mem::take(bricks);
+        self.leaf_limits.remove(&node);
+
+        let (left_bricks, right_bricks) = bricks.split_at_mut(k);
+        let left = self.top_down_bv_tree(left_bricks, aabbs, BVHSplit::Sah, node);
+        let right = self.top_down_bv_tree(right_bricks, aabbs, BVHSplit::Sah, node);
+        self.arena[node] = BVHNode::Internal { aabb, left, right };
+    }
+
+    // Recompute boxes from `node` up to the root. An emptied leaf keeps its
+    // old box; it only makes queries visit one extra node.
+    fn refit(&mut self, mut node: usize, aabbs: &[AABB]) {
+        while node != NO_NODE {
+            let bounds = match &self.arena[node] {
+                BVHNode::Leaf { bricks, .. } if bricks.is_empty() => None,
+                BVHNode::Leaf { bricks, .. } => Some(bounds_of(bricks, aabbs)),
+                BVHNode::Internal { left, right, .. } => {
+                    Some(self.arena[*left].aabb().union(self.arena[*right].aabb()))
+                }
+            };
+            if let Some(bounds) = bounds {
+                match &mut self.arena[node] {
+                    BVHNode::Leaf { aabb, .. } | BVHNode::Internal { aabb, .. } => *aabb = bounds,
+                }
+            }
+            node = self.parents[node];
+        }
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        let mut leaves = 0;
+        self.walk(|node, _| {
+            if matches!(node, BVHNode::Leaf { .. }) {
+                leaves += 1;
+            }
+        });
+        leaves
+    }
+
+    pub fn depth(&self) -> usize {
+        let mut max_depth = 0;
+        self.walk(|_, depth| max_depth = max_depth.max(depth));
+        max_depth
+    }
+
+    // Depth of the leaf holding each brick, 0 for bricks outside the tree.
+    pub fn brick_depths(&self) -> Vec<usize> {
+        let mut depths = vec![0; self.leaf_of.len()];
+        self.walk(|node, depth| {
+            if let BVHNode::Leaf { bricks, .. } = node {
+                for &brick in bricks {
+                    depths[brick] = depth;
+                }
+            }
+        });
+        depths
+    }
+
+    // Visit every node with its depth (root is 0).
+    pub fn walk(&self, mut visit: impl FnMut(&BVHNode, usize)) {
+        if self.arena.is_empty() {
+            return;
+        }
+        let mut stack = vec![(0, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            visit(&self.arena[node], depth);
+            if let BVHNode::Internal { left, right, .. } = &self.arena[node] {
+                stack.push((*right, depth + 1));
+                stack.push((*left, depth + 1));
+            }
+        }
+    }
+
+    // Generic box-test traversal the public queries are built on: descends
+    // into nodes whose box passes `test` and reports every brick whose own box
+    // passes it too.
+    pub fn query(&self, aabbs: &[AABB], test: impl Fn(&AABB) -> bool, mut visit: impl FnMut(usize)) {
+        if self.arena.is_empty() {
+            return;
+        }
         let mut stack = vec![0];
         while let Some(node) = stack.pop() {
             match &self.arena[node] {
                 BVHNode::Internal { aabb, left, right } => {
-                    if aabb.intersects(ray) {
+                    if test(aabb) {
                         stack.push(*left);
                         stack.push(*right);
                     }
                 },
-                BVHNode::Leaf { i } => {
-                    if aabbs[*i].intersects(ray) {
-                        return Some(*i);
+                BVHNode::Leaf { aabb, bricks } => {
+                    if test(aabb) {
+                        for &i in bricks {
+                            if test(&aabbs[i]) {
+                                visit(i);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Bricks whose boxes overlap or touch `bounds`.
+    pub fn query_aabb(&self, bounds: &AABB, aabbs: &[AABB]) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.query(aabbs, |aabb| aabb.neighbors(bounds), |i| found.push(i));
+        found
+    }
+
+    pub fn query_sphere(&self, center: Vec3, radius: f32, aabbs: &[AABB]) -> Vec<usize> {
+        let radius_sq = radius * radius;
+        let mut found = Vec::new();
+        self.query(aabbs, |aabb| aabb.distance_squared(center) <= radius_sq, |i| found.push(i));
+        found
+    }
+
+    // Bricks at least partly inside the frustum.
+    pub fn query_frustum(&self, frustum: &Frustum, aabbs: &[AABB]) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.query(aabbs, |aabb| frustum.intersects_aabb(aabb), |i| found.push(i));
+        found
+    }
+
+    pub fn query_point(&self, point: Vec3, aabbs: &[AABB]) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.query(aabbs, |aabb| aabb.contains_point(point), |i| found.push(i));
+        found
+    }
+
+    // The `k` bricks whose boxes are closest to `point`, nearest first, with
+    // their distances. Best-first search: nodes come off a min-heap keyed by
+    // box distance and the walk stops once no node can beat the k-th hit.
+    pub fn nearest(&self, point: Vec3, k: usize, aabbs: &[AABB]) -> Vec<(usize, f32)> {
+        if self.arena.is_empty() || k == 0 {
+            return Vec::new();
+        }
+
+        // Non-negative floats order the same as their bit patterns, which
+        // gives the heaps a total order without a float wrapper.
+        let key = |d: f32| d.to_bits();
+        let mut nodes = BinaryHeap::new();
+        let mut best: BinaryHeap<(u32, usize)> = BinaryHeap::with_capacity(k + 1);
+        nodes.push(Reverse((key(self.arena[0].aabb().distance_squared(point)), 0)));
+
+        while let Some(Reverse((node_key, node))) = nodes.pop() {
+            if best.len() == k && best.peek().is_some_and(|&(worst, _)| node_key > worst) {
+                break;
+            }
+            match &self.arena[node] {
+                BVHNode::Internal { left, right, .. } => {
+                    for child in [*left, *right] {
+                        let d = self.arena[child].aabb().distance_squared(point);
+                        nodes.push(Reverse((key(d), child)));
+                    }
+                },
+                BVHNode::Leaf { bricks, .. } => {
+                    for &i in bricks {
+                        best.push((key(aabbs[i].distance_squared(point)), i));
+                        if best.len() > k {
+                            best.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|(d, i)| (i, f32::from_bits(d).sqrt()))
+            .collect()
+    }
+
+    // Closest brick face along the ray, tested against the actual face
+    // polygons rather than brick boxes. Children are visited nearest-first and
+    // anything that starts beyond the best hit so far is skipped. Only hits
+    // whose point passes `keep` count; faces are two-sided, so a ray through
+    // a section cut lands on the inside of the brick it exposes.
+    pub fn raycast(&self, ray: Ray3d, aabbs: &[AABB], faces: &[Vec<Face>], keep: impl Fn(Vec3) -> bool) -> Option<RayHit> {
+        let root_distance = self.arena.first()?.aabb().ray_distance(ray)?;
+        let mut best: Option<RayHit> = None;
+        let mut stack = vec![(0, root_distance)];
+
+        while let Some((node, distance)) = stack.pop() {
+            if best.is_some_and(|hit| distance > hit.distance) {
+                continue;
+            }
+            match &self.arena[node] {
+                BVHNode::Internal { left, right, .. } => {
+                    let left_distance = self.arena[*left].aabb().ray_distance(ray);
+                    let right_distance = self.arena[*right].aabb().ray_distance(ray);
+                    // push the farther child first so the nearer one pops next
+                    let mut children = [(*left, left_distance), (*right, right_distance)];
+                    if left_distance < right_distance {
+                        children.swap(0, 1);
+                    }
+                    for (child, child_distance) in children {
+                        if let Some(child_distance) = child_distance {
+                            stack.push((child, child_distance));
+                        }
+                    }
+                },
+                BVHNode::Leaf { bricks, .. } => {
+                    for &i in bricks {
+                        if aabbs[i].ray_distance(ray).is_none() {
+                            continue;
+                        }
+                        for (j, face) in faces[i].iter().enumerate() {
+                            let Some(t) = face.ray_distance(ray) else { continue; };
+                            if best.is_none_or(|hit| t < hit.distance) && keep(ray.get_point(t)) {
+                                best = Some(RayHit {
+                                    brick: i,
+                                    face: j,
+                                    point: ray.get_point(t),
+                                    normal: face.normal,
+                                    distance: t,
+                                });
+                            }
+                        }
                     }
                 }
             }
         }
-        None
+        best
     }
 }
 
@@ -131,70 +460,191 @@ impl Index<usize> for BVH {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    pub brick: usize,
+    // index into the brick's faces from `gen_faces`
+    pub face: usize,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
 pub enum BVHNode {
-    Leaf { i: usize },
+    Leaf { aabb: AABB, bricks: Vec<usize> },
     Internal { aabb: AABB, left: usize, right: usize }
 }
 
-fn partition_bricks(indices: &mut [usize], aabbs: &[AABB]) -> (usize, AABB) {
-    // calculate volume containing all sub-volumes
-    let mut min = aabbs[indices[0]].center;
-    let mut max = aabbs[indices[0]].center;
+impl BVHNode {
+    pub fn aabb(&self) -> &AABB {
+        match self {
+            BVHNode::Leaf { aabb, .. } | BVHNode::Internal { aabb, .. } => aabb,
+        }
+    }
+}
 
-    for i in indices.iter() {
-        let aabb = &aabbs[*i];
-        let aabb_min = aabb.center - aabb.halfwidths;
-        let aabb_max = aabb.center + aabb.halfwidths;
+// calculate volume containing all sub-volumes
+fn bounds_of(indices: &[usize], aabbs: &[AABB]) -> AABB {
+    let first = &aabbs[indices[0]];
+    let (min, max) = indices.iter().fold((first.min(), first.max()), |(min, max), &i| {
+        (min.min(aabbs[i].min()), max.max(aabbs[i].max()))
+    });
+    AABB::from_min_max(min, max)
+}
 
-        min = min.min(aabb_min);
-        max = max.max(aabb_max);
+fn median_split(indices: &mut [usize], aabbs: &[AABB], aabb: &AABB) -> usize {
+    // split at the median along the longest axis; a full sort is wasted work
+    // when only the median partition is needed
+    let k = indices.len() / 2;
+    let axis = longest_axis(aabb.halfwidths);
+    indices.select_nth_unstable_by_key(k, |i| aabbs[*i].center[axis]);
+    k
+}
+
+fn longest_axis(v: IVec3) -> usize {
+    if v.x >= v.y && v.x >= v.z {
+        0
+    } else if v.y >= v.z {
+        1
+    } else {
+        2
     }
+}
+
+#[derive(Clone, Copy)]
+struct SahBin {
+    count: usize,
+    min: IVec3,
+    max: IVec3,
+}
 
-    // if total size is uneven add a bit to the max of the volume
-    let size: IVec3 = max - min;
-    if size.x % 2 == 0 {
-        max.x += 1;
+impl SahBin {
+    const EMPTY: SahBin = SahBin { count: 0, min: IVec3::MAX, max: IVec3::MIN };
+
+    fn grow(&mut self, aabb: &AABB) {
+        self.count += 1;
+        self.min = self.min.min(aabb.min());
+        self.max = self.max.max(aabb.max());
     }
-    if size.y % 2 == 0 {
-        max.y += 1;
+
+    fn merge(&self, other: &SahBin) -> SahBin {
+        SahBin {
+            count: self.count + other.count,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
     }
-    if size.z % 2 == 0 {
-        max.z += 1;
+
+    fn area(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        surface_area(self.max - self.min)
     }
+}
 
-    let center = (min + max) / 2;
-    let halfwidths = (max - min) / 2;
+fn surface_area(size: IVec3) -> f64 {
+    let size = size.as_dvec3();
+    2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+}
 
-    let aabb = AABB {
-        center,
-        halfwidths
+// Binned SAH: bucket brick centers into `SAH_BINS` slabs per axis, sweep the
+// slab boundaries, and split where (area × bricks) summed over both sides is
+// lowest. Falls back to the median when no boundary separates the centers
+// (e.g. a stack of identical bricks). `None` means the node is cheaper as a
+// leaf.
+fn sah_split(indices: &mut [usize], aabbs: &[AABB], aabb: &AABB) -> Option<usize> {
+    let (centroid_min, centroid_max) = indices.iter().fold((IVec3::MAX, IVec3::MIN), |(min, max), &i| {
+        (min.min(aabbs[i].center), max.max(aabbs[i].center))
+    });
+    let extent = centroid_max - centroid_min;
+    let bin_of = |axis: usize, center: IVec3| {
+        let offset = (center[axis] - centroid_min[axis]) as i64;
+        ((offset * SAH_BINS as i64) / (extent[axis] as i64 + 1)) as usize
     };
 
-    // split at the median along the longest axis; a full sort is wasted work
-    // when only the median partition is needed
-    let k = indices.len() / 2;
-    let hw = aabb.halfwidths;
-    if hw.x >= hw.y && hw.x >= hw.z {
-        indices.select_nth_unstable_by_key(k, |i| aabbs[*i].center.x);
-    } else if hw.y >= hw.z {
-        indices.select_nth_unstable_by_key(k, |i| aabbs[*i].center.y);
-    } else {
-        indices.select_nth_unstable_by_key(k, |i| aabbs[*i].center.z);
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        if extent[axis] == 0 {
+            continue;
+        }
+
+        let mut bins = [SahBin::EMPTY; SAH_BINS];
+        for &i in indices.iter() {
+            bins[bin_of(axis, aabbs[i].center)].grow(&aabbs[i]);
+        }
+
+        // right-to-left sweep first so the left-to-right pass can price each
+        // boundary in one step
+        let mut right_costs = [0.0; SAH_BINS];
+        let mut right = SahBin::EMPTY;
+        for b in (1..SAH_BINS).rev() {
+            right = right.merge(&bins[b]);
+            right_costs[b] = right.area() * right.count as f64;
+        }
+
+        let mut left = SahBin::EMPTY;
+        for b in 0..SAH_BINS - 1 {
+            left = left.merge(&bins[b]);
+            if left.count == 0 || left.count == indices.len() {
+                continue;
+            }
+            let cost = left.area() * left.count as f64 + right_costs[b + 1];
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, b));
+            }
+        }
+    }
+
+    let Some((cost, axis, boundary)) = best else {
+        return Some(median_split(indices, aabbs, aabb));
+    };
+
+    // Splitting has to beat testing every brick here directly, unless the node
+    // is too big to be a leaf at all.
+    let node_area = surface_area(aabb.halfwidths * 2);
+    let leaf_cost = node_area * indices.len() as f64;
+    let split_cost = SAH_TRAVERSAL_COST * node_area + cost;
+    if split_cost >= leaf_cost && indices.len() <= MAX_SAH_LEAF_BRICKS {
+        return None;
     }
 
-    (k, aabb)
+    let mut k = 0;
+    for j in 0..indices.len() {
+        if bin_of(axis, aabbs[indices[j]].center) <= boundary {
+            indices.swap(k, j);
+            k += 1;
+        }
+    }
+    Some(k)
 }
 
 // (position bits, packed normal, packed color) — the full vertex identity.
 // World positions are exact f32 integers (integer local coords through
 // axis-aligned 90° rotations + integer brick offset), so coincident verts are
 // bit-identical and safe to weld on this key.
-type VertKey = ([u32; 3], [i8; 4], [u8; 4]);
+type VertKey = ([u32; 3], [i8; 4], [u8; 4], [u32; 2]);
+
+// No brick covers the face, so it stays once shown.
+const NEVER_COVERED: u32 = u32::MAX;
+// Low bits of a reveal rank that go on `ATTRIBUTE_UV_1`; see `reveal_halves`.
+const REVEAL_LOW_BITS: u32 = 12;
+
+// A face's reveal range as vertex attributes: the high bits of each rank for
+// `ATTRIBUTE_UV_0` and the low bits for `ATTRIBUTE_UV_1`. An f32 only holds
+// whole numbers exactly up to 2^24, and the shader compares ranks exactly.
+fn reveal_halves(range: [u32; 2]) -> ([f32; 2], [f32; 2]) {
+    let mask = (1 << REVEAL_LOW_BITS) - 1;
+    (range.map(|rank| (rank >> REVEAL_LOW_BITS) as f32), range.map(|rank| (rank & mask) as f32))
+}
 
 pub struct Buffers {
     position: Vec<[f32; 3]>,
     color: Vec<[u8; 4]>,
     normal: Vec<[i8; 4]>,
+    // construction playback range, see `set_reveal_order`
+    reveal_high: Vec<[f32; 2]>,
+    reveal_low: Vec<[f32; 2]>,
     indices: Vec<u32>,
     // welds duplicate verts across faces within this chunk
     vert_map: HashMap<VertKey, u32>,
@@ -206,13 +656,15 @@ impl Buffers {
             position: Vec::new(),
             color: Vec::new(),
             normal: Vec::new(),
+            reveal_high: Vec::new(),
+            reveal_low: Vec::new(),
             indices: Vec::new(),
             vert_map: HashMap::default(),
         }
     }
 
-    fn weld(&mut self, pos: [f32; 3], normal: [i8; 4], color: [u8; 4]) -> u32 {
-        let key = ([pos[0].to_bits(), pos[1].to_bits(), pos[2].to_bits()], normal, color);
+    fn weld(&mut self, pos: [f32; 3], normal: [i8; 4], color: [u8; 4], reveal: [u32; 2]) -> u32 {
+        let key = ([pos[0].to_bits(), pos[1].to_bits(), pos[2].to_bits()], normal, color, reveal);
         if let Some(&idx) = self.vert_map.get(&key) {
             return idx;
         }
@@ -220,28 +672,66 @@ impl Buffers {
         self.position.push(pos);
         self.color.push(color);
         self.normal.push(normal);
+        let (high, low) = reveal_halves(reveal);
+        self.reveal_high.push(high);
+        self.reveal_low.push(low);
         self.vert_map.insert(key, idx);
         idx
     }
 }
 
-pub struct BVHMeshGenerator<'a> {
-    save_data: &'a SaveData,
-    faces: Vec<Vec<Face>>,
+// Renderer-side key for one chunk mesh: (material slot, chunk coord).
+pub type ChunkKey = (usize, IVec3);
+
+// Owns the save along with the faces, boxes, BVH and per-chunk buckets built
+// from it, and keeps all of them in sync as bricks are edited so only the
+// chunks an edit touches need re-meshing.
+pub struct BVHMeshGenerator {
+    pub save_data: SaveData,
+    pub faces: Vec<Vec<Face>>,
     pub aabbs: Vec<AABB>,
     pub bvh: BVH,
+    hidden_masks: Vec<u16>,
+    // Removed bricks keep their slot (and their faces, for undo) so brick
+    // indices stay stable for everything holding on to them.
+    removed: Vec<bool>,
+    // Bricks hidden by the filter panel. Like removed ones they're out of the
+    // BVH and the meshes, so they don't pick or cover anything, but they stay
+    // in the save.
+    filtered: Vec<bool>,
+    split: BVHSplit,
+    material_map: Vec<usize>,
+    buckets: HashMap<ChunkKey, Vec<usize>>,
+    dirty: HashSet<ChunkKey>,
+    // per-brick display colors replacing the save's, for the color-by modes
+    color_override: Option<Vec<[u8; 4]>>,
+    // each brick's place in construction playback
+    reveal_order: Option<Vec<u32>>,
 }
 
-impl<'a> BVHMeshGenerator<'a> {
-    pub fn new(save_data: &'a SaveData) -> Self {
-        let faces = gen_faces(save_data);
-        let aabbs = gen_aabbs(save_data);
+impl BVHMeshGenerator {
+    pub fn new(save_data: SaveData) -> Self {
+        Self::with_split(save_data, BVHSplit::Sah)
+    }
+
+    pub fn with_split(save_data: SaveData, split: BVHSplit) -> Self {
+        let faces = gen_faces(&save_data);
+        let aabbs = gen_aabbs(&save_data);
         let now = SystemTime::now();
         let indices = (0..save_data.bricks.len()).collect();
-        let bvh = BVH::new(indices, &aabbs);
-        info!("Built BVH in {} seconds", now.elapsed().unwrap().as_secs_f32());
+        let bvh = BVH::build(indices, &aabbs, split);
+        info!("Built BVH ({:?}, {} nodes) in {} seconds", split, bvh.arena.len(), now.elapsed().unwrap().as_secs_f32());
 
         Self {
+            hidden_masks: vec![0; save_data.bricks.len()],
+            removed: vec![false; save_data.bricks.len()],
+            filtered: vec![false; save_data.bricks.len()],
+            split,
+            material_map: material_slots(&save_data),
+            buckets: HashMap::default(),
+            dirty: HashSet::default(),
+            color_override: None,
+            reveal_order: None,
             save_data,
             faces,
             aabbs,
@@ -249,88 +739,419 @@ impl<'a> BVHMeshGenerator<'a> {
         }
     }
 
-    pub fn gen_mesh(&self) -> Vec<Vec<Mesh>> {
+    pub fn into_save_data(self) -> SaveData {
+        self.save_data
+    }
+
+    // Cull every brick and mesh every chunk. Returns the meshes grouped by
+    // material slot.
+    pub fn gen_mesh(&mut self) -> Vec<Vec<(ChunkKey, Mesh)>> {
         let now = SystemTime::now();
-        // Hidden faces as a bitmask per brick (bricks have at most 9 faces).
-        // map_init reuses the neighbor scratch buffers per rayon worker instead
-        // of reallocating them for every brick.
-        let hidden_masks: Vec<u16> = self.save_data.bricks.par_iter().enumerate()
-            .map_init(
-                || (Vec::new(), HashMap::default()),
-                |(neighbors, neighbor_faces), (i, brick)| {
-                    if !brick.visibility || self.faces[i].is_empty() {
-                        return 0;
-                    }
-                    neighbors.clear();
-                    self.traverse_neighbors(i, neighbors);
-                    self.cull_faces(i, neighbors, neighbor_faces)
-                },
-            )
-            .collect();
+        self.hidden_masks = self.cull_hidden_faces();
         info!("Culled faces in {} seconds", now.elapsed().unwrap().as_secs_f32());
 
         let now = SystemTime::now();
 
-        let mut material_map: Vec<usize> = Vec::with_capacity(self.save_data.header2.materials.len());
-        for i in 0..self.save_data.header2.materials.len() {
-            let point = match self.save_data.header2.materials[i].as_str() {
-                "BMC_Plastic" => 0,
-                "BMC_Glow" => 1,
-                "BMC_Glass" => 2,
-                "BMC_Metallic" => 3,
-                _ => 0,
-            };
-            material_map.push(point);
-        }
-
         // Bucket bricks by (material slot, chunk coord). Cheap serial pass; the
         // expensive weld+triangulate happens per bucket in parallel below.
-        let mut buckets: HashMap<(usize, IVec3), Vec<usize>> = HashMap::default();
+        self.buckets.clear();
+        self.dirty.clear();
         for i in 0..self.save_data.bricks.len() {
-            if self.faces[i].is_empty() {
-                continue;
-            }
-            let material = material_map[self.save_data.bricks[i].material_index as usize];
-            let chunk = self.aabbs[i].center / CHUNK_SIZE;
-            buckets.entry((material, chunk)).or_default().push(i);
+            self.bucket(i);
         }
+        self.dirty.clear();
 
         // Each chunk is independent (its own weld map), so build them all across
         // cores instead of welding millions of verts on one thread.
-        let buckets: Vec<((usize, IVec3), Vec<usize>)> = buckets.into_iter().collect();
-        let built: Vec<(usize, Mesh, usize)> = buckets.par_iter()
-            .map(|((material, _chunk), brick_ids)| {
-                let (mesh, verts) = self.build_chunk_mesh(brick_ids, &hidden_masks);
-                (*material, mesh, verts)
+        let buckets: Vec<(&ChunkKey, &Vec<usize>)> = self.buckets.iter().collect();
+        let built: Vec<(ChunkKey, Mesh, usize)> = buckets.par_iter()
+            .map(|(key, brick_ids)| {
+                let (mesh, verts) = self.build_chunk_mesh(brick_ids);
+                (**key, mesh, verts)
             })
             .collect();
 
-        let mut material_meshes: Vec<Vec<Mesh>> = vec![Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        let mut material_meshes: Vec<Vec<(ChunkKey, Mesh)>> = vec![Vec::new(), Vec::new(), Vec::new(), Vec::new()];
         let total_chunks = built.len();
         let mut total_verts = 0;
-        for (material, mesh, verts) in built {
+        for (key, mesh, verts) in built {
             total_verts += verts;
-            material_meshes[material].push(mesh);
+            material_meshes[key.0].push((key, mesh));
         }
 
         info!("Generated {} mesh chunks ({} welded verts) in {} seconds", total_chunks, total_verts, now.elapsed().unwrap().as_secs_f32());
         material_meshes
     }
 
+    // Hidden faces as a bitmask per brick (bricks have at most 9 faces).
+    // map_init reuses the neighbor scratch buffers per rayon worker instead
+    // of reallocating them for every brick.
+    pub fn cull_hidden_faces(&self) -> Vec<u16> {
+        (0..self.save_data.bricks.len()).into_par_iter()
+            .map_init(
+                || (Vec::new(), HashMap::default()),
+                |(neighbors, neighbor_faces), i| self.cull_brick(i, neighbors, neighbor_faces),
+            )
+            .collect()
+    }
+
+    fn cull_brick(
+        &self,
+        i: usize,
+        neighbors: &mut Vec<usize>,
+        neighbor_faces: &mut HashMap<IVec3, Vec<(usize, usize)>>,
+    ) -> u16 {
+        if !self.is_present(i) || !self.save_data.bricks[i].visibility || self.faces[i].is_empty() {
+            return 0;
+        }
+        neighbors.clear();
+        self.traverse_neighbors(i, neighbors);
+        self.cull_faces(i, neighbors, neighbor_faces)
+    }
+
+    pub fn is_removed(&self, i: usize) -> bool {
+        self.removed[i]
+    }
+
+    // In the BVH and the meshes: neither removed nor filtered out.
+    pub fn is_present(&self, i: usize) -> bool {
+        !self.removed[i] && !self.filtered[i]
+    }
+
+    // Present and not hidden by its own visibility flag, i.e. on screen.
+    pub fn is_shown(&self, i: usize) -> bool {
+        self.is_present(i) && self.save_data.bricks[i].visibility
+    }
+
+    pub fn filter(&self) -> &[bool] {
+        &self.filtered
+    }
+
+    pub fn removed_bricks(&self) -> impl Iterator<Item = usize> + '_ {
+        self.removed.iter().enumerate().filter(|(_, &removed)| removed).map(|(i, _)| i)
+    }
+
+    // Bricks with their own visibility flag off, leaving out removed ones.
+    pub fn hidden_bricks(&self) -> impl Iterator<Item = usize> + '_ {
+        self.save_data.bricks.iter().enumerate()
+            .filter(|(i, brick)| !brick.visibility && !self.removed[*i])
+            .map(|(i, _)| i)
+    }
+
+    // Take a brick out of the build. Returns false if it was already removed.
+    pub fn remove_brick(&mut self, i: usize) -> bool {
+        if self.removed[i] {
+            return false;
+        }
+        if self.is_present(i) {
+            self.detach(i);
+        }
+        self.removed[i] = true;
+        true
+    }
+
+    pub fn restore_brick(&mut self, i: usize) -> bool {
+        if !self.removed[i] {
+            return false;
+        }
+        self.removed[i] = false;
+        if self.is_present(i) {
+            self.attach(i);
+        }
+        true
+    }
+
+    // Hide (`true`) or show bricks for the filter panel. Small changes go
+    // brick by brick like edits; past `FILTER_REBUILD_FRACTION` of the build
+    // it's cheaper to rebuild the BVH and re-cull everything in parallel.
+    pub fn set_filter(&mut self, filtered: Vec<bool>) {
+        let changed: Vec<usize> = (0..self.filtered.len())
+            .filter(|&i| filtered.get(i).copied().unwrap_or(false) != self.filtered[i])
+            .collect();
+        if changed.is_empty() {
+            return;
+        }
+
+        if changed.len() * FILTER_REBUILD_FRACTION < self.filtered.len() {
+            for i in changed {
+                if self.removed[i] {
+                    self.filtered[i] = !self.filtered[i];
+                } else if self.filtered[i] {
+                    self.filtered[i] = false;
+                    self.attach(i);
+                } else {
+                    self.detach(i);
+                    self.filtered[i] = true;
+                }
+            }
+            return;
+        }
+
+        for i in changed {
+            self.filtered[i] = !self.filtered[i];
+        }
+        let present: Vec<usize> = (0..self.filtered.len()).filter(|&i| self.is_present(i)).collect();
+        self.bvh = BVH::build(present, &self.aabbs, self.split);
+        self.hidden_masks = self.cull_hidden_faces();
+
+        // chunks that end up empty must be marked too, so their meshes go
+        self.dirty.extend(self.buckets.keys().copied());
+        self.buckets.clear();
+        for i in 0..self.save_data.bricks.len() {
+            if self.is_present(i) {
+                self.bucket(i);
+            }
+        }
+    }
+
+    // Append a brick to the save and return its index. Its asset, material
+    // and palette indices must already be valid for the save's header.
+    pub fn add_brick(&mut self, brick: Brick) -> usize {
+        let shapes = asset_shapes(&self.save_data);
+        self.faces.push(brick_faces(&brick, &shapes));
+        self.aabbs.push(AABB::from_brick(&brick, &self.save_data));
+        self.save_data.bricks.push(brick);
+        self.hidden_masks.push(0);
+        self.removed.push(false);
+        self.filtered.push(false);
+
+        let i = self.save_data.bricks.len() - 1;
+        self.attach(i);
+        i
+    }
+
+    // Returns the previous color. Color doesn't affect culling, so only the
+    // brick's own chunk is rebuilt.
+    pub fn set_color(&mut self, i: usize, color: BrickColor) -> BrickColor {
+        let old = std::mem::replace(&mut self.save_data.bricks[i].color, color);
+        if self.is_present(i) && !self.faces[i].is_empty() {
+            let key = self.chunk_key(i);
+            self.dirty.insert(key);
+        }
+        old
+    }
+
+    // Hidden bricks have no faces, so they stop covering their neighbors.
+    // Returns the previous visibility.
+    pub fn set_visible(&mut self, i: usize, visible: bool) -> bool {
+        let old = self.save_data.bricks[i].visibility;
+        if old == visible {
+            return old;
+        }
+        self.unbucket(i);
+        self.save_data.bricks[i].visibility = visible;
+        self.faces[i] = brick_faces(&self.save_data.bricks[i], &asset_shapes(&self.save_data));
+        if self.is_present(i) {
+            self.bucket(i);
+            let mut affected = self.neighbors_of(i);
+            affected.push(i);
+            self.recull(&affected);
+        }
+        old
+    }
+
+    // Moves the brick to its new material slot's chunk. Returns the previous
+    // material index.
+    pub fn set_material(&mut self, i: usize, material_index: u32) -> u32 {
+        if self.material_map.len() != self.save_data.header2.materials.len() {
+            self.material_map = material_slots(&self.save_data);
+        }
+        self.unbucket(i);
+        let old = std::mem::replace(&mut self.save_data.bricks[i].material_index, material_index);
+        if self.is_present(i) {
+            self.bucket(i);
+        }
+        old
+    }
+
+    // Index of a material in the save's header, appending it if the save
+    // doesn't use it yet.
+    pub fn material_index(&mut self, name: &str) -> u32 {
+        let materials = &mut self.save_data.header2.materials;
+        match materials.iter().position(|material| material == name) {
+            Some(i) => i as u32,
+            None => {
+                materials.push(name.to_string());
+                (materials.len() - 1) as u32
+            }
+        }
+    }
+
+    // A copy of the save as it stands after edits: removed bricks dropped and
+    // component brick indices remapped to match.
+    pub fn export_save(&self) -> SaveData {
+        let mut save_data = self.save_data.clone();
+        let mut new_index = vec![u32::MAX; save_data.bricks.len()];
+        let mut next = 0;
+        for (i, &removed) in self.removed.iter().enumerate() {
+            if !removed {
+                new_index[i] = next;
+                next += 1;
+            }
+        }
+
+        let mut i = 0;
+        save_data.bricks.retain(|_| {
+            i += 1;
+            !self.removed[i - 1]
+        });
+        for component in save_data.components.values_mut() {
+            component.brick_indices = component.brick_indices.iter()
+                .map(|&i| new_index[i as usize])
+                .filter(|&i| i != u32::MAX)
+                .collect();
+        }
+        save_data.header1.brick_count = save_data.bricks.len() as u32;
+        save_data
+    }
+
+    // Swap every brick's display color without touching faces or culling.
+    // The chunk meshes only live in the render world, so chunks whose bricks
+    // change color are re-welded on the next `take_dirty_meshes`; ones that
+    // come out the same are left alone. Bricks past the end of `colors`
+    // (added later) keep their own color.
+    pub fn set_color_override(&mut self, colors: Option<Vec<[u8; 4]>>) {
+        let before = std::mem::replace(&mut self.color_override, colors);
+        let changed: Vec<ChunkKey> = self.buckets.par_iter()
+            .filter(|(key, brick_ids)| {
+                !self.dirty.contains(*key)
+                    && brick_ids.iter().any(|&i| self.display_color_under(before.as_deref(), i) != self.display_color(i))
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        self.dirty.extend(changed);
+    }
+
+    // Give every brick a place in construction playback, or `None` to stop.
+    // All chunks are re-welded with each face's range in the order on
+    // the UV attributes: shown from when its brick appears until the first
+    // brick covering it does. Faces culled as hidden are kept for that, so
+    // the meshes grow while it's on. Bricks past the end of `order` (added
+    // later) are there from the start.
+    pub fn set_reveal_order(&mut self, order: Option<Vec<u32>>) {
+        self.reveal_order = order;
+        self.dirty.extend(self.buckets.keys().copied());
+    }
+
+    pub fn has_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    fn display_color(&self, i: usize) -> [u8; 4] {
+        self.display_color_under(self.color_override.as_deref(), i)
+    }
+
+    fn display_color_under(&self, colors: Option<&[[u8; 4]]>, i: usize) -> [u8; 4] {
+        match colors.and_then(|colors| colors.get(i)) {
+            Some(color) => *color,
+            None => cu8(brick_color(&self.save_data, &self.save_data.bricks[i])),
+        }
+    }
+
+    // Re-mesh every chunk an edit has touched since the last call. `None`
+    // means the chunk has no bricks left and its mesh should go.
+    pub fn take_dirty_meshes(&mut self) -> Vec<(ChunkKey, Option<Mesh>)> {
+        let dirty: Vec<ChunkKey> = self.dirty.drain().collect();
+        dirty.par_iter()
+            .map(|key| {
+                let mesh = self.buckets.get(key)
+                    .filter(|brick_ids| !brick_ids.is_empty())
+                    .map(|brick_ids| self.build_chunk_mesh(brick_ids).0);
+                (*key, mesh)
+            })
+            .collect()
+    }
+
+    fn chunk_key(&self, i: usize) -> ChunkKey {
+        let material = self.material_map
+            .get(self.save_data.bricks[i].material_index as usize)
+            .copied()
+            .unwrap_or(0);
+        (material, self.aabbs[i].center / CHUNK_SIZE)
+    }
+
+    fn bucket(&mut self, i: usize) {
+        if self.faces[i].is_empty() {
+            return;
+        }
+        let key = self.chunk_key(i);
+        self.buckets.entry(key).or_default().push(i);
+        self.dirty.insert(key);
+    }
+
+    fn unbucket(&mut self, i: usize) {
+        if self.faces[i].is_empty() {
+            return;
+        }
+        let key = self.chunk_key(i);
+        if let Some(brick_ids) = self.buckets.get_mut(&key) {
+            brick_ids.retain(|&b| b != i);
+        }
+        self.dirty.insert(key);
+    }
+
+    // Put a brick back into the BVH and its chunk, re-culling it and whatever
+    // it now covers.
+    fn attach(&mut self, i: usize) {
+        self.bvh.insert(i, &self.aabbs);
+        self.bucket(i);
+        let mut affected = self.neighbors_of(i);
+        affected.push(i);
+        self.recull(&affected);
+    }
+
+    // Take a brick out of the BVH and its chunk. Its neighbors are re-culled,
+    // since faces it was covering are now exposed.
+    fn detach(&mut self, i: usize) {
+        let neighbors = self.neighbors_of(i);
+        self.unbucket(i);
+        self.bvh.remove(i, &self.aabbs);
+        self.hidden_masks[i] = 0;
+        self.recull(&neighbors);
+    }
+
+    fn neighbors_of(&self, i: usize) -> Vec<usize> {
+        let mut neighbors = Vec::new();
+        self.traverse_neighbors(i, &mut neighbors);
+        neighbors
+    }
+
+    // Re-cull a handful of bricks after an edit and mark the chunks whose
+    // visible faces actually changed.
+    fn recull(&mut self, bricks: &[usize]) {
+        let mut neighbors = Vec::new();
+        let mut neighbor_faces = HashMap::default();
+        for &i in bricks {
+            let mask = self.cull_brick(i, &mut neighbors, &mut neighbor_faces);
+            if mask != self.hidden_masks[i] {
+                self.hidden_masks[i] = mask;
+                if !self.faces[i].is_empty() {
+                    let key = self.chunk_key(i);
+                    self.dirty.insert(key);
+                }
+            }
+        }
+    }
+
     // Weld + triangulate one chunk's visible faces into a single mesh. Runs on a
-    // rayon worker; touches only shared immutable state (`self`, `hidden_masks`).
-    fn build_chunk_mesh(&self, brick_ids: &[usize], hidden_masks: &[u16]) -> (Mesh, usize) {
+    // rayon worker; touches only shared immutable state.
+    fn build_chunk_mesh(&self, brick_ids: &[usize]) -> (Mesh, usize) {
+        let hidden_masks = &self.hidden_masks;
         let mut buffers = Buffers::new();
+        let mut neighbors = Vec::new();
+        let mut neighbor_faces = HashMap::default();
 
         for &i in brick_ids {
-            let color = match &self.save_data.bricks[i].color {
-                BrickColor::Index(c) => cu8(&self.save_data.header2.colors[*c as usize]),
-                BrickColor::Unique(color) => cu8(color),
+            let color = self.display_color(i);
+            let appear = self.reveal_rank(i);
+            let covered = match &self.reveal_order {
+                Some(_) if hidden_masks[i] != 0 => self.cover_ranks(i, &mut neighbors, &mut neighbor_faces),
+                _ => [NEVER_COVERED; 16],
             };
 
             let brick_faces = &self.faces[i];
             for j in 0..brick_faces.len() {
-                if hidden_masks[i] & (1 << j) != 0 {
+                if hidden_masks[i] & (1 << j) != 0 && (self.reveal_order.is_none() || covered[j] <= appear) {
                     continue;
                 }
 
@@ -344,7 +1165,7 @@ impl<'a> BVHMeshGenerator<'a> {
                 for (k, vert) in face.verts.iter().enumerate() {
                     // flush -0.0 to +0.0 so mirrored bricks weld
                     let pos = (*vert + Vec3::ZERO).to_array();
-                    vi[k] = buffers.weld(pos, normal, color);
+                    vi[k] = buffers.weld(pos, normal, color, [appear, covered[j]]);
                 }
                 for k in 0..face.verts.len().saturating_sub(2) {
                     buffers.indices.push(vi[0]);
@@ -355,7 +1176,7 @@ impl<'a> BVHMeshGenerator<'a> {
         }
 
         // drop the weld map up front — only the vertex buffers feed the mesh
-        let Buffers { position, color, normal, indices, vert_map } = buffers;
+        let Buffers { position, color, normal, reveal_high, reveal_low, indices, vert_map } = buffers;
         drop(vert_map);
         let verts = position.len();
 
@@ -373,6 +1194,10 @@ impl<'a> BVHMeshGenerator<'a> {
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, position);
         mesh.insert_attribute(ATTRIBUTE_PACKED_COLOR, VertexAttributeValues::Unorm8x4(color));
         mesh.insert_attribute(ATTRIBUTE_PACKED_NORMAL, VertexAttributeValues::Snorm8x4(normal));
+        if self.reveal_order.is_some() {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, reveal_high);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_1, reveal_low);
+        }
         mesh.insert_indices(indices);
         (mesh, verts)
     }
@@ -412,36 +1237,73 @@ impl<'a> BVHMeshGenerator<'a> {
 
         hidden
     }
-    
 
-    fn traverse_neighbors(&self, target_index: usize, neighbors: &mut Vec<usize>) {
-        let target_aabb = self.aabbs[target_index];
-        let mut stack = vec![0];
-    
-        while let Some(node) = stack.pop() {
-            match &self.bvh[node] {
-                BVHNode::Internal { aabb, left, right } => {
-                    if target_aabb.neighbors(aabb) {
-                        stack.push(*left);
-                        stack.push(*right);
-                    }
-                },
-                BVHNode::Leaf { i } => {
-                    if target_index != *i && target_aabb.neighbors(&self.aabbs[*i]) {
-                        neighbors.push(*i);
-                    }
+    fn reveal_rank(&self, i: usize) -> u32 {
+        self.reveal_order.as_ref().and_then(|order| order.get(i)).copied().unwrap_or(0)
+    }
+
+    // For each face of `target`, the earliest place in the reveal order of a
+    // brick whose face covers it; `cull_faces` stops at the first cover, this
+    // needs them all.
+    fn cover_ranks(
+        &self,
+        target: usize,
+        neighbors: &mut Vec<usize>,
+        neighbor_faces: &mut HashMap<IVec3, Vec<(usize, usize)>>,
+    ) -> [u32; 16] {
+        neighbors.clear();
+        self.traverse_neighbors(target, neighbors);
+        neighbor_faces.clear();
+        for &i in neighbors.iter() {
+            for (j, face) in self.faces[i].iter().enumerate() {
+                neighbor_faces.entry(face.int_normal).or_default().push((i, j));
+            }
+        }
+
+        let mut ranks = [NEVER_COVERED; 16];
+        for (j, face) in self.faces[target].iter().enumerate() {
+            let Some(coplanar_faces) = neighbor_faces.get(&face.int_normal.neg()) else {
+                continue;
+            };
+            for &(other_i, other_j) in coplanar_faces {
+                if face.inside(&self.faces[other_i][other_j]) {
+                    ranks[j] = ranks[j].min(self.reveal_rank(other_i));
                 }
             }
         }
+        ranks
+    }
+
+    fn traverse_neighbors(&self, target_index: usize, neighbors: &mut Vec<usize>) {
+        let target_aabb = &self.aabbs[target_index];
+        self.bvh.query(&self.aabbs, |aabb| target_aabb.neighbors(aabb), |i| {
+            if i != target_index {
+                neighbors.push(i);
+            }
+        });
     }
 }
 
-fn gen_faces(save_data: &SaveData) -> Vec<Vec<Face>> {
-    let now = SystemTime::now();
+// Which of the four chunk materials each save material renders with.
+fn material_slots(save_data: &SaveData) -> Vec<usize> {
+    save_data.header2.materials.iter()
+        .map(|material| match material.as_str() {
+            "BMC_Plastic" => 0,
+            "BMC_Glow" => 1,
+            "BMC_Glass" => 2,
+            "BMC_Metallic" => 3,
+            _ => 0,
+        })
+        .collect()
+}
+
+// Shape constructor and fixed size (when the asset has one) per brick asset.
+type AssetShape = (fn(Vec3) -> Vec<Face>, Option<Vec3>);
 
-    // Resolve the shape constructor and fixed size once per asset instead of
-    // string-matching per brick.
-    let asset_shapes: Vec<(fn(Vec3) -> Vec<Face>, Option<Vec3>)> = save_data.header2.brick_assets.iter()
+// Resolve the shape constructor and fixed size once per asset instead of
+// string-matching per brick.
+fn asset_shapes(save_data: &SaveData) -> Vec<AssetShape> {
+    save_data.header2.brick_assets.iter()
         .map(|asset| {
             let shape_fn: fn(Vec3) -> Vec<Face> = match asset.as_str() {
                 "PB_DefaultWedge" => default_wedge,
@@ -463,54 +1325,63 @@ fn gen_faces(save_data: &SaveData) -> Vec<Vec<Face>> {
                 .map(|&(w, l, h)| Vec3::new(w as f32, h as f32, l as f32));
             (shape_fn, fixed_size)
         })
-        .collect();
+        .collect()
+}
 
-    let mut data = Vec::with_capacity(save_data.bricks.len());
+fn gen_faces(save_data: &SaveData) -> Vec<Vec<Face>> {
+    let now = SystemTime::now();
 
-    data.par_extend(save_data.bricks.par_iter().map(|brick| {
-        let mut brick_faces = Vec::new();
+    let shapes = asset_shapes(save_data);
+    let mut data = Vec::with_capacity(save_data.bricks.len());
+    data.par_extend(save_data.bricks.par_iter().map(|brick| brick_faces(brick, &shapes)));
 
-        if brick.visibility {
-            let (shape_fn, fixed_size) = &asset_shapes[brick.asset_name_index as usize];
-            let size = match brick.size {
-                Size::Procedural(w, l, h) => Vec3::new(w as f32, h as f32, l as f32),
-                Size::Empty => match fixed_size {
-                    Some(size) => *size,
-                    None => return brick_faces,
-                }
-            };
+    info!("Generated faces in {} seconds", now.elapsed().unwrap().as_secs_f32());
 
-            brick_faces = shape_fn(size);
+    data
+}
 
-            let brick_position = brick_pos(brick);
-            for face in &mut brick_faces {
-                for vert in &mut face.verts {
-                    *vert = ORIENTATION_MAP[d2o(brick.direction as u8, brick.rotation as u8) as usize]
-                            .mul_vec3(*vert);
-                    *vert = *vert + brick_position;
-                }
-                face.calc_normal();
-            }
+fn brick_faces(brick: &Brick, shapes: &[AssetShape]) -> Vec<Face> {
+    let mut brick_faces = Vec::new();
+    if !brick.visibility {
+        return brick_faces;
+    }
 
-            // cull downward faces
-            brick_faces.retain(|face| {
-                face.normal != Vec3::NEG_Y
-            });
-            // precalculate projection onto its normal plane
-            for face in &mut brick_faces {
-                face.calc_2d();
-            }
+    let Some((shape_fn, fixed_size)) = shapes.get(brick.asset_name_index as usize) else {
+        return brick_faces;
+    };
+    let size = match brick.size {
+        Size::Procedural(w, l, h) => Vec3::new(w as f32, h as f32, l as f32),
+        Size::Empty => match fixed_size {
+            Some(size) => *size,
+            None => return brick_faces,
         }
+    };
 
-        brick_faces
-    }));
+    brick_faces = shape_fn(size);
 
-    info!("Generated faces in {} seconds", now.elapsed().unwrap().as_secs_f32());
+    let brick_position = brick_pos(brick);
+    for face in &mut brick_faces {
+        for vert in &mut face.verts {
+            *vert = ORIENTATION_MAP[d2o(brick.direction as u8, brick.rotation as u8) as usize]
+                    .mul_vec3(*vert);
+            *vert = *vert + brick_position;
+        }
+        face.calc_normal();
+    }
 
-    data
+    // cull downward faces
+    brick_faces.retain(|face| {
+        face.normal != Vec3::NEG_Y
+    });
+    // precalculate projection onto its normal plane
+    for face in &mut brick_faces {
+        face.calc_2d();
+    }
+
+    brick_faces
 }
 
-fn gen_aabbs(save_data: &SaveData) -> Vec<AABB> {
+pub fn gen_aabbs(save_data: &SaveData) -> Vec<AABB> {
     let now = SystemTime::now();
     let mut aabbs = Vec::with_capacity(save_data.bricks.len());
     aabbs.par_extend(save_data.bricks.par_iter().map(|brick| AABB::from_brick(brick, save_data)));
@@ -525,3 +1396,167 @@ fn brick_pos(brick: &Brick) -> Vec3 {
         brick.position.1 as f32,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(x: i32, y: i32, z: i32, half: i32) -> AABB {
+        AABB { center: IVec3::new(x, y, z), halfwidths: IVec3::splat(half) }
+    }
+
+    fn reachable(bvh: &BVH) -> usize {
+        let mut nodes = 0;
+        bvh.walk(|_, _| nodes += 1);
+        nodes
+    }
+
+    #[test]
+    fn inserts_into_an_empty_tree() {
+        let aabbs: Vec<AABB> = (0..200).map(|i| cube((i % 20) * 20, 0, (i / 20) * 20, 10)).collect();
+        let mut bvh = BVH::build(Vec::new(), &aabbs, BVHSplit::Sah);
+        for i in 0..aabbs.len() {
+            bvh.insert(i, &aabbs);
+        }
+        for (i, aabb) in aabbs.iter().enumerate() {
+            assert!(bvh.contains(i));
+            assert!(bvh.query_point(aabb.center.as_vec3(), &aabbs).contains(&i));
+        }
+        // split leaves are reused in place, nothing is left unreachable
+        assert_eq!(reachable(&bvh), bvh.arena.len());
+        assert!(bvh.depth() > 1);
+    }
+
+    #[test]
+    fn keeps_a_leaf_sah_would_not_split() {
+        // heavily overlapping boxes are cheaper tested together
+        let aabbs: Vec<AABB> = (0..16).map(|i| cube(i * 2, 0, 0, 1000)).collect();
+        let mut bvh = BVH::build(Vec::new(), &aabbs, BVHSplit::Sah);
+        for i in 0..aabbs.len() {
+            bvh.insert(i, &aabbs);
+        }
+        assert_eq!(bvh.arena.len(), 1);
+        assert_eq!(bvh.query_point(Vec3::ZERO, &aabbs).len(), aabbs.len());
+    }
+
+    #[test]
+    fn removes_and_reinserts() {
+        let aabbs: Vec<AABB> = (0..64).map(|i| cube(i * 20, 0, 0, 10)).collect();
+        let mut bvh = BVH::build((0..aabbs.len()).collect(), &aabbs, BVHSplit::Sah);
+        let point = aabbs[5].center.as_vec3();
+
+        assert!(bvh.remove(5, &aabbs));
+        assert!(!bvh.contains(5));
+        assert!(!bvh.remove(5, &aabbs));
+        assert!(!bvh.query_point(point, &aabbs).contains(&5));
+        assert_eq!(bvh.brick_depths()[5], 0);
+
+        bvh.insert(5, &aabbs);
+        assert!(bvh.contains(5));
+        assert_eq!(bvh.query_point(point, &aabbs), vec![5]);
+    }
+
+    #[test]
+    fn refits_after_inserting_outside_the_root() {
+        let mut aabbs: Vec<AABB> = (0..8).map(|i| cube(i * 20, 0, 0, 10)).collect();
+        let mut bvh = BVH::build((0..aabbs.len()).collect(), &aabbs, BVHSplit::Sah);
+        aabbs.push(cube(5000, 300, -700, 10));
+        bvh.insert(8, &aabbs);
+        assert!(bvh.root_aabb().unwrap().contains_point(Vec3::new(5000.0, 300.0, -700.0)));
+        assert_eq!(bvh.query_point(Vec3::new(5000.0, 300.0, -700.0), &aabbs), vec![8]);
+    }
+
+    #[test]
+    fn reveal_ranks_survive_the_float_attributes() {
+        // past 2^24 a single f32 would round these to even numbers
+        for rank in [0, 1, 4095, 4096, (1 << 24) + 1, u32::MAX - 1, NEVER_COVERED] {
+            let (high, low) = reveal_halves([rank, rank]);
+            for k in 0..2 {
+                assert_eq!(((high[k] as u32) << REVEAL_LOW_BITS) | low[k] as u32, rank);
+            }
+        }
+    }
+
+    // A few thousand boxes of mixed sizes, some overlapping, from a fixed
+    // seed so failures reproduce.
+    fn scattered(n: usize) -> Vec<AABB> {
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = |range: i32| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) % range as u64) as i32
+        };
+        (0..n)
+            .map(|_| AABB {
+                center: IVec3::new(next(4000) - 2000, next(1000), next(4000) - 2000),
+                halfwidths: IVec3::new(next(60) + 5, next(30) + 2, next(60) + 5),
+            })
+            .collect()
+    }
+
+    fn both_splits(aabbs: &[AABB]) -> [BVH; 2] {
+        [BVHSplit::Sah, BVHSplit::Median].map(|split| BVH::build((0..aabbs.len()).collect(), aabbs, split))
+    }
+
+    fn sorted(mut found: Vec<usize>) -> Vec<usize> {
+        found.sort_unstable();
+        found
+    }
+
+    #[test]
+    fn sah_leaves_stay_within_the_documented_size() {
+        let aabbs = scattered(3000);
+        let bvh = BVH::build((0..aabbs.len()).collect(), &aabbs, BVHSplit::Sah);
+        bvh.walk(|node, _| {
+            if let BVHNode::Leaf { bricks, .. } = node {
+                assert!(bricks.len() <= MAX_SAH_LEAF_BRICKS);
+            }
+        });
+    }
+
+    #[test]
+    fn frustum_queries_match_a_brute_force_scan() {
+        let aabbs = scattered(3000);
+        let frustum = Frustum::new(vec![
+            Vec4::new(1.0, 0.0, 0.2, 900.0),
+            Vec4::new(-1.0, 0.1, 0.0, 400.0),
+            Vec4::new(0.3, 0.0, 1.0, 1200.0),
+            Vec4::new(0.0, -1.0, -0.4, 700.0),
+        ]);
+        let expected: Vec<usize> = (0..aabbs.len()).filter(|&i| frustum.intersects_aabb(&aabbs[i])).collect();
+        assert!(!expected.is_empty() && expected.len() < aabbs.len());
+        for bvh in both_splits(&aabbs) {
+            assert_eq!(sorted(bvh.query_frustum(&frustum, &aabbs)), expected);
+        }
+    }
+
+    #[test]
+    fn sphere_queries_match_a_brute_force_scan() {
+        let aabbs = scattered(3000);
+        for (center, radius) in [(Vec3::new(0.0, 500.0, 0.0), 600.0), (Vec3::new(-1800.0, 0.0, 1500.0), 250.0), (Vec3::new(9000.0, 0.0, 0.0), 100.0)] {
+            let expected: Vec<usize> = (0..aabbs.len())
+                .filter(|&i| aabbs[i].distance_squared(center) <= radius * radius)
+                .collect();
+            for bvh in both_splits(&aabbs) {
+                assert_eq!(sorted(bvh.query_sphere(center, radius, &aabbs)), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_matches_a_brute_force_scan() {
+        let aabbs = scattered(3000);
+        for point in [Vec3::new(0.0, 500.0, 0.0), Vec3::new(-1950.0, 990.0, 1950.0), Vec3::new(5000.0, -200.0, 0.0)] {
+            let mut distances: Vec<f32> = aabbs.iter().map(|aabb| aabb.distance_squared(point).sqrt()).collect();
+            distances.sort_by(f32::total_cmp);
+            for bvh in both_splits(&aabbs) {
+                let found = bvh.nearest(point, 10, &aabbs);
+                // ties may come back in either order, so compare by distance
+                let found_distances: Vec<f32> = found.iter().map(|&(_, d)| d).collect();
+                assert_eq!(found_distances, distances[..10]);
+                for (i, d) in found {
+                    assert_eq!(aabbs[i].distance_squared(point).sqrt(), d);
+                }
+            }
+        }
+    }
+}
@@ -1,54 +1,454 @@
-use bevy::{anti_alias::fxaa::Fxaa, camera::{RenderTarget, ScalingMode}, core_pipeline::prepass::{MotionVectorPrepass, DepthPrepass, DeferredPrepass}, input::mouse::{MouseMotion, MouseWheel}, light::cluster::ClusterConfig, prelude::*, render::render_resource::TextureFormat, render::view::screenshot::{save_to_disk, Screenshot}, window::PrimaryWindow};
+use std::collections::VecDeque;
 
-use crate::{bvh::BVHNode, state::{GameState, HideOnScreenshot, Screenshotting}, SaveBVH};
+use bevy::{anti_alias::fxaa::Fxaa, camera::{ClearColorConfig, RenderTarget, ScalingMode}, core_pipeline::prepass::{MotionVectorPrepass, DepthPrepass, DeferredPrepass}, input::mouse::{MouseMotion, MouseWheel}, light::cluster::ClusterConfig, prelude::*, render::render_resource::TextureFormat, render::view::screenshot::{Screenshot, ScreenshotCaptured}, window::PrimaryWindow};
+
+use crate::{aabb::AABB, anim::{pose_animation, AnimationRender}, console::{on_off, AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand}, controls::{Action, Actions}, job::RenderJob, lit::Sun, poster::{PosterCanvas, PosterRequest, PosterWrites, TileGrid}, screenshot::{screenshot_command, screenshot_dir_command, screenshot_format_command, screenshot_name_command, screenshot_transparent_command, sync_screenshot_settings, ScreenshotSaved, ScreenshotSettings, ShotFormat, ShotMetadata, ShotOutput}, selection::Selection, settings::Settings, state::{GameState, HideOnScreenshot, Screenshotting}, SaveBVH};
 
 const DEFAULT_CAMERA_ZOOM: f32 = 800.0;
 const ISO_SCALING_MODE: f32 = 2.0;
 const CAM_CLIP_DIST: f32 = 4000000.0;
 const CAM_DIST: f32 = 100000.0;
-const ZOOM_SPEED: f32 = 12.0;
 const MIN_ZOOM: f32 = 1.0;
 const MAX_ZOOM: f32 = 100000.0;
+// Build units per stud, for pixel-perfect zoom.
+const STUD: f32 = 10.0;
+const TWEEN_SECONDS: f32 = 0.45;
+const DEFAULT_FOV: f32 = 60.0;
+pub const MIN_FOV: f32 = 10.0;
+pub const MAX_FOV: f32 = 120.0;
+const PERSPECTIVE_NEAR: f32 = 1.0;
+// Fly mode can look above the horizon, short of straight up.
+const FLY_MAX_VERTICAL_ANGLE: f32 = 179.0;
+const FLY_SPEED: f32 = 500.0;
+// Room left around a framed build, as a factor on the tight fit.
+const FRAME_MARGIN: f32 = 1.15;
+const DEFAULT_ORBIT_SPEED: f32 = 15.0;
+// For completion; `ViewPreset::parse` takes aliases too.
+const PRESET_NAMES: [&str; 4] = ["iso", "dimetric", "classic", "trimetric"];
 
 pub struct IsoCameraPlugin;
 
+// `vertical_angle` is measured from straight down: 0 is a top-down view, 90
+// looks along the horizon. Elevation above the horizon is 90 minus it.
 #[derive(Component, Default)]
 pub struct IsoCamera {
     pub target: Vec3,
     pub horizontal_angle: f32,
     pub vertical_angle: f32,
+    // How far the perspective camera sits from the target. The orthographic
+    // camera instead backs off far enough to see the whole build.
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CameraMode {
+    #[default]
+    Iso,
+    // Perspective, orbiting the same target.
+    Perspective,
+    // Perspective from a free eye: WASD flies along the view, space and C
+    // rise and sink, and turning looks around instead of orbiting.
+    Fly,
+}
+
+impl CameraMode {
+    // in declaration order, for `name`
+    pub const NAMES: [&'static str; 3] = ["iso", "perspective", "fly"];
+
+    pub fn name(self) -> &'static str {
+        CameraMode::NAMES[self as usize]
+    }
+
+    pub fn parse(name: &str) -> Option<CameraMode> {
+        match name {
+            "iso" | "ortho" => Some(CameraMode::Iso),
+            "perspective" | "persp" => Some(CameraMode::Perspective),
+            "fly" => Some(CameraMode::Fly),
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> CameraMode {
+        match self {
+            CameraMode::Iso => CameraMode::Perspective,
+            CameraMode::Perspective => CameraMode::Fly,
+            CameraMode::Fly => CameraMode::Iso,
+        }
+    }
+
+    fn max_vertical_angle(self) -> f32 {
+        match self {
+            CameraMode::Fly => FLY_MAX_VERTICAL_ANGLE,
+            _ => 90.0,
+        }
+    }
+}
+
+// Named view angles. Each one is a heading within the current 90° quadrant
+// plus an elevation, so switching presets keeps looking from the same corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewPreset {
+    // Equal foreshortening on all three axes: elevation atan(1/√2).
+    Isometric,
+    // Pixel-art 2:1 lines: elevation atan(1/2).
+    Dimetric,
+    // Three different foreshortenings; any heading and elevation.
+    Trimetric { heading: f32, elevation: f32 },
+    // The old 45°/45° default.
+    Classic,
+}
+
+impl ViewPreset {
+    pub const TRIMETRIC: ViewPreset = ViewPreset::Trimetric { heading: 30.0, elevation: 30.0 };
+    pub const ALL: [ViewPreset; 4] = [ViewPreset::Isometric, ViewPreset::Dimetric, ViewPreset::TRIMETRIC, ViewPreset::Classic];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ViewPreset::Isometric => "iso",
+            ViewPreset::Dimetric => "dimetric",
+            ViewPreset::Trimetric { .. } => "trimetric",
+            ViewPreset::Classic => "classic",
+        }
+    }
+
+    // `trimetric` takes an optional heading and elevation in degrees.
+    pub fn parse(name: &str, angles: Option<(f32, f32)>) -> Result<ViewPreset, String> {
+        match (name, angles) {
+            ("iso" | "isometric", None) => Ok(ViewPreset::Isometric),
+            ("dimetric" | "2:1", None) => Ok(ViewPreset::Dimetric),
+            ("trimetric", None) => Ok(ViewPreset::TRIMETRIC),
+            ("trimetric", Some((heading, elevation))) => {
+                if !(0.0..=90.0).contains(&elevation) {
+                    return Err("elevation must be between 0° and 90°".into());
+                }
+                Ok(ViewPreset::Trimetric { heading: heading.rem_euclid(90.0), elevation })
+            },
+            ("classic", None) => Ok(ViewPreset::Classic),
+            (_, Some(_)) if ViewPreset::parse(name, None).is_ok() => Err("only trimetric takes a heading and elevation".into()),
+            _ => Err(format!("no projection {name}")),
+        }
+    }
+
+    pub fn heading(self) -> f32 {
+        match self {
+            ViewPreset::Trimetric { heading, .. } => heading,
+            _ => 45.0,
+        }
+    }
+
+    pub fn elevation(self) -> f32 {
+        match self {
+            ViewPreset::Isometric => 35.264_39,
+            ViewPreset::Dimetric => 26.565_05,
+            ViewPreset::Trimetric { elevation, .. } => elevation,
+            ViewPreset::Classic => 45.0,
+        }
+    }
+
+    pub fn vertical_angle(self) -> f32 {
+        90.0 - self.elevation()
+    }
+
+    // The preset's angles within whichever quadrant `pose` faces.
+    pub fn posed(self, pose: CameraPose) -> CameraPose {
+        let quadrant = (pose.horizontal_angle / 90.0).floor() * 90.0;
+        CameraPose {
+            horizontal_angle: quadrant + self.heading(),
+            vertical_angle: self.vertical_angle(),
+            ..pose
+        }
+    }
+}
+
+// Everything a camera move can change: `IsoCamera`'s target and angles plus
+// the zoom. `scale` is the orthographic scale; in perspective it's the scale
+// that frames the target the same way, so poses carry across projections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraPose {
+    pub target: Vec3,
+    pub horizontal_angle: f32,
+    pub vertical_angle: f32,
+    pub scale: f32,
+}
+
+impl CameraPose {
+    pub fn current(cam: &IsoCamera, projection: &Projection) -> Self {
+        let scale = match projection {
+            Projection::Orthographic(ortho) => ortho.scale,
+            Projection::Perspective(perspective) => cam.distance * (perspective.fov / 2.0).tan() * 2.0 / ISO_SCALING_MODE,
+            _ => DEFAULT_CAMERA_ZOOM,
+        };
+        Self {
+            target: cam.target,
+            horizontal_angle: cam.horizontal_angle,
+            vertical_angle: cam.vertical_angle,
+            scale,
+        }
+    }
+
+    // Centered on `bounds` and zoomed so the whole box fits a viewport
+    // `aspect` wide per unit of height, seen from this pose's angles.
+    pub fn framing(self, bounds: &AABB, aspect: f32) -> Self {
+        let view = orbit_transform(self.horizontal_angle, self.vertical_angle, 1.0);
+        let half = bounds.halfwidths.as_vec3();
+        // half the box's extent along a view axis
+        let extent = |axis: Vec3| (axis.abs() * half).element_sum();
+        let fit = extent(*view.up()).max(extent(*view.right()) / aspect.max(f32::EPSILON));
+        Self {
+            target: bounds.center.as_vec3(),
+            scale: (fit * FRAME_MARGIN * 2.0 / ISO_SCALING_MODE).clamp(MIN_ZOOM, MAX_ZOOM),
+            ..self
+        }
+    }
+
+    // `t` of the way to `to`, turning the short way round and zooming in log
+    // space so it feels even at any scale.
+    pub fn lerp(self, to: CameraPose, t: f32) -> Self {
+        let turn = (to.horizontal_angle - self.horizontal_angle + 180.0).rem_euclid(360.0) - 180.0;
+        Self {
+            target: self.target.lerp(to.target, t),
+            horizontal_angle: self.horizontal_angle + turn * t,
+            vertical_angle: self.vertical_angle.lerp(to.vertical_angle, t),
+            scale: self.scale.ln().lerp(to.scale.ln(), t).exp(),
+        }
+    }
+
+    // Put the camera here; the transform follows in `update_transform`.
+    pub fn apply(self, cam: &mut IsoCamera, projection: &mut Projection) {
+        cam.target = self.target;
+        cam.horizontal_angle = self.horizontal_angle;
+        cam.vertical_angle = self.vertical_angle;
+        match projection {
+            Projection::Orthographic(ortho) => ortho.scale = self.scale,
+            Projection::Perspective(perspective) => cam.distance = perspective_distance(self.scale, perspective.fov),
+            _ => {}
+        }
+    }
+}
+
+// Cubic ease-in-out.
+pub fn ease(t: f32) -> f32 {
+    if t < 0.5 { 4.0 * t * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0 }
+}
+
+// Eases the camera to a pose instead of snapping. Insert it on the
+// `IsoCamera` entity; inserting another mid-flight starts from wherever the
+// camera is by then. Removed when it arrives.
+#[derive(Component)]
+pub struct CameraTween {
+    from: Option<CameraPose>,
+    to: CameraPose,
+    elapsed: f32,
+}
+
+impl CameraTween {
+    pub fn to(pose: CameraPose) -> Self {
+        Self { from: None, to: pose, elapsed: 0.0 }
+    }
+}
+
+// Zoom to fit the selection, or the whole build when nothing is selected
+// or `whole_build` is set. Sent by F and `/frame`; a new build is framed on
+// its own.
+#[derive(Message, Debug, Clone, Copy, Default)]
+pub struct FrameView {
+    pub whole_build: bool,
+    // Width over height of the view to fit, the window's when `None`; a
+    // poster job frames for the poster.
+    pub aspect: Option<f32>,
+}
+
+// Take an F2 screenshot; sent by `/screenshot take` and the remote control,
+// which tickets its shots to know its own `ScreenshotSaved` from the rest.
+#[derive(Message, Debug, Clone, Copy, Default)]
+pub struct TakeScreenshot {
+    pub ticket: Option<u64>,
+}
+
+// Turntable: degrees per second around the target, 0 when off.
+#[derive(Resource, Default)]
+pub struct Orbit(pub f32);
+
+#[derive(Resource)]
+pub struct ViewSettings {
+    pub mode: CameraMode,
+    // Vertical field of view in degrees, for perspective and fly.
+    pub fov: f32,
+    pub preset: ViewPreset,
+    // Snap zoom so a stud is a whole number of pixels, and the camera to the
+    // pixel grid, so edges don't shimmer while panning.
+    pub pixel_perfect: bool,
+}
+
+impl Default for ViewSettings {
+    fn default() -> Self {
+        Self { mode: CameraMode::Iso, fov: DEFAULT_FOV, preset: ViewPreset::Isometric, pixel_perfect: false }
+    }
 }
 
 impl Plugin for IsoCameraPlugin {
     fn build(&self, app: &mut App) {
+        // SettingsPlugin has read the file by now
+        let shots = app.world().get_resource::<Settings>().map(|settings| settings.screenshots.clone()).unwrap_or_default();
         app
             .add_systems(Startup, spawn_camera)
             .init_resource::<ScreenshotSeq>()
             .init_resource::<HiResShot>()
-            .add_systems(Update, (screenshot_sequence, hires_screenshot_sequence, move_cam_keyboard, move_cam_mouse, jump_home, update_transform, rotate_keyboard, rotate_mouse))
-            .add_systems(FixedUpdate, zoom_cam);
+            .insert_resource(shots)
+            .init_resource::<ViewSettings>()
+            .init_resource::<Orbit>()
+            .add_message::<FrameView>()
+            .add_message::<TakeScreenshot>()
+            .add_message::<ScreenshotSaved>()
+            .add_systems(Update, sync_screenshot_settings.before(screenshot_sequence))
+            .add_systems(Update, (screenshot_sequence, hires_screenshot_sequence, move_cam_keyboard, fly_keyboard, move_cam_mouse, jump_home, rotate_keyboard, rotate_mouse, cycle_camera_mode))
+            // the F2/F3 captures copy the snapped transform and scale
+            .add_systems(Update, (apply_camera_mode, frame_view, orbit, run_camera_tween, pivot_on_eye, pose_animation, update_transform, snap_to_pixels).chain().before(hires_screenshot_sequence))
+            .add_systems(FixedUpdate, zoom_cam)
+            .add_console_command(ConsoleCommand::new("/camera", "iso, perspective or fly (P cycles)").arg(Arg::word("mode", &CameraMode::NAMES).optional()), camera_command)
+            .add_console_command(ConsoleCommand::new("/fov", "perspective field of view").arg(Arg::float("degrees")), fov_command)
+            .add_console_command(
+                ConsoleCommand::new("/projection", "iso, dimetric (2:1), classic or trimetric, which can take a heading and elevation")
+                    .arg(Arg::word("preset", &PRESET_NAMES))
+                    .arg(Arg::float("heading").optional())
+                    .arg(Arg::float("elevation").optional()),
+                projection_command,
+            )
+            .add_console_command(ConsoleCommand::new("/pixelperfect", "snap zoom and camera to whole pixels per stud").arg(Arg::switch()), pixel_perfect_command)
+            .add_console_command(ConsoleCommand::new("/frame", "zoom to fit the selection"), frame_command)
+            .add_console_command(ConsoleCommand::new("/frame all", "zoom to fit the whole build"), frame_all_command)
+            .add_console_command(ConsoleCommand::new("/orbit", "turntable around the build in degrees a second; /orbit 0 stops").arg(Arg::float("speed").optional()), orbit_command)
+            .add_console_command(ConsoleCommand::new("/goto", "look at a point in save coordinates, Z up").arg(Arg::vec3("position")), goto_command)
+            .add_console_command(ConsoleCommand::new("/screenshot", "show where F2/F3/posters go and how they're named"), screenshot_command)
+            .add_console_command(ConsoleCommand::new("/screenshot take", "take a screenshot, like F2"), take_screenshot_command)
+            .add_console_command(ConsoleCommand::new("/screenshot dir", "save screenshots to a folder").arg(Arg::path("path")).local_only(), screenshot_dir_command)
+            .add_console_command(ConsoleCommand::new("/screenshot name", "name screenshots from {save} {time} {n} {kind}").arg(Arg::text("template")), screenshot_name_command)
+            .add_console_command(ConsoleCommand::new("/screenshot format", "image format for screenshots").arg(Arg::word("format", &ShotFormat::NAMES)), screenshot_format_command)
+            .add_console_command(ConsoleCommand::new("/screenshot transparent", "toggle a transparent background"), screenshot_transparent_command);
     }
 }
 
+fn take_screenshot_command(
+    In(_): In<CommandArgs>,
+    mut shots: MessageWriter<TakeScreenshot>,
+) -> CommandResult {
+    shots.write(TakeScreenshot::default());
+    Ok(String::new())
+}
+
+fn camera_command(
+    In(args): In<CommandArgs>,
+    mut settings: ResMut<ViewSettings>,
+) -> CommandResult {
+    let mode = match args.word("mode") {
+        None => settings.mode.next(),
+        Some(name) => CameraMode::parse(name).ok_or_else(|| format!("unknown camera {name}; one of {}", CameraMode::NAMES.join(", ")))?,
+    };
+    settings.mode = mode;
+    Ok(format!("{} camera", mode.name()))
+}
+
+fn fov_command(
+    In(args): In<CommandArgs>,
+    mut settings: ResMut<ViewSettings>,
+) -> CommandResult {
+    let fov = args.float("degrees").filter(|fov| (MIN_FOV..=MAX_FOV).contains(fov))
+        .ok_or_else(|| format!("field of view must be {MIN_FOV}-{MAX_FOV}°"))?;
+    settings.fov = fov;
+    Ok(format!("field of view {fov}°"))
+}
+
+fn projection_command(
+    In(args): In<CommandArgs>,
+    mut commands: Commands,
+    mut settings: ResMut<ViewSettings>,
+    cam_query: Query<(Entity, &IsoCamera, &Projection)>,
+) -> CommandResult {
+    let angles = match (args.float("heading"), args.float("elevation")) {
+        (Some(heading), Some(elevation)) => Some((heading, elevation)),
+        (Some(_), None) => return Err("give the elevation too".into()),
+        _ => None,
+    };
+    let preset = ViewPreset::parse(args.word("preset").unwrap_or_default(), angles)?;
+    settings.preset = preset;
+    if let Ok((entity, cam, projection)) = cam_query.single() {
+        commands.entity(entity).insert(CameraTween::to(preset.posed(CameraPose::current(cam, projection))));
+    }
+    Ok(format!("{} projection: heading {:.3}°, elevation {:.3}°", preset.name(), preset.heading(), preset.elevation()))
+}
+
+fn pixel_perfect_command(
+    In(args): In<CommandArgs>,
+    mut settings: ResMut<ViewSettings>,
+) -> CommandResult {
+    settings.pixel_perfect = args.switch(settings.pixel_perfect);
+    Ok(format!("pixel perfect {}", on_off(settings.pixel_perfect)))
+}
+
+fn frame_command(
+    In(_): In<CommandArgs>,
+    mut frame: MessageWriter<FrameView>,
+) -> CommandResult {
+    frame.write(FrameView::default());
+    Ok(String::new())
+}
+
+fn frame_all_command(
+    In(_): In<CommandArgs>,
+    mut frame: MessageWriter<FrameView>,
+) -> CommandResult {
+    frame.write(FrameView { whole_build: true, ..default() });
+    Ok(String::new())
+}
+
+fn orbit_command(
+    In(args): In<CommandArgs>,
+    mut commands: Commands,
+    mut orbit: ResMut<Orbit>,
+    cam_query: Query<(Entity, &IsoCamera, &Projection)>,
+    bvh_query: Query<&SaveBVH>,
+) -> CommandResult {
+    let speed = args.float("speed").unwrap_or(if orbit.0 == 0.0 { DEFAULT_ORBIT_SPEED } else { 0.0 });
+    orbit.0 = speed;
+    if speed == 0.0 {
+        return Ok("orbit off".into());
+    }
+    // swing round the middle of the build
+    let com = bvh_query.iter().next().map(|save_bvh| save_bvh.com);
+    if let (Some(target), Ok((entity, cam, projection))) = (com, cam_query.single()) {
+        commands.entity(entity).insert(CameraTween::to(CameraPose { target, ..CameraPose::current(cam, projection) }));
+    }
+    Ok(format!("orbiting at {speed}°/s"))
+}
+
+// `/goto x y z` in the save's axes, as the brick info window shows them.
+fn goto_command(
+    In(args): In<CommandArgs>,
+    mut commands: Commands,
+    cam_query: Query<(Entity, &IsoCamera, &Projection)>,
+) -> CommandResult {
+    let position = args.vec3("position").unwrap_or_default();
+    let (entity, cam, projection) = cam_query.single().map_err(|_| "no camera")?;
+    let target = Vec3::new(position.x, position.z, position.y);
+    commands.entity(entity).insert(CameraTween::to(CameraPose { target, ..CameraPose::current(cam, projection) }));
+    Ok(format!("looking at {} {} {}", position.x, position.y, position.z))
+}
+
 fn spawn_camera(
     mut commands: Commands,
 ) {
     let default_translation = Vec3::new(CAM_DIST, CAM_DIST, CAM_DIST);
     let default_transform = Transform::from_translation(default_translation).looking_at(Vec3::ZERO, Vec3::Y);
 
+    let preset = ViewSettings::default().preset;
     commands.spawn((
         IsoCamera {
-            horizontal_angle: 45.0,
-            vertical_angle: 45.0,
+            horizontal_angle: preset.heading(),
+            vertical_angle: preset.vertical_angle(),
             ..default()
         },
         Camera3d::default(),
-        Projection::Orthographic(OrthographicProjection {
-            scale: DEFAULT_CAMERA_ZOOM,
-            scaling_mode: ScalingMode::FixedVertical { viewport_height: ISO_SCALING_MODE },
-            far: CAM_CLIP_DIST,
-            ..OrthographicProjection::default_3d()
-        }),
+        ortho_projection(DEFAULT_CAMERA_ZOOM),
         default_transform,
         // Gentle ambient fill lifts shadows off pure black without washing them out.
         // (A second *directional* fill light would flatten/erase the sun's shadows.)
@@ -69,6 +469,77 @@ fn spawn_camera(
     ));
 }
 
+fn ortho_projection(scale: f32) -> Projection {
+    Projection::Orthographic(OrthographicProjection {
+        scale,
+        scaling_mode: ScalingMode::FixedVertical { viewport_height: ISO_SCALING_MODE },
+        far: CAM_CLIP_DIST,
+        ..OrthographicProjection::default_3d()
+    })
+}
+
+// Perspective distance that shows as much around the target as an
+// orthographic `scale` does.
+fn perspective_distance(scale: f32, fov: f32) -> f32 {
+    scale * ISO_SCALING_MODE / 2.0 / (fov / 2.0).tan()
+}
+
+// Swap projections when the mode or FOV changes, keeping the framing at the
+// target. The iso view is put aside on the way out and restored on return.
+fn apply_camera_mode(
+    settings: Res<ViewSettings>,
+    mut query: Query<(&mut IsoCamera, &mut Projection)>,
+    mut iso_view: Local<Option<CameraPose>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let Ok((mut cam, mut projection)) = query.single_mut() else { return; };
+    let pose = CameraPose::current(&cam, &projection);
+    let fov = settings.fov.to_radians();
+
+    match (settings.mode, projection.as_mut()) {
+        (CameraMode::Iso, Projection::Perspective(_)) => {
+            let pose = iso_view.take().unwrap_or(pose);
+            *projection = ortho_projection(pose.scale);
+            cam.target = pose.target;
+            cam.horizontal_angle = pose.horizontal_angle;
+            cam.vertical_angle = pose.vertical_angle;
+        },
+        (CameraMode::Perspective | CameraMode::Fly, Projection::Orthographic(_)) => {
+            *iso_view = Some(pose);
+            *projection = Projection::Perspective(PerspectiveProjection {
+                fov,
+                near: PERSPECTIVE_NEAR,
+                far: CAM_CLIP_DIST,
+                ..default()
+            });
+            cam.distance = perspective_distance(pose.scale, fov);
+        },
+        (CameraMode::Perspective | CameraMode::Fly, Projection::Perspective(perspective)) => {
+            if perspective.fov != fov {
+                perspective.fov = fov;
+                cam.distance = perspective_distance(pose.scale, fov);
+            }
+            let max = settings.mode.max_vertical_angle();
+            if cam.vertical_angle > max {
+                cam.vertical_angle = max;
+            }
+        },
+        _ => {}
+    }
+}
+
+fn cycle_camera_mode(
+    actions: Actions,
+    game_state: Res<GameState>,
+    mut settings: ResMut<ViewSettings>,
+) {
+    if actions.just_pressed(Action::CycleCamera) && game_state.input_listening() {
+        settings.mode = settings.mode.next();
+    }
+}
+
 #[derive(Default)]
 enum ShotPhase {
     #[default]
@@ -85,35 +556,68 @@ struct ScreenshotSeq {
     counter: u32,
     // Prior visibility of each hidden overlay, restored after capture.
     saved: Vec<(Entity, Visibility)>,
+    // The live camera's clear color, swapped out for a transparent shot.
+    clear_color: Option<ClearColorConfig>,
+    // Requested shots waiting for the one in flight, by ticket.
+    queued: VecDeque<Option<u64>>,
+    // the ticket of the one in flight
+    ticket: Option<u64>,
+}
+
+// The shot's metadata: the loaded save and the live camera and sun.
+fn shot_metadata(
+    bvh_query: &Query<&SaveBVH>,
+    cam: &IsoCamera,
+    projection: &Projection,
+    sun_query: &Query<&Transform, With<Sun>>,
+    view: &ViewSettings,
+) -> ShotMetadata {
+    let save = bvh_query.iter().next().map(|save_bvh| save_bvh.path.as_path());
+    ShotMetadata::new(save, cam, projection, sun_query.iter().next(), view)
 }
 
 // F2 hides overlay UI, waits a frame, captures, then restores. `Screenshotting`
 // lets non-Visibility UI (view cube, egui brick info) opt out during capture.
 fn screenshot_sequence(
-    input: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
+    mut requests: MessageReader<TakeScreenshot>,
     mut commands: Commands,
     mut seq: ResMut<ScreenshotSeq>,
     mut screenshotting: ResMut<Screenshotting>,
     mut overlays: Query<(Entity, &mut Visibility), With<HideOnScreenshot>>,
+    mut main_cam: Query<(&mut Camera, &IsoCamera, &Projection)>,
+    sun_query: Query<&Transform, With<Sun>>,
+    bvh_query: Query<&SaveBVH>,
+    settings: Res<ScreenshotSettings>,
+    view: Res<ViewSettings>,
 ) {
+    seq.queued.extend(requests.read().map(|request| request.ticket));
     match seq.phase {
         ShotPhase::Idle => {
-            if input.just_pressed(KeyCode::F2) {
+            let ticket = if actions.just_pressed(Action::Screenshot) { Some(None) } else { seq.queued.pop_front() };
+            if let Some(ticket) = ticket {
+                seq.ticket = ticket;
                 seq.saved.clear();
                 for (entity, mut vis) in overlays.iter_mut() {
                     seq.saved.push((entity, *vis));
                     *vis = Visibility::Hidden;
                 }
+                if let Ok((mut camera, _, _)) = main_cam.single_mut() {
+                    let clear_color = std::mem::replace(&mut camera.clear_color, settings.clear_color());
+                    seq.clear_color = Some(clear_color);
+                }
                 seq.phase = ShotPhase::Capture;
                 screenshotting.0 = true;
             }
         }
         ShotPhase::Capture => {
-            let path = format!("./screenshot-{}.png", seq.counter);
+            let metadata = main_cam.single().ok()
+                .map(|(_, cam, projection)| shot_metadata(&bvh_query, cam, projection, &sun_query, &view));
+            let output = settings.output("screenshot", seq.counter, metadata);
             seq.counter += 1;
             commands
                 .spawn(Screenshot::primary_window())
-                .observe(save_to_disk(path));
+                .observe(output.on_shot_captured(seq.ticket));
             seq.phase = ShotPhase::Restore;
         }
         ShotPhase::Restore => {
@@ -122,16 +626,15 @@ fn screenshot_sequence(
                     *current = vis;
                 }
             }
+            if let (Some(clear_color), Ok((mut camera, _, _))) = (seq.clear_color.take(), main_cam.single_mut()) {
+                camera.clear_color = clear_color;
+            }
             screenshotting.0 = false;
             seq.phase = ShotPhase::Idle;
         }
     }
 }
 
-// Supersample factor for F3 high-res captures. The output image is up to this
-// many times the window's pixel dimensions, so a 4x shot of a 1080p window is
-// ~7680x4320 (8K). Framing matches the live view; you just get more pixels.
-const HIRES_SCALE: f32 = 4.0;
 // wgpu's default `max_texture_dimension_2d`. Larger targets fail to allocate, so
 // the effective scale is clamped to keep both dimensions within this bound.
 const HIRES_MAX_DIM: u32 = 8192;
@@ -142,6 +645,9 @@ const HIRES_MAX_DIM: u32 = 8192;
 // (renders black) until they're ready. Matching the window's texture format
 // keeps this cheap (pipelines are shared), but a margin is still needed.
 const HIRES_WARMUP_FRAMES: u8 = 24;
+// Later poster tiles reuse the warm camera and target; only the projection
+// changes.
+const TILE_WARMUP_FRAMES: u8 = 3;
 
 #[derive(Default)]
 enum HiResPhase {
@@ -161,39 +667,99 @@ struct HiResShot {
     image: Option<Handle<Image>>,
     cam: Option<Entity>,
     saved: Vec<(Entity, Visibility)>,
+    // Projections left to render, the one in flight first: the live camera's
+    // for F3 and animations, or a poster's tiles.
+    tiles: VecDeque<Projection>,
+    tile_index: usize,
+    poster: Option<PosterCanvas>,
+    // Where an F3 shot goes, named when it starts.
+    output: Option<ShotOutput>,
 }
 
 // F3 renders the scene to an off-screen texture larger than the window, then
 // screenshots that texture — yielding resolutions above the monitor's. A short
 // clone of the live camera (same transform + orthographic projection + deferred
 // prepass stack) targets the image so the framing is identical to what's on
-// screen, only sharper.
+// screen, only sharper. A `PosterRequest` runs the same sequence once per
+// tile, swapping the projection between captures, and an animation once per
+// frame, following the live camera as it's posed.
 fn hires_screenshot_sequence(
-    input: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
+    mut posters: MessageReader<PosterRequest>,
     mut commands: Commands,
     mut shot: ResMut<HiResShot>,
     mut screenshotting: ResMut<Screenshotting>,
     mut overlays: Query<(Entity, &mut Visibility), With<HideOnScreenshot>>,
     mut images: ResMut<Assets<Image>>,
     windows: Query<&Window, With<PrimaryWindow>>,
-    main_cam: Query<(&Transform, &Projection), With<IsoCamera>>,
+    main_cam: Query<(&Transform, &Projection, &IsoCamera, &AmbientLight)>,
+    sun_query: Query<&Transform, With<Sun>>,
+    bvh_query: Query<&SaveBVH>,
+    settings: Res<ScreenshotSettings>,
+    view: Res<ViewSettings>,
+    mut animation: ResMut<AnimationRender>,
+    user_settings: Res<Settings>,
+    (job, mut exit): (Option<Res<RenderJob>>, MessageWriter<AppExit>),
 ) {
+    // A poster that can't start fails its job instead of leaving it waiting.
+    let mut reject_poster = |error: &str| {
+        error!("Cannot render poster: {error}");
+        if job.is_some() {
+            exit.write(AppExit::error());
+        }
+    };
+    let poster = posters.read().last().cloned();
+    if poster.is_some() && !matches!(shot.phase, HiResPhase::Idle) {
+        reject_poster("another render is running");
+    }
+
     match shot.phase {
         HiResPhase::Idle => {
-            if !input.just_pressed(KeyCode::F3) {
+            let frame_size = animation.waiting();
+            if poster.is_none() && frame_size.is_none() && !actions.just_pressed(Action::HiResScreenshot) {
                 return;
             }
             let Ok(window) = windows.single() else { return };
-            let Ok((cam_transform, cam_projection)) = main_cam.single() else { return };
+            let Ok((cam_transform, cam_projection, iso_cam, ambient)) = main_cam.single() else { return };
+            let metadata = shot_metadata(&bvh_query, iso_cam, cam_projection, &sun_query, &view);
 
-            let (w, h) = (window.physical_width().max(1), window.physical_height().max(1));
-            // Uniform scale preserves aspect ratio while keeping both dimensions
-            // under the GPU's max texture size.
-            let scale = HIRES_SCALE
-                .min(HIRES_MAX_DIM as f32 / w as f32)
-                .min(HIRES_MAX_DIM as f32 / h as f32);
-            let width = ((w as f32 * scale) as u32).max(1);
-            let height = ((h as f32 * scale) as u32).max(1);
+            let (width, height) = match (poster, frame_size) {
+                (Some(request), _) => {
+                    let Projection::Orthographic(ortho) = cam_projection else {
+                        reject_poster("posters need the iso camera");
+                        return;
+                    };
+                    let grid = TileGrid::new(request.size);
+                    let mut output = settings.output("poster", shot.counter, Some(metadata));
+                    if let Some(path) = request.path {
+                        output = output.at(path);
+                    }
+                    shot.counter += 1;
+                    info!("Rendering a {}x{} poster to {} in {} tiles", grid.size.x, grid.size.y, output.path.display(), grid.len());
+                    shot.tiles = (0..grid.len()).map(|i| grid.projection(i, ortho)).collect();
+                    shot.poster = Some(PosterCanvas::new(grid, output));
+                    (grid.tile.x, grid.tile.y)
+                },
+                (None, Some(size)) => {
+                    animation.begin();
+                    shot.tiles = VecDeque::from([cam_projection.clone()]);
+                    (size.x, size.y)
+                },
+                (None, None) => {
+                    let (w, h) = (window.physical_width().max(1), window.physical_height().max(1));
+                    // Uniform scale preserves aspect ratio while keeping both dimensions
+                    // under the GPU's max texture size. Framing matches the live view;
+                    // you just get more pixels.
+                    let scale = user_settings.hires_scale
+                        .min(HIRES_MAX_DIM as f32 / w as f32)
+                        .min(HIRES_MAX_DIM as f32 / h as f32);
+                    shot.tiles = VecDeque::from([cam_projection.clone()]);
+                    shot.output = Some(settings.output("screenshot-hires", shot.counter, Some(metadata)));
+                    shot.counter += 1;
+                    (((w as f32 * scale) as u32).max(1), ((h as f32 * scale) as u32).max(1))
+                },
+            };
+            shot.tile_index = 0;
 
             // Match the window swapchain format so the scene's already-compiled
             // render pipelines are reused instead of re-specialized (which would
@@ -211,14 +777,14 @@ fn hires_screenshot_sequence(
             let cam = commands
                 .spawn((
                     Camera3d::default(),
-                    RenderTarget::Image(image.clone().into()),
-                    cam_projection.clone(),
-                    *cam_transform,
-                    AmbientLight {
-                        color: Color::WHITE,
-                        brightness: 600.0,
+                    Camera {
+                        clear_color: settings.clear_color(),
                         ..default()
                     },
+                    RenderTarget::Image(image.clone().into()),
+                    shot.tiles[0].clone(),
+                    *cam_transform,
+                    ambient.clone(),
                     Msaa::Off,
                     ClusterConfig::Single,
                     DepthPrepass,
@@ -239,6 +805,11 @@ fn hires_screenshot_sequence(
             shot.phase = HiResPhase::Warmup(HIRES_WARMUP_FRAMES);
         }
         HiResPhase::Warmup(n) => {
+            if animation.running() {
+                if let (Some(cam), Ok((transform, projection, _, _))) = (shot.cam, main_cam.single()) {
+                    commands.entity(cam).insert((*transform, projection.clone()));
+                }
+            }
             shot.phase = if n > 0 { HiResPhase::Warmup(n - 1) } else { HiResPhase::Capture };
         }
         HiResPhase::Capture => {
@@ -246,11 +817,22 @@ fn hires_screenshot_sequence(
             // the screenshot copy runs after the render graph, so it reads fresh
             // pixels rather than a stale or cleared texture.
             if let Some(image) = shot.image.clone() {
-                let path = format!("./screenshot-hires-{}.png", shot.counter);
-                shot.counter += 1;
-                commands
-                    .spawn(Screenshot::image(image))
-                    .observe(save_to_disk(path));
+                let mut screenshot = commands.spawn(Screenshot::image(image));
+                if shot.poster.is_some() {
+                    let index = shot.tile_index;
+                    screenshot.observe(move |captured: On<ScreenshotCaptured>, mut shot: ResMut<HiResShot>, mut writes: ResMut<PosterWrites>| {
+                        let done = shot.poster.as_mut().is_some_and(|poster| poster.paste(index, &captured.image));
+                        if let Some(poster) = shot.poster.take_if(|_| done) {
+                            writes.push(poster.save());
+                        }
+                    });
+                } else if let Some(output) = shot.output.take().or_else(|| {
+                    let metadata = main_cam.single().ok()
+                        .map(|(_, projection, cam, _)| shot_metadata(&bvh_query, cam, projection, &sun_query, &view));
+                    animation.frame_output(&settings, metadata)
+                }) {
+                    screenshot.observe(output.on_captured());
+                }
             }
             shot.phase = HiResPhase::Draining(8);
         }
@@ -258,6 +840,17 @@ fn hires_screenshot_sequence(
             if n > 0 {
                 shot.phase = HiResPhase::Draining(n - 1);
             } else {
+                shot.tiles.pop_front();
+                if let (Some(next), Some(cam)) = (shot.tiles.front().cloned(), shot.cam) {
+                    commands.entity(cam).insert(next);
+                    shot.tile_index += 1;
+                    shot.phase = HiResPhase::Warmup(TILE_WARMUP_FRAMES);
+                    return;
+                }
+                if animation.running() && animation.advance() {
+                    shot.phase = HiResPhase::Warmup(TILE_WARMUP_FRAMES);
+                    return;
+                }
                 // Readback done: despawn the temp camera, free the image, restore UI.
                 if let Some(cam) = shot.cam.take() {
                     commands.entity(cam).despawn();
@@ -281,22 +874,13 @@ fn move_cam_mouse(
     mut cam_query: Query<(&Transform, &mut IsoCamera)>,
     projection_query: Query<&Projection, With<IsoCamera>>,
     mut motion_evr: MessageReader<MouseMotion>,
-    mouse: Res<ButtonInput<MouseButton>>,
+    actions: Actions,
 ) {
-    if !mouse.pressed(MouseButton::Left) || mouse.just_pressed(MouseButton::Left) {
+    // a box-select drag on the same button, ctrl-drag by default, doesn't count
+    if !actions.pressed(Action::PanDrag) || actions.just_pressed(Action::PanDrag) {
         return;
     }
 
-    let projection = projection_query.single().unwrap();
-    let scale = match projection {
-        Projection::Orthographic(projection) => {
-            projection.scale
-        },
-        _ => {
-            return;
-        }
-    };
-
     let mut motion = Vec2::ZERO;
 
     for ev in motion_evr.read() {
@@ -309,6 +893,7 @@ fn move_cam_mouse(
     }
 
     let (transform, mut cam) = cam_query.single_mut().unwrap();
+    let scale = CameraPose::current(&cam, projection_query.single().unwrap()).scale;
 
     let move_x = transform.local_x() * motion.x;
     let move_z = transform.local_y() * motion.y;
@@ -318,30 +903,31 @@ fn move_cam_mouse(
 
 fn move_cam_keyboard(
     mut cam_query: Query<&mut IsoCamera>,
-    keyboard: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
     game_state: Res<GameState>,
+    settings: Res<ViewSettings>,
     time: Res<Time>,
 ) {
-    if !game_state.input_listening() {
+    if !game_state.input_listening() || settings.mode == CameraMode::Fly {
         return;
     }
 
     let mut movement = Vec3::ZERO;
 
-    if keyboard.pressed(KeyCode::KeyW) {
+    if actions.pressed(Action::MoveForward) {
         movement += Vec3::NEG_Z;
-    } else if keyboard.pressed(KeyCode::KeyS) {
+    } else if actions.pressed(Action::MoveBack) {
         movement += Vec3::Z;
     }
-    if keyboard.pressed(KeyCode::KeyD) {
+    if actions.pressed(Action::MoveRight) {
         movement += Vec3::X;
-    } else if keyboard.pressed(KeyCode::KeyA) {
+    } else if actions.pressed(Action::MoveLeft) {
         movement += Vec3::NEG_X;
     }
 
     movement = movement.normalize_or_zero();
 
-    if keyboard.pressed(KeyCode::ShiftLeft) {
+    if actions.pressed(Action::Faster) {
         movement *= 10.0;
     }
 
@@ -350,10 +936,53 @@ fn move_cam_keyboard(
     main_cam.target += delta;
 }
 
+// Fly mode moves along the view rather than across the ground.
+fn fly_keyboard(
+    mut cam_query: Query<(&Transform, &mut IsoCamera)>,
+    actions: Actions,
+    game_state: Res<GameState>,
+    settings: Res<ViewSettings>,
+    time: Res<Time>,
+) {
+    if !game_state.input_listening() || settings.mode != CameraMode::Fly {
+        return;
+    }
+    let Ok((transform, mut cam)) = cam_query.single_mut() else { return; };
+
+    let mut movement = Vec3::ZERO;
+    if actions.pressed(Action::MoveForward) {
+        movement += *transform.forward();
+    } else if actions.pressed(Action::MoveBack) {
+        movement += *transform.back();
+    }
+    if actions.pressed(Action::MoveRight) {
+        movement += *transform.right();
+    } else if actions.pressed(Action::MoveLeft) {
+        movement += *transform.left();
+    }
+    if actions.pressed(Action::MoveUp) {
+        movement += Vec3::Y;
+    } else if actions.pressed(Action::MoveDown) {
+        movement += Vec3::NEG_Y;
+    }
+
+    movement = movement.normalize_or_zero();
+    if movement == Vec3::ZERO {
+        return;
+    }
+
+    if actions.pressed(Action::Faster) {
+        movement *= 10.0;
+    }
+
+    cam.target += movement * time.delta_secs() * FLY_SPEED;
+}
+
 fn zoom_cam(
     mut scroll_evr: MessageReader<MouseWheel>,
-    mut query: Query<&mut Projection, With<IsoCamera>>,
+    mut query: Query<(&mut Projection, &mut IsoCamera)>,
     time: Res<Time>,
+    settings: Res<Settings>,
 ) {
     let mut zoom_delta = 0.;
 
@@ -374,54 +1003,140 @@ fn zoom_cam(
         return;
     }
 
-    for mut projection in query.iter_mut() {
+    for (mut projection, mut cam) in query.iter_mut() {
         match projection.as_mut() {
             Projection::Orthographic(projection) => {
                 let mut log_scale = projection.scale.ln();
-                log_scale -= zoom_delta * time.delta_secs() * ZOOM_SPEED;
+                log_scale -= zoom_delta * time.delta_secs() * settings.zoom_speed;
                 projection.scale = log_scale.exp().clamp(MIN_ZOOM, MAX_ZOOM);
             },
+            // perspective zoom dollies toward the target
+            Projection::Perspective(_) => {
+                let mut log_distance = cam.distance.ln();
+                log_distance -= zoom_delta * time.delta_secs() * settings.zoom_speed;
+                cam.distance = log_distance.exp().clamp(MIN_ZOOM, MAX_ZOOM);
+            },
             _ => {}
         }
     }
 }
 
 fn jump_home(
-    mut query: Query<&mut IsoCamera>,
+    mut commands: Commands,
+    query: Query<(Entity, &IsoCamera, &Projection)>,
     bvh_query: Query<&SaveBVH>,
-    keyboard: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
     game_state: Res<GameState>,
 ) {
-    if !keyboard.just_pressed(KeyCode::KeyH) || !game_state.input_listening() {
+    if !actions.just_pressed(Action::Recenter) || !game_state.input_listening() {
         return;
     }
 
-    if let Ok(mut cam) = query.single_mut() {
-        if let Some(bvh) = bvh_query.iter().last() {
-            cam.target = bvh.com;
-        } else {
-            cam.target = Vec3::ZERO;
+    if let Ok((entity, cam, projection)) = query.single() {
+        let target = bvh_query.iter().last().map_or(Vec3::ZERO, |bvh| bvh.com);
+        commands.entity(entity).insert(CameraTween::to(CameraPose { target, ..CameraPose::current(cam, projection) }));
+    }
+}
+
+fn frame_view(
+    mut commands: Commands,
+    mut requests: MessageReader<FrameView>,
+    actions: Actions,
+    game_state: Res<GameState>,
+    selection: Res<Selection>,
+    bvh_query: Query<Ref<SaveBVH>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cam_query: Query<(Entity, &IsoCamera, &Projection)>,
+) {
+    let mut request = requests.read().last().copied();
+    if actions.just_pressed(Action::Frame) && game_state.input_listening() {
+        request = Some(FrameView::default());
+    }
+    let Ok(save_bvh) = bvh_query.single() else { return; };
+    if save_bvh.is_added() && !request.is_some_and(|request| request.whole_build) {
+        request = Some(FrameView { whole_build: true, ..default() });
+    }
+    let Some(request) = request else { return; };
+
+    let build = &save_bvh.generator;
+    let selected = if request.whole_build || selection.is_empty() {
+        None
+    } else {
+        AABB::bounds(&selection.sorted().into_iter().map(|i| build.aabbs[i]).collect::<Vec<_>>())
+    };
+    let Some(bounds) = selected.or_else(|| build.bvh.root_aabb().copied()) else { return; };
+    let Ok((entity, cam, projection)) = cam_query.single() else { return; };
+    let aspect = request.aspect.unwrap_or_else(|| {
+        windows.single().map_or(1.0, |window| window.width() / window.height().max(1.0))
+    });
+
+    commands.entity(entity).insert(CameraTween::to(CameraPose::current(cam, projection).framing(&bounds, aspect)));
+}
+
+fn run_camera_tween(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut IsoCamera, &mut Projection, &mut CameraTween)>,
+) {
+    for (entity, mut cam, mut projection, mut tween) in &mut query {
+        let from = *tween.from.get_or_insert(CameraPose::current(&cam, &projection));
+        let to = tween.to;
+        tween.elapsed += time.delta_secs();
+        let t = (tween.elapsed / TWEEN_SECONDS).min(1.0);
+        from.lerp(to, ease(t)).apply(&mut cam, &mut projection);
+
+        if t >= 1.0 {
+            commands.entity(entity).remove::<CameraTween>();
         }
     }
 }
 
+// Turntable rotation, paused while a tween is moving the camera.
+fn orbit(
+    orbit: Res<Orbit>,
+    time: Res<Time>,
+    mut query: Query<&mut IsoCamera, Without<CameraTween>>,
+) {
+    if orbit.0 == 0.0 {
+        return;
+    }
+    for mut cam in &mut query {
+        cam.horizontal_angle += orbit.0 * time.delta_secs();
+    }
+}
+
+// In fly mode turning looks around from the eye instead of orbiting: whatever
+// changed the angles, slide the target so the eye stays put. The transform
+// still holds last frame's eye at this point.
+fn pivot_on_eye(
+    settings: Res<ViewSettings>,
+    mut query: Query<(&Transform, &mut IsoCamera), Without<CameraTween>>,
+    mut last_angles: Local<Option<(f32, f32)>>,
+) {
+    if settings.mode != CameraMode::Fly {
+        *last_angles = None;
+        return;
+    }
+    for (transform, mut cam) in &mut query {
+        let angles = (cam.horizontal_angle, cam.vertical_angle);
+        if last_angles.is_some_and(|last| last != angles) {
+            let forward = *orbit_transform(angles.0, angles.1, 1.0).forward();
+            cam.target = transform.translation + forward * cam.distance;
+        }
+        *last_angles = Some(angles);
+    }
+}
+
 // Process changes to camera target
 fn update_transform(
-    mut query: Query<(&mut Transform, &mut IsoCamera), Changed<IsoCamera>>,
+    mut query: Query<(&mut Transform, &mut IsoCamera, &Projection), Changed<IsoCamera>>,
     bvh_query: Query<&SaveBVH>,
+    settings: Res<ViewSettings>,
 ) {
-    for (mut transform, mut cam) in query.iter_mut() {
-        let rotate_z = Quat::from_axis_angle(Vec3::NEG_Z, cam.vertical_angle.to_radians());
-        let rotate_y = Quat::from_axis_angle(Vec3::Y, -cam.horizontal_angle.to_radians());
-        let rotation = rotate_y * rotate_z;
-
+    for (mut transform, mut cam, projection) in query.iter_mut() {
         let mut max_dist = 0.0;
         for save_bvh in bvh_query.iter() {
-            let root = &save_bvh.bvh[0];
-            let aabb = match root {
-                BVHNode::Internal { aabb, left: _, right: _ } => aabb,
-                _ => { continue; }
-            };
+            let Some(aabb) = save_bvh.generator.bvh.root_aabb() else { continue; };
             let max_side = aabb.halfwidths.x.max(aabb.halfwidths.y).max(aabb.halfwidths.z);
             let dist = max_side as f32 * 2.0;
 
@@ -430,27 +1145,42 @@ fn update_transform(
             }
         }
 
-        let translation = rotation.mul_vec3(Vec3::new(0.0, max_dist, 0.0)) + cam.target;
-
-        let up = if cam.vertical_angle == 0.0 {
-            rotate_y.mul_vec3(Vec3::NEG_Z)
-        } else {
-            Vec3::Y
-        };
+        if let Projection::Perspective(_) = projection {
+            max_dist = cam.distance;
+        }
 
-        *transform = Transform::from_translation(translation).looking_at(cam.target, up);
+        *transform = orbit_transform(cam.horizontal_angle, cam.vertical_angle, max_dist);
+        transform.translation += cam.target;
 
-        if cam.horizontal_angle >= 360. || cam.horizontal_angle < -360. {
-            cam.horizontal_angle = 0.0;
+        // wrap rather than reset, so turntable and tweens stay smooth
+        if cam.horizontal_angle >= 360. || cam.horizontal_angle < 0. {
+            cam.horizontal_angle = cam.horizontal_angle.rem_euclid(360.0);
         }
 
-        cam.vertical_angle = cam.vertical_angle.clamp(0.0, 90.0);
+        cam.vertical_angle = cam.vertical_angle.clamp(0.0, settings.mode.max_vertical_angle());
     }
 }
 
+// The camera `distance` out from a target at the origin, looking at it.
+fn orbit_transform(horizontal_angle: f32, vertical_angle: f32, distance: f32) -> Transform {
+    let rotate_z = Quat::from_axis_angle(Vec3::NEG_Z, vertical_angle.to_radians());
+    let rotate_y = Quat::from_axis_angle(Vec3::Y, -horizontal_angle.to_radians());
+    let rotation = rotate_y * rotate_z;
+
+    let translation = rotation.mul_vec3(Vec3::new(0.0, distance, 0.0));
+
+    let up = if vertical_angle == 0.0 {
+        rotate_y.mul_vec3(Vec3::NEG_Z)
+    } else {
+        Vec3::Y
+    };
+
+    Transform::from_translation(translation).looking_at(Vec3::ZERO, up)
+}
+
 fn rotate_keyboard(
     mut query: Query<&mut IsoCamera>,
-    keyboard: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
     game_state: Res<GameState>,
     time: Res<Time>,
 ) {
@@ -459,13 +1189,13 @@ fn rotate_keyboard(
     }
 
     let mut delta: f32 = 0.0;
-    if keyboard.pressed(KeyCode::KeyQ) {
+    if actions.pressed(Action::RotateLeft) {
         delta += 1.0;
     }
-    if keyboard.pressed(KeyCode::KeyE) {
+    if actions.pressed(Action::RotateRight) {
         delta -= 1.0;
     }
-    if keyboard.pressed(KeyCode::ShiftLeft) {
+    if actions.pressed(Action::Faster) {
         delta *= 10.0;
     }
 
@@ -480,9 +1210,10 @@ fn rotate_keyboard(
 fn rotate_mouse(
     mut query: Query<&mut IsoCamera>,
     mut motion_evr: MessageReader<MouseMotion>,
-    mouse: Res<ButtonInput<MouseButton>>,
+    actions: Actions,
+    settings: Res<ViewSettings>,
 ) {
-    if !mouse.pressed(MouseButton::Right) {
+    if !actions.pressed(Action::RotateDrag) {
         return;
     }
 
@@ -501,6 +1232,54 @@ fn rotate_mouse(
         cam.vertical_angle += motion.y * 0.1;
         cam.horizontal_angle += motion.x * 0.1;
 
-        cam.vertical_angle = cam.vertical_angle.clamp(0.0, 90.0);
+        cam.vertical_angle = cam.vertical_angle.clamp(0.0, settings.mode.max_vertical_angle());
+    }
+}
+
+// Pixel-perfect mode. Zoom snaps to whole pixels per stud, measured across
+// the screen along a ground axis as the preset's heading foreshortens it (so
+// 2:1 dimetric lines stay clean), or to a half, a quarter... of a pixel once
+// a stud is smaller than one. The scale the zoom controls asked for is kept
+// aside (as `raw`) so small scroll steps still add up instead of snapping
+// back. The camera then slides within its
+// view plane onto the pixel grid, leaving `IsoCamera::target` alone so slow
+// pans aren't rounded away.
+fn snap_to_pixels(
+    settings: Res<ViewSettings>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cam_query: Query<(&mut Transform, &mut Projection), With<IsoCamera>>,
+    mut raw: Local<Option<(f32, f32)>>,
+) {
+    if !settings.pixel_perfect {
+        *raw = None;
+        return;
+    }
+    let Ok(window) = windows.single() else { return; };
+    let Ok((mut transform, mut projection)) = cam_query.single_mut() else { return; };
+    let Projection::Orthographic(ortho) = projection.as_mut() else { return; };
+    let height = window.physical_height().max(1) as f32;
+
+    // (unsnapped, last snapped): scale the raw value by whatever zoom did since
+    let raw_scale = match *raw {
+        Some((raw_scale, snapped)) => raw_scale * ortho.scale / snapped,
+        None => ortho.scale,
+    };
+    let heading = settings.preset.heading().to_radians();
+    let stud = STUD * heading.cos().abs().max(heading.sin().abs());
+    let stud_pixels = stud * height / (ISO_SCALING_MODE * raw_scale);
+    let stud_pixels = if stud_pixels >= 1.0 { stud_pixels.round() } else { stud_pixels.log2().round().exp2() };
+    let scale = stud * height / (ISO_SCALING_MODE * stud_pixels);
+    if ortho.scale != scale {
+        ortho.scale = scale;
+    }
+    *raw = Some((raw_scale, scale));
+
+    let pixel = ISO_SCALING_MODE * scale / height;
+    let (right, up) = (*transform.right(), *transform.up());
+    let x = transform.translation.dot(right);
+    let y = transform.translation.dot(up);
+    let offset = right * ((x / pixel).round() * pixel - x) + up * ((y / pixel).round() * pixel - y);
+    if offset != Vec3::ZERO {
+        transform.translation += offset;
     }
 }
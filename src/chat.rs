@@ -1,13 +1,30 @@
-use std::time::Duration;
+use std::{collections::VecDeque, fmt::{self, Write as _}, fs::{self, OpenOptions}, io::Write as _, path::{Path, PathBuf}, sync::{mpsc, Mutex}, time::Duration};
 
-use bevy::{ecs::system::SystemParam, input::{keyboard::KeyboardInput, ButtonState}, prelude::*};
+use bevy::{
+    ecs::entity_disabling::Disabled,
+    input::{keyboard::KeyboardInput, ButtonState},
+    log::{tracing::{field::{Field, Visit}, Event, Subscriber}, tracing_subscriber::{layer::Context, Layer}, BoxedLayer, Level},
+    prelude::*,
+};
 
-use crate::{asset_loader::SceneAssets, components::Light, lit::Sun, state::{BVHView, BrickInfoEnabled, BuildLoaded, GameState, InputState}, ChunkMesh, Ground, SaveBVH, Water};
+use crate::{asset_loader::SceneAssets, components::Light, console::{on_off, run_command, set_visible, AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand, ConsoleCommands}, controls::{Action, Actions}, state::{BVHView, BrickInfoEnabled, BuildLoaded, GameState, InputState}, config_directory, queue_load, ChunkMesh, Ground, SaveBVH, Water};
 
 pub struct ChatPlugin;
 
 /// Max log lines kept in the scrollback before oldest are dropped.
 const MAX_LOG_LINES: usize = 200;
+/// Max lines kept for `/log save`, well past what's on screen.
+const MAX_TRANSCRIPT_LINES: usize = 10_000;
+/// Max commands kept in history, in memory and in the history file.
+const MAX_HISTORY: usize = 1000;
+/// History file in the config directory, one command per line.
+const HISTORY_FILE: &str = "history.txt";
+/// Levels `/log mirror` takes, quietest first. Nothing finer than info gets
+/// past the log filter.
+const MIRROR_LEVELS: [&str; 4] = ["off", "error", "warn", "info"];
+/// Log target for lines that are already in the console, like a script's
+/// errors, so mirroring doesn't show them twice.
+pub const ECHOED: &str = "console";
 
 // Palette.
 const COLOR_ACCENT: Color = Color::srgb(0.45, 0.85, 0.55);
@@ -41,19 +58,34 @@ struct ConsoleState {
     browse: Option<usize>,
     /// In-progress line stashed when the user starts browsing history.
     stash: String,
+    /// Ctrl+R search through history, while it's open.
+    search: Option<HistorySearch>,
+    /// Everything printed to the scrollback, oldest first, for `/log save`.
+    transcript: VecDeque<String>,
+    /// Most verbose log level copied into the scrollback. `None` = off.
+    mirror: Option<Level>,
 }
 
-/// Scene entities a command may touch, grouped to stay under the system-param limit.
-#[derive(SystemParam)]
-struct SceneQueries<'w, 's> {
-    mesh: Query<'w, 's, Entity, With<ChunkMesh>>,
-    lights: Query<'w, 's, (Entity, &'static mut Visibility), (With<Light>, Without<Water>, Without<Console>)>,
-    bvh: Query<'w, 's, Entity, With<SaveBVH>>,
-    water: Query<'w, 's, &'static mut Visibility, (With<Water>, Without<Console>)>,
-    ground: Query<'w, 's, &'static mut Visibility, (With<Ground>, Without<Console>, Without<Water>, Without<Light>)>,
-    sun: Query<'w, 's, &'static mut DirectionalLight, With<Sun>>,
+#[derive(Default)]
+struct HistorySearch {
+    query: String,
+    /// History index of the current match.
+    found: Option<usize>,
 }
 
+impl ConsoleState {
+    fn record(&mut self, line: &str) {
+        if self.transcript.len() >= MAX_TRANSCRIPT_LINES {
+            self.transcript.pop_front();
+        }
+        self.transcript.push_back(line.to_string());
+    }
+}
+
+/// Log output on its way from the tracing layer to the scrollback.
+#[derive(Resource)]
+struct MirroredLog(Mutex<mpsc::Receiver<(Level, String)>>);
+
 #[derive(Resource)]
 struct Timers {
     tenth_second: Timer,
@@ -68,8 +100,21 @@ impl Plugin for ChatPlugin {
                 half_second: Timer::new(Duration::from_millis(500), TimerMode::Repeating),
             })
             .init_resource::<ConsoleState>()
-            .add_systems(Startup, spawn_chat)
-            .add_systems(Update, (blink_cursor, keyboard_system, trim_log))
+            .add_console_command(ConsoleCommand::new("/help", "list commands, or the ones named like this").arg(Arg::words("command", &[]).optional()), help_command)
+            .add_console_command(ConsoleCommand::new("/load", "load a build, as if picked with L").arg(Arg::path("path")), load_command)
+            .add_console_command(ConsoleCommand::new("/clear", "remove all bricks").alias("/clearbricks").alias("/clearallbricks"), clear_command)
+            .add_console_command(ConsoleCommand::new("/water", "toggle the water plane, or raise it to a height").arg(Arg::word("height", &["on", "off"]).optional()), water_command)
+            .add_console_command(ConsoleCommand::new("/ground", "toggle the ground plane").arg(Arg::switch()), ground_command)
+            .add_console_command(ConsoleCommand::new("/bvh", "toggle the bvh view").arg(Arg::switch()), bvh_command)
+            .add_console_command(ConsoleCommand::new("/brickinfo", "toggle brick hover info").arg(Arg::switch()), brick_info_command)
+            .add_console_command(ConsoleCommand::new("/debuglights", "toggle light debug gizmos").alias("/lightdebug").arg(Arg::switch()), light_debug_command)
+            .add_console_command(ConsoleCommand::new("/log save", "write the scrollback to a file").arg(Arg::path("path")).local_only(), log_save_command)
+            .add_console_command(
+                ConsoleCommand::new("/log mirror", "copy log output into the console, down to a level").arg(Arg::choice("level", &MIRROR_LEVELS).optional()),
+                log_mirror_command,
+            )
+            .add_systems(Startup, (spawn_chat, load_history))
+            .add_systems(Update, (blink_cursor, keyboard_system, trim_log, mirror_log))
             .add_systems(Update, enable_chat.after(keyboard_system));
     }
 }
@@ -118,7 +163,7 @@ fn spawn_chat(
             ConsoleLog,
         )).with_children(|log| {
             log.spawn((
-                Text::new("brs console — type /help for commands"),
+                Text::new("brs console — type /help for commands, Tab completes"),
                 text_font(),
                 TextColor(COLOR_INFO),
                 ConsoleLogLine,
@@ -175,11 +220,11 @@ fn blink_cursor(
 fn enable_chat(
     mut query: Query<&mut Visibility, With<Console>>,
     mut game_state: ResMut<GameState>,
-    keyboard: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
 ) {
     match game_state.input {
         InputState::Listen => {
-            if !keyboard.just_pressed(KeyCode::Slash) {
+            if !actions.just_pressed(Action::OpenConsole) {
                 return;
             }
         },
@@ -216,11 +261,9 @@ fn keyboard_system(
     mut rd: MessageReader<KeyboardInput>,
     mut game_state: ResMut<GameState>,
     mut console_state: ResMut<ConsoleState>,
-    mut build_loaded: ResMut<BuildLoaded>,
-    mut brick_info_enabled: ResMut<BrickInfoEnabled>,
+    registry: Res<ConsoleCommands>,
+    keyboard: Res<ButtonInput<KeyCode>>,
     mut commands: Commands,
-    mut scene: SceneQueries,
-    assets: Res<SceneAssets>,
 ) {
     if game_state.input_listening() || game_state.is_changed() {
         return;
@@ -228,11 +271,15 @@ fn keyboard_system(
 
     let Ok(entity) = chat_query.single() else { return; };
     let Ok(log_entity) = log_query.single() else { return; };
+    let ctrl = keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
 
     for ev in rd.read() {
         if ev.state != ButtonState::Pressed {
             continue;
         }
+        if search_key(ev, ctrl, &mut writer, entity, &mut console_state) {
+            continue;
+        }
         match ev.key_code {
             KeyCode::Escape => {
                 game_state.input = InputState::Listen;
@@ -264,11 +311,38 @@ fn keyboard_system(
             KeyCode::ArrowDown => {
                 recall_history(&mut writer, entity, &mut console_state, 1);
             },
+            KeyCode::Tab => {
+                // Complete the word before the cursor: all the way if there's
+                // one candidate, as far as they agree if there are several,
+                // and list them when they already agree that far.
+                let before = writer.text(entity, 1).clone();
+                let candidates = registry.complete(&before);
+                let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+                let completed = match candidates.as_slice() {
+                    [] => continue,
+                    [only] if only.ends_with('/') => only.clone(),
+                    [only] => format!("{only} "),
+                    _ => common_prefix(&candidates),
+                };
+                if completed.len() > before.len() - start {
+                    *writer.text(entity, 1) = format!("{}{completed}", &before[..start]);
+                    console_state.browse = None;
+                } else {
+                    let listing = candidates.join("  ");
+                    console_state.record(&listing);
+                    commands.entity(log_entity).with_child((
+                        Text::new(listing),
+                        text_font(),
+                        TextColor(COLOR_ECHO),
+                        ConsoleLogLine,
+                    ));
+                }
+            },
             KeyCode::Enter => {
                 let before = writer.text(entity, 1).clone();
                 let after = writer.text(entity, 3).clone();
                 let command = format!("{before}{after}");
-                let command = command.trim();
+                let command = command.trim().to_string();
 
                 writer.text(entity, 1).clear();
                 writer.text(entity, 3).clear();
@@ -278,98 +352,18 @@ fn keyboard_system(
                     continue;
                 }
 
-                console_state.history.push(command.to_string());
-
-                let mut feedback: Vec<(String, Color)> = Vec::new();
-                match command {
-                    "/clear" | "/clearbricks" | "/clearallbricks" => {
-                        commands.spawn((
-                            AudioPlayer::new(assets.sounds.clear_bricks.clone()),
-                            PlaybackSettings::DESPAWN,
-                        ));
-                        for entity in scene.mesh.iter() {
-                            commands.entity(entity).despawn();
-                        }
-                        for (entity, _) in scene.lights.iter() {
-                            commands.entity(entity).despawn();
-                        }
-                        for entity in scene.bvh.iter() {
-                            commands.entity(entity).despawn();
-                        }
-                        build_loaded.0 = false;
-                        feedback.push(("cleared all bricks".into(), COLOR_INFO));
-                    },
-                    "/water" => {
-                        if let Ok(mut visibility) = scene.water.single_mut() {
-                            let on = toggle_visibility(&mut visibility);
-                            feedback.push((format!("water {}", on_off(on)), COLOR_INFO));
-                        }
-                    },
-                    "/ground" => {
-                        if let Ok(mut visibility) = scene.ground.single_mut() {
-                            let on = toggle_visibility(&mut visibility);
-                            feedback.push((format!("ground {}", on_off(on)), COLOR_INFO));
-                        }
-                    },
-                    "/bvh" => {
-                        match game_state.bvh_view {
-                            BVHView::Off => {
-                                game_state.bvh_view = BVHView::On(0);
-                                feedback.push(("bvh view on".into(), COLOR_INFO));
-                            },
-                            BVHView::On(_) => {
-                                game_state.bvh_view = BVHView::Off;
-                                feedback.push(("bvh view off".into(), COLOR_INFO));
-                            }
-                        }
-                    },
-                    "/shadows" => {
-                        if let Ok(mut sun) = scene.sun.single_mut() {
-                            sun.shadow_maps_enabled = !sun.shadow_maps_enabled;
-                            feedback.push((format!("shadows {}", on_off(sun.shadow_maps_enabled)), COLOR_INFO));
-                        }
-                    },
-                    "/lights" => {
-                        let mut on = false;
-                        for (_, mut visibility) in scene.lights.iter_mut() {
-                            on = toggle_visibility(&mut visibility);
-                        }
-                        feedback.push((format!("lights {}", on_off(on)), COLOR_INFO));
-                    },
-                    "/debuglights" | "/lightdebug" => {
-                        game_state.light_debug = !game_state.light_debug;
-                        feedback.push((format!("light debug {}", on_off(game_state.light_debug)), COLOR_INFO));
-                    }
-                    "/brickinfo" => {
-                        brick_info_enabled.0 = !brick_info_enabled.0;
-                        feedback.push((format!("brick info {}", on_off(brick_info_enabled.0)), COLOR_INFO));
-                    }
-                    "/help" => {
-                        for line in HELP_LINES {
-                            feedback.push((line.to_string(), COLOR_INFO));
-                        }
-                    }
-                    _ => {
-                        feedback.push((format!("unknown command: {command} (try /help)"), COLOR_ERROR));
-                    }
+                console_state.history.push(command.clone());
+                if console_state.history.len() > MAX_HISTORY {
+                    console_state.history.remove(0);
                 }
-
-                // Echo the command, then its output, into the scrollback.
-                commands.entity(log_entity).with_children(|log| {
-                    log.spawn((
-                        Text::new(format!("> {command}")),
-                        text_font(),
-                        TextColor(COLOR_ECHO),
-                        ConsoleLogLine,
-                    ));
-                    for (line, color) in feedback {
-                        log.spawn((
-                            Text::new(line),
-                            text_font(),
-                            TextColor(color),
-                            ConsoleLogLine,
-                        ));
-                    }
+                save_history_line(&command);
+
+                // Run it once this system is done, echoed into the scrollback
+                // with its output underneath.
+                commands.queue(move |world: &mut World| {
+                    print_echo(world, &command);
+                    let result = run_command(world, &command);
+                    print_result(world, result);
                 });
             },
             _ => {
@@ -388,17 +382,294 @@ fn keyboard_system(
     }
 }
 
-const HELP_LINES: &[&str] = &[
-    "/clear         remove all bricks",
-    "/water         toggle water plane",
-    "/ground        toggle ground plane",
-    "/lights        toggle brick lights",
-    "/shadows       toggle sun shadows",
-    "/bvh           toggle bvh view",
-    "/brickinfo     toggle brick hover info",
-    "/debuglights   toggle light debug gizmos",
-    "/help          show this list",
-];
+/// Echo a command into the scrollback, as typed.
+pub fn print_echo(world: &mut World, command: &str) {
+    print(world, &format!("> {command}"), COLOR_ECHO);
+}
+
+/// Print a command's output, a line at a time, in info or error colors.
+pub fn print_result(world: &mut World, result: CommandResult) {
+    match result {
+        Ok(text) => print(world, &text, COLOR_INFO),
+        Err(text) => print(world, &text, COLOR_ERROR),
+    }
+}
+
+fn print(world: &mut World, text: &str, color: Color) {
+    let mut state = world.resource_mut::<ConsoleState>();
+    for line in text.lines() {
+        state.record(line);
+    }
+    let Ok(log_entity) = world.query_filtered::<Entity, With<ConsoleLog>>().single(world) else { return; };
+    world.entity_mut(log_entity).with_children(|log| {
+        for line in text.lines() {
+            log.spawn((
+                Text::new(line),
+                text_font(),
+                TextColor(color),
+                ConsoleLogLine,
+            ));
+        }
+    });
+}
+
+/// Ctrl+R search through history, typed into the prompt. Returns whether it
+/// took the key. Keys it doesn't take end the search with the match left in
+/// the input and then act as usual, so Enter runs it; Esc just ends it.
+fn search_key(
+    ev: &KeyboardInput,
+    ctrl: bool,
+    writer: &mut TextUiWriter,
+    entity: Entity,
+    state: &mut ConsoleState,
+) -> bool {
+    let Some(search) = state.search.as_mut() else {
+        if !(ctrl && ev.key_code == KeyCode::KeyR) {
+            return false;
+        }
+        state.search = Some(HistorySearch::default());
+        state.browse = None;
+        *writer.text(entity, 0) = "(search '') ".into();
+        return true;
+    };
+    let history = &state.history;
+    // where to look back from: Ctrl+R steps past the current match, typing
+    // narrows it from where it is
+    let before = match ev.key_code {
+        KeyCode::KeyR if ctrl => search.found.unwrap_or(history.len()),
+        KeyCode::Backspace => {
+            search.query.pop();
+            history.len()
+        },
+        KeyCode::Escape => {
+            end_search(writer, entity, state);
+            return true;
+        },
+        KeyCode::Enter | KeyCode::Tab | KeyCode::ArrowLeft | KeyCode::ArrowRight | KeyCode::ArrowUp | KeyCode::ArrowDown => {
+            end_search(writer, entity, state);
+            return false;
+        },
+        _ => {
+            // modifiers and the like
+            let Some(text) = ev.text.as_ref().filter(|_| !ctrl) else { return true; };
+            search.query.extend(text.chars().filter(|c| !c.is_control()));
+            search.found.map_or(history.len(), |i| i + 1)
+        },
+    };
+    let found = history[..before].iter().rposition(|line| line.contains(search.query.as_str()));
+    if let Some(i) = found {
+        search.found = Some(i);
+        set_input(writer, entity, &history[i]);
+    }
+    let failed = if found.is_some() { "" } else { "failed " };
+    *writer.text(entity, 0) = format!("({failed}search '{}') ", search.query);
+    true
+}
+
+fn end_search(writer: &mut TextUiWriter, entity: Entity, state: &mut ConsoleState) {
+    state.search = None;
+    *writer.text(entity, 0) = "> ".into();
+}
+
+fn history_path() -> Option<PathBuf> {
+    config_directory().map(|directory| directory.join(HISTORY_FILE))
+}
+
+/// Pick up the history earlier runs left, trimming the file once it's
+/// grown past the cap.
+fn load_history(
+    mut console_state: ResMut<ConsoleState>,
+) {
+    let Some(path) = history_path() else { return; };
+    let Ok(text) = fs::read_to_string(&path) else { return; };
+    let lines: Vec<String> = text.lines().filter(|line| !line.trim().is_empty()).map(String::from).collect();
+    let excess = lines.len().saturating_sub(MAX_HISTORY);
+    console_state.history = lines[excess..].to_vec();
+    if excess > 0 {
+        let mut text = console_state.history.join("\n");
+        text.push('\n');
+        if let Err(e) = fs::write(&path, text) {
+            warn!("Cannot trim {}: {e}", path.display());
+        }
+    }
+}
+
+/// Add a submitted command to the history file.
+fn save_history_line(command: &str) {
+    let Some(path) = history_path() else { return; };
+    let result = path.parent().map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(&path))
+        .and_then(|mut file| writeln!(file, "{command}"));
+    if let Err(e) = result {
+        warn!("Cannot save history to {}: {e}", path.display());
+    }
+}
+
+/// The longest start every candidate shares.
+fn common_prefix(candidates: &[String]) -> String {
+    let Some((first, rest)) = candidates.split_first() else { return String::new(); };
+    let mut len = first.len();
+    for candidate in rest {
+        len = first.char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .map(|((i, a), _)| i + a.len_utf8())
+            .last()
+            .unwrap_or(0)
+            .min(len);
+    }
+    first[..len].to_string()
+}
+
+/// `/help`, generated from the registry: every command, or the ones whose
+/// name contains the words given.
+fn help_command(
+    In(args): In<CommandArgs>,
+    registry: Res<ConsoleCommands>,
+) -> CommandResult {
+    let filter = args.words("command");
+    let mut matching: Vec<&ConsoleCommand> = registry.iter()
+        .filter(|command| filter.iter().all(|word| command.name().contains(word.trim_start_matches('/'))))
+        .collect();
+    if matching.is_empty() {
+        return Err(format!("no command matching {} (try /help)", filter.join(" ")));
+    }
+    matching.sort_by_key(|command| command.name());
+    let lines: Vec<String> = matching.iter()
+        .map(|command| format!("{:<24} {}", command.usage(), command.help()))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+// Commands for the scene itself, which has no plugin of its own.
+
+fn load_command(
+    In(args): In<CommandArgs>,
+    world: &mut World,
+) -> CommandResult {
+    let path = args.path("path").map(Path::to_path_buf).unwrap_or_default();
+    if !path.is_file() {
+        return Err(format!("no such file: {}", path.display()));
+    }
+    let message = format!("loading {}", path.display());
+    queue_load(world, path);
+    Ok(message)
+}
+
+fn clear_command(
+    In(_): In<CommandArgs>,
+    mut commands: Commands,
+    mesh_query: Query<Entity, With<ChunkMesh>>,
+    // `Has<Disabled>` opts in to lights whose brick was edited away
+    light_query: Query<(Entity, Has<Disabled>), With<Light>>,
+    bvh_query: Query<Entity, With<SaveBVH>>,
+    mut build_loaded: ResMut<BuildLoaded>,
+    assets: Res<SceneAssets>,
+) -> CommandResult {
+    commands.spawn((
+        AudioPlayer::new(assets.sounds.clear_bricks.clone()),
+        PlaybackSettings::DESPAWN,
+    ));
+    for entity in mesh_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for (entity, _) in light_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for entity in bvh_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    build_loaded.0 = false;
+    Ok("cleared all bricks".into())
+}
+
+fn water_command(
+    In(args): In<CommandArgs>,
+    mut water_query: Query<(&mut Visibility, &mut Transform), With<Water>>,
+) -> CommandResult {
+    let (mut visibility, mut transform) = water_query.single_mut().map_err(|_| "no water plane")?;
+    let on = match args.word("height") {
+        None => *visibility != Visibility::Visible,
+        Some("on") => true,
+        Some("off") => false,
+        Some(height) => {
+            let height: f32 = height.parse().ok().filter(|height: &f32| height.is_finite())
+                .ok_or_else(|| format!("height: expected a number, not {height}"))?;
+            transform.translation.y = height;
+            set_visible(&mut visibility, true);
+            return Ok(format!("water at {height}"));
+        },
+    };
+    Ok(format!("water {}", on_off(set_visible(&mut visibility, on))))
+}
+
+fn ground_command(
+    In(args): In<CommandArgs>,
+    mut ground_query: Query<&mut Visibility, With<Ground>>,
+) -> CommandResult {
+    let mut visibility = ground_query.single_mut().map_err(|_| "no ground plane")?;
+    let on = args.switch(*visibility == Visibility::Visible);
+    Ok(format!("ground {}", on_off(set_visible(&mut visibility, on))))
+}
+
+fn bvh_command(
+    In(args): In<CommandArgs>,
+    mut game_state: ResMut<GameState>,
+) -> CommandResult {
+    let on = args.switch(matches!(game_state.bvh_view, BVHView::On(_)));
+    // keep the depth being shown when it's already on
+    if !on {
+        game_state.bvh_view = BVHView::Off;
+    } else if matches!(game_state.bvh_view, BVHView::Off) {
+        game_state.bvh_view = BVHView::On(0);
+    }
+    Ok(format!("bvh view {}", on_off(on)))
+}
+
+fn brick_info_command(
+    In(args): In<CommandArgs>,
+    mut brick_info_enabled: ResMut<BrickInfoEnabled>,
+) -> CommandResult {
+    brick_info_enabled.0 = args.switch(brick_info_enabled.0);
+    Ok(format!("brick info {}", on_off(brick_info_enabled.0)))
+}
+
+fn log_save_command(
+    In(args): In<CommandArgs>,
+    console_state: Res<ConsoleState>,
+) -> CommandResult {
+    let path = args.path("path").unwrap_or(Path::new(""));
+    let mut text = String::new();
+    for line in &console_state.transcript {
+        text.push_str(line);
+        text.push('\n');
+    }
+    fs::write(path, text).map_err(|e| format!("couldn't write {}: {e}", path.display()))?;
+    Ok(format!("saved {} lines to {}", console_state.transcript.len(), path.display()))
+}
+
+fn log_mirror_command(
+    In(args): In<CommandArgs>,
+    mut console_state: ResMut<ConsoleState>,
+) -> CommandResult {
+    match args.word("level") {
+        None => {},
+        Some("off") => console_state.mirror = None,
+        Some(level) => console_state.mirror = level.parse().ok(),
+    }
+    Ok(match console_state.mirror {
+        Some(level) => format!("mirroring log output down to {}", level.as_str().to_lowercase()),
+        None => "log mirror off".into(),
+    })
+}
+
+fn light_debug_command(
+    In(args): In<CommandArgs>,
+    mut game_state: ResMut<GameState>,
+) -> CommandResult {
+    game_state.light_debug = args.switch(game_state.light_debug);
+    Ok(format!("light debug {}", on_off(game_state.light_debug)))
+}
 
 /// Step through command history. `dir` is -1 for older, +1 for newer.
 fn recall_history(
@@ -448,15 +719,59 @@ fn set_input(writer: &mut TextUiWriter, entity: Entity, value: &str) {
     writer.text(entity, 3).clear();
 }
 
-fn on_off(on: bool) -> &'static str {
-    if on { "on" } else { "off" }
+/// For `LogPlugin::custom_layer`: a tracing layer that hands every event to
+/// the console, which shows the ones `/log mirror` asks for.
+pub fn log_layer(app: &mut App) -> Option<BoxedLayer> {
+    let (tx, rx) = mpsc::channel();
+    app.insert_resource(MirroredLog(Mutex::new(rx)));
+    Some(Box::new(ConsoleLayer(tx)))
 }
 
-/// Toggle visibility; returns true if now visible.
-fn toggle_visibility(visibility: &mut Visibility) -> bool {
-    *visibility = match *visibility {
-        Visibility::Visible => Visibility::Hidden,
-        _ => Visibility::Visible,
-    };
-    matches!(*visibility, Visibility::Visible)
+struct ConsoleLayer(mpsc::Sender<(Level, String)>);
+
+impl<S: Subscriber> Layer<S> for ConsoleLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() == ECHOED {
+            return;
+        }
+        let mut line = LogLine::default();
+        event.record(&mut line);
+        // the receiver only goes away on exit
+        let _ = self.0.send((*event.metadata().level(), line.0));
+    }
+}
+
+/// An event's message, then its other fields as `name=value`.
+#[derive(Default)]
+struct LogLine(String);
+
+impl Visit for LogLine {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{value:?}"));
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+/// Copy log output into the scrollback, warnings and errors in the error
+/// color, down to the level `/log mirror` set.
+fn mirror_log(world: &mut World) {
+    let Some(log) = world.get_resource::<MirroredLog>() else { return; };
+    // drained when off too, so nothing piles up
+    let lines: Vec<(Level, String)> = log.0.lock().map(|rx| rx.try_iter().collect()).unwrap_or_default();
+    let Some(mirror) = world.resource::<ConsoleState>().mirror else { return; };
+    for (level, line) in lines {
+        // more verbose levels compare greater
+        if level > mirror {
+            continue;
+        }
+        let color = match level {
+            Level::ERROR | Level::WARN => COLOR_ERROR,
+            Level::INFO => COLOR_INFO,
+            _ => COLOR_ECHO,
+        };
+        print(world, &format!("{}: {line}", level.as_str().to_lowercase()), color);
+    }
 }
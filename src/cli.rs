@@ -0,0 +1,382 @@
+//! Window-less subcommands for scripts and build pipelines. Each one reads a
+//! save through the same `load_save_data` path the viewer uses and exits
+//! without ever creating a window:
+//!
+//! - `info <save>` prints a JSON summary: counts, bounds and histograms.
+//! - `convert <input> <output>` rewrites a save as `.brs` or `.brz`.
+//! - `dump <save>` writes one record per brick as JSON lines or CSV.
+//! - `bench <save>` times the BVH build and face culling for each split
+//!   strategy.
+//!
+//! `poster <save> <size>` and `animate <save> <kind>` are the exceptions:
+//! they need the renderer, so they come back from `viewer_job` for the viewer
+//! to run before quitting. `--exec <script>` and `--remote <port>` are pulled
+//! out first by `split_options`; the viewer runs those console scripts once
+//! it starts, and serves the remote control on that port.
+//!
+//! Positions, sizes and bounds are reported in Brickadia's own axes (Z up),
+//! not the renderer's swizzled Y-up coordinates.
+
+use std::{collections::BTreeMap, error::Error, fs::File, io::{self, BufWriter, Write}, path::{Path, PathBuf}, time::{Duration, Instant}};
+
+use bevy::math::IVec3;
+use brickadia::{save::{Brick, SaveData, Size, UnrealType}, util::BRICK_SIZE_MAP};
+use serde_json::{json, Map, Value};
+
+use crate::{aabb::AABB, brdb_load, bvh::{gen_aabbs, BVHMeshGenerator, BVHSplit}, load_save_data, save_extension, anim::AnimationRequest, job::{JobRender, RenderJob}, poster::PosterRequest, write_save_data, utils::{asset_name, brick_color, hex_color, material_name, owner_name}};
+
+type DynError = Box<dyn Error>;
+
+const USAGE: &str = "\
+usage: brs_iso [--exec <script>]... [--remote <port>] [command] [args]
+
+With no command, opens the viewer.
+
+options:
+  --exec <script>                          run a file of console commands once the viewer
+                                           starts, after autoexec.cfg in the config directory
+  --remote <port>                          serve the JSON-RPC remote control on 127.0.0.1
+
+commands:
+  info <save>                              print a JSON summary of a save
+  convert <input> <output>                 convert to .brs or .brz, picked by extension
+  dump <save> [--csv] [--out <file>]       write every brick as JSON lines (default) or CSV
+  bench <save> [--runs <n>]                compare median and SAH BVH builds on culling time
+  poster <save> <w>x<h>[px|in|cm] [<dpi>dpi] [<out.png>]
+                                           open the save, frame it and render a tiled poster
+  animate <save> <turntable|sun|views|construction|stills> [<seconds>s] [<fps>fps] [<w>x<h>] [<dir>]
+                                           render numbered PNG frames of a turntable, a sun
+                                           sweep, a flight through the saved views or the
+                                           build going up brick by brick; stills writes one
+                                           image per saved view, named after it
+  help                                     show this message";
+
+/// Options for the viewer itself, which can come before any command.
+#[derive(Debug, Default)]
+pub struct ViewerOptions {
+    /// `--exec <script>`s, in order.
+    pub scripts: Vec<PathBuf>,
+    /// `--remote <port>`: serve the remote control on localhost.
+    pub remote: Option<u16>,
+}
+
+/// Pull every `--exec <script>` and `--remote <port>` out of the arguments,
+/// leaving the rest.
+pub fn split_options(args: Vec<String>) -> Result<(Vec<String>, ViewerOptions), String> {
+    let mut rest = Vec::new();
+    let mut options = ViewerOptions::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--exec" => options.scripts.push(PathBuf::from(args.next().ok_or("--exec needs a script file")?)),
+            "--remote" => {
+                let port = args.next().ok_or("--remote needs a port")?;
+                options.remote = Some(port.parse().map_err(|_| format!("--remote: {port} isn't a port"))?);
+            },
+            _ => rest.push(arg),
+        }
+    }
+    Ok((rest, options))
+}
+
+/// The `poster` or `animate` job, if that's the command. It opens the viewer,
+/// so it's checked before `run`.
+pub fn viewer_job(args: &[String]) -> Option<Result<RenderJob, String>> {
+    let (command, rest) = args.split_first()?;
+    if command != "poster" && command != "animate" {
+        return None;
+    }
+    let Some((save, rest)) = rest.split_first() else {
+        return Some(Err(USAGE.into()));
+    };
+    let rest: Vec<&str> = rest.iter().map(String::as_str).collect();
+    let render = match command.as_str() {
+        "poster" => PosterRequest::parse(&rest).map(JobRender::Poster),
+        _ => AnimationRequest::parse(&rest).map(JobRender::Animation),
+    };
+    Some(render.map(|render| RenderJob { save: PathBuf::from(save), render }))
+}
+
+/// Run a subcommand if one was given. Returns the process exit code, or `None`
+/// when there are no arguments and the viewer should start.
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    let result = match command.as_str() {
+        "info" => info(rest),
+        "convert" => convert(rest),
+        "dump" => dump(rest),
+        "bench" => bench(rest),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => {
+            eprintln!("unknown command: {command}\n\n{USAGE}");
+            return Some(2);
+        }
+    };
+
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("error: {e}");
+            Some(1)
+        }
+    }
+}
+
+fn info(args: &[String]) -> Result<(), DynError> {
+    let [path] = args else {
+        return Err("usage: brs_iso info <save>".into());
+    };
+    let save_data = load_save_data(Path::new(path))?;
+
+    let mut assets: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut materials: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut owners: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut colors: BTreeMap<String, usize> = BTreeMap::new();
+    for brick in &save_data.bricks {
+        *assets.entry(asset_name(&save_data, brick)).or_default() += 1;
+        *materials.entry(material_name(&save_data, brick)).or_default() += 1;
+        *owners.entry(owner_name(&save_data, brick)).or_default() += 1;
+        *colors.entry(hex_color(brick_color(&save_data, brick))).or_default() += 1;
+    }
+
+    let bounds = AABB::bounds(&gen_aabbs(&save_data)).map(|aabb| json!({
+        "min": save_axes(aabb.min()),
+        "max": save_axes(aabb.max()),
+    }));
+
+    let components: BTreeMap<&str, usize> = save_data.components.iter()
+        .map(|(name, component)| (name.as_str(), component.brick_indices.len()))
+        .collect();
+    let point_lights = components.get("BCD_PointLight").copied().unwrap_or(0);
+    let spot_lights = components.get("BCD_SpotLight").copied().unwrap_or(0);
+
+    let summary = json!({
+        "path": path,
+        "bricks": save_data.bricks.len(),
+        "bounds": bounds,
+        "lights": {
+            "point": point_lights,
+            "spot": spot_lights,
+            "total": point_lights + spot_lights,
+        },
+        "assets": assets,
+        "materials": materials,
+        "owners": owners,
+        "colors": colors,
+        "components": components,
+    });
+    println!("{}", serde_json::to_string_pretty(&summary)?);
+    Ok(())
+}
+
+fn convert(args: &[String]) -> Result<(), DynError> {
+    let [input, output] = args else {
+        return Err("usage: brs_iso convert <input> <output.brs|output.brz>".into());
+    };
+    let input = Path::new(input);
+    let save_data = load_save_data(input)?;
+    let output = Path::new(output);
+    let bricks = save_data.bricks.len();
+    for loss in conversion_losses(&save_data, input, output) {
+        eprintln!("warning: {loss}");
+    }
+    write_save_data(save_data, output)?;
+
+    eprintln!("wrote {bricks} bricks to {}", output.display());
+    Ok(())
+}
+
+/// What converting `input` to `output` leaves behind: owners the `.brdb`
+/// reader doesn't read, and components the `.brz` writer doesn't write.
+fn conversion_losses(save_data: &SaveData, input: &Path, output: &Path) -> Vec<String> {
+    let mut losses = Vec::new();
+    let input_extension = save_extension(input);
+    if matches!(input_extension.as_str(), "brdb" | "brz") {
+        losses.push(format!("owners aren't read from .{input_extension} saves, so every brick is public"));
+    }
+    if save_extension(output) == "brz" {
+        losses.extend(brdb_load::brz_losses(save_data));
+    }
+    losses
+}
+
+fn dump(args: &[String]) -> Result<(), DynError> {
+    const DUMP_USAGE: &str = "usage: brs_iso dump <save> [--csv] [--out <file>]";
+
+    let mut path = None;
+    let mut csv = false;
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--csv" => csv = true,
+            "--jsonl" => csv = false,
+            "--out" | "-o" => out = Some(args.next().ok_or(DUMP_USAGE)?),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(DUMP_USAGE.into()),
+        }
+    }
+    let path = path.ok_or(DUMP_USAGE)?;
+    let save_data = load_save_data(Path::new(path))?;
+
+    let writer: Box<dyn Write> = match out {
+        Some(out) => Box::new(File::create(out)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut writer = BufWriter::new(writer);
+
+    if csv {
+        writeln!(writer, "index,x,y,z,size_x,size_y,size_z,asset,material,color,owner,components")?;
+    }
+
+    for (i, brick) in save_data.bricks.iter().enumerate() {
+        if !csv {
+            writeln!(writer, "{}", brick_record(&save_data, i))?;
+            continue;
+        }
+        let (x, y, z) = brick.position;
+        let size = brick_size(&save_data, brick);
+        let owner = owner_name(&save_data, brick);
+        let color = hex_color(brick_color(&save_data, brick));
+        let mut components: Vec<&str> = brick.components.keys().map(|k| k.as_str()).collect();
+        components.sort_unstable();
+        writeln!(
+            writer,
+            "{i},{x},{y},{z},{},{},{},{},{},{color},{},{}",
+            size[0], size[1], size[2],
+            csv_field(asset_name(&save_data, brick)),
+            csv_field(material_name(&save_data, brick)),
+            csv_field(owner),
+            csv_field(&components.join(";")),
+        )?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// One brick as a JSON object, as `dump` writes it a line at a time.
+pub fn brick_record(save_data: &SaveData, i: usize) -> Value {
+    let brick = &save_data.bricks[i];
+    let (x, y, z) = brick.position;
+    let components: Map<String, Value> = brick.components.iter()
+        .map(|(name, props)| {
+            let props: Map<String, Value> = props.iter()
+                .map(|(key, value)| (key.clone(), unreal_json(value)))
+                .collect();
+            (name.clone(), Value::Object(props))
+        })
+        .collect();
+    json!({
+        "index": i,
+        "position": [x, y, z],
+        "size": brick_size(save_data, brick),
+        "asset": asset_name(save_data, brick),
+        "material": material_name(save_data, brick),
+        "color": hex_color(brick_color(save_data, brick)),
+        "owner": owner_name(save_data, brick),
+        "components": components,
+    })
+}
+
+// Culling is the BVH's heaviest user (one neighbor query per brick), so it's
+// what the split strategies are compared on. Both must hide exactly the same
+// faces; anything else means a query is missing bricks.
+fn bench(args: &[String]) -> Result<(), DynError> {
+    const BENCH_USAGE: &str = "usage: brs_iso bench <save> [--runs <n>]";
+
+    let mut path = None;
+    let mut runs = 3;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" | "-n" => runs = args.next().ok_or(BENCH_USAGE)?.parse()?,
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(BENCH_USAGE.into()),
+        }
+    }
+    let path = path.ok_or(BENCH_USAGE)?;
+    if runs == 0 {
+        return Err("--runs must be at least 1".into());
+    }
+    let mut save_data = load_save_data(Path::new(path))?;
+    let bricks = save_data.bricks.len();
+
+    let mut results = Map::new();
+    let mut hidden_counts = Vec::new();
+    for (name, split) in [("median", BVHSplit::Median), ("sah", BVHSplit::Sah)] {
+        let start = Instant::now();
+        let generator = BVHMeshGenerator::with_split(save_data, split);
+        // includes face and box generation, which is the same for both
+        let build = start.elapsed();
+
+        let mut best = Duration::MAX;
+        let mut hidden_faces = 0;
+        for _ in 0..runs {
+            let start = Instant::now();
+            let hidden_masks = generator.cull_hidden_faces();
+            best = best.min(start.elapsed());
+            hidden_faces = hidden_masks.iter().map(|mask| mask.count_ones() as usize).sum();
+        }
+        hidden_counts.push(hidden_faces);
+
+        results.insert(name.to_string(), json!({
+            "build_seconds": build.as_secs_f64(),
+            "cull_seconds": best.as_secs_f64(),
+            "nodes": generator.bvh.arena.len(),
+            "leaves": generator.bvh.leaf_count(),
+            "depth": generator.bvh.depth(),
+            "hidden_faces": hidden_faces,
+        }));
+        save_data = generator.into_save_data();
+    }
+
+    if hidden_counts.windows(2).any(|pair| pair[0] != pair[1]) {
+        return Err(format!("split strategies disagree on hidden faces: {hidden_counts:?}").into());
+    }
+
+    let summary = json!({
+        "path": path,
+        "bricks": bricks,
+        "runs": runs,
+        "splits": results,
+    });
+    println!("{}", serde_json::to_string_pretty(&summary)?);
+    Ok(())
+}
+
+// Procedural bricks carry their size; basic bricks get it from their asset.
+fn brick_size(save_data: &SaveData, brick: &Brick) -> [u32; 3] {
+    match brick.size {
+        Size::Procedural(x, y, z) => [x, y, z],
+        Size::Empty => BRICK_SIZE_MAP.get(asset_name(save_data, brick))
+            .map(|&(x, y, z)| [x as u32, y as u32, z as u32])
+            .unwrap_or([0, 0, 0]),
+    }
+}
+
+// Renderer coordinates are (x, z, y); swap back to Brickadia's Z-up.
+fn save_axes(v: IVec3) -> [i32; 3] {
+    [v.x, v.z, v.y]
+}
+
+fn unreal_json(value: &UnrealType) -> Value {
+    match value {
+        UnrealType::Boolean(b) => json!(b),
+        UnrealType::Float(f) => json!(f),
+        UnrealType::Color(c) => json!([c.r, c.g, c.b, c.a]),
+        UnrealType::Rotator(roll, pitch, yaw) => json!([roll, pitch, yaw]),
+        other => json!(format!("{other:?}")),
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
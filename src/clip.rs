@@ -0,0 +1,324 @@
+use bevy::{
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    render::render_resource::{AsBindGroup, Face, ShaderType},
+    shader::ShaderRef,
+};
+
+use crate::{aabb::Frustum, asset_loader::SceneAssets, cam::IsoCamera, console::{AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand}, construct::Construction, controls::{Action, Actions}, state::{GameState, Screenshotting}, SaveBVH};
+
+// Section views. A horizontal clip height steps through a build floor by
+// floor (PageUp / PageDown, snapped to plates), and `/clip view` adds planes
+// cut square to the camera. The brick materials discard everything outside
+// the planes in the shader and paint the exposed back faces as a cap.
+//
+// The cap is only as good as the mesh is closed. The chunk mesher drops faces
+// hidden against a neighbour and coincident faces, so where a cut runs
+// through bricks packed together there are no back faces there to paint, and
+// the cap shows gaps onto the bricks behind.
+
+pub type BrickMaterial = ExtendedMaterial<StandardMaterial, ClipExtension>;
+
+const CLIP_SHADER: &str = "embedded://shaders/clip.wgsl";
+// Fixed-size array in the shader's uniform; the height plane takes one slot.
+pub const MAX_CLIP_PLANES: usize = 4;
+pub const PLATE_HEIGHT: i32 = 4;
+// Top faces sit exactly on a plate boundary; without some slack they'd
+// flicker in and out at the clip height.
+const CLIP_MARGIN: f32 = 0.5;
+const DEFAULT_STEP_PLATES: i32 = 3;
+const DEFAULT_CAP_COLOR: Color = Color::srgb(0.85, 0.22, 0.18);
+const SECTION_LINE_COLOR: Color = Color::srgba(1.0, 0.35, 0.3, 0.8);
+
+pub struct ClipPlugin;
+
+#[derive(Clone, Copy, Debug, Default, Reflect, ShaderType)]
+pub struct ClipUniform {
+    planes: [Vec4; MAX_CLIP_PLANES],
+    cap_color: Vec4,
+    count: u32,
+}
+
+// Construction playback's threshold: how many bricks are shown. Only meshes
+// built with a reveal order carry the ranges it's tested against.
+#[derive(Clone, Copy, Debug, Default, Reflect, ShaderType)]
+pub struct RevealUniform {
+    pub shown: u32,
+}
+
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone, Default)]
+pub struct ClipExtension {
+    #[uniform(100)]
+    pub clip: ClipUniform,
+    #[uniform(101)]
+    pub reveal: RevealUniform,
+}
+
+impl MaterialExtension for ClipExtension {
+    fn fragment_shader() -> ShaderRef {
+        CLIP_SHADER.into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        CLIP_SHADER.into()
+    }
+
+    // shadow maps and the depth prepass
+    fn prepass_fragment_shader() -> ShaderRef {
+        CLIP_SHADER.into()
+    }
+}
+
+#[derive(Resource)]
+pub struct Clip {
+    // Section height in build units (save Z, renderer Y); `None` when off.
+    pub height: Option<i32>,
+    // Planes from `/clip view` in renderer space, normals pointing at what's
+    // kept.
+    pub planes: Vec<Vec4>,
+    // Plates per PageUp / PageDown.
+    pub step: i32,
+    pub cap_color: Color,
+}
+
+impl Default for Clip {
+    fn default() -> Self {
+        Self {
+            height: None,
+            planes: Vec::new(),
+            step: DEFAULT_STEP_PLATES,
+            cap_color: DEFAULT_CAP_COLOR,
+        }
+    }
+}
+
+impl Clip {
+    pub fn is_active(&self) -> bool {
+        self.height.is_some() || !self.planes.is_empty()
+    }
+
+    // Every active plane as one region, for picking against what's drawn.
+    pub fn frustum(&self) -> Frustum {
+        let height = self.height.map(|h| Vec4::new(0.0, -1.0, 0.0, h as f32 + CLIP_MARGIN));
+        Frustum::new(height.into_iter().chain(self.planes.iter().copied()).take(MAX_CLIP_PLANES).collect())
+    }
+
+    pub fn set_height(&mut self, height: f32) {
+        self.height = Some(snap_to_plate(height));
+    }
+
+    // Cut square to the view through `target`, dropping everything between it
+    // and the camera.
+    pub fn add_view_plane(&mut self, target: Vec3, forward: Vec3) -> Result<(), String> {
+        let slots = MAX_CLIP_PLANES - self.height.is_some() as usize;
+        if self.planes.len() >= slots {
+            return Err(format!("at most {} clip planes", MAX_CLIP_PLANES));
+        }
+        self.planes.push(forward.extend(-forward.dot(target)));
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.height = None;
+        self.planes.clear();
+    }
+
+    fn uniform(&self) -> ClipUniform {
+        let frustum = self.frustum();
+        let mut planes = [Vec4::ZERO; MAX_CLIP_PLANES];
+        for (slot, plane) in planes.iter_mut().zip(&frustum.planes) {
+            *slot = *plane;
+        }
+        ClipUniform {
+            planes,
+            cap_color: LinearRgba::from(self.cap_color).to_vec4(),
+            count: frustum.planes.len() as u32,
+        }
+    }
+}
+
+pub fn snap_to_plate(height: f32) -> i32 {
+    (height / PLATE_HEIGHT as f32).round() as i32 * PLATE_HEIGHT
+}
+
+impl Plugin for ClipPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_plugins(MaterialPlugin::<BrickMaterial>::default())
+            .init_resource::<Clip>()
+            .add_systems(Update, (clip_keys, sync_clip_materials, sync_discarding_materials, section_gizmos).chain())
+            .add_console_command(ConsoleCommand::new("/clip", "toggle a section cut, or set its height").arg(Arg::float("height").optional()), clip_command)
+            .add_console_command(ConsoleCommand::new("/clip off", "remove every cut"), clip_off_command)
+            .add_console_command(ConsoleCommand::new("/clip view", "cut away everything in front of the view center"), clip_view_command)
+            .add_console_command(ConsoleCommand::new("/clip step", "plates per PgUp/PgDn").arg(Arg::int("plates")), clip_step_command)
+            .add_console_command(ConsoleCommand::new("/clip cap", "color of the cut faces").arg(Arg::word("hex", &[])), clip_cap_command);
+    }
+}
+
+fn clip_command(
+    In(args): In<CommandArgs>,
+    mut clip: ResMut<Clip>,
+    bvh_query: Query<&SaveBVH>,
+) -> CommandResult {
+    if let Some(height) = args.float("height") {
+        clip.set_height(height);
+        return Ok(format!("section at {}", snap_to_plate(height)));
+    }
+    if clip.is_active() {
+        clip.clear();
+        return Ok("clipping off".into());
+    }
+    let top = bvh_query.iter().next()
+        .and_then(|save_bvh| save_bvh.generator.bvh.root_aabb())
+        .ok_or("no build loaded")?
+        .max().y;
+    clip.set_height(top as f32);
+    Ok(format!("section at {} (PgUp / PgDn to move it)", clip.height.unwrap_or_default()))
+}
+
+fn clip_off_command(
+    In(_): In<CommandArgs>,
+    mut clip: ResMut<Clip>,
+) -> CommandResult {
+    clip.clear();
+    Ok("clipping off".into())
+}
+
+fn clip_view_command(
+    In(_): In<CommandArgs>,
+    mut clip: ResMut<Clip>,
+    cam_query: Query<(&Transform, &IsoCamera)>,
+) -> CommandResult {
+    let (transform, cam) = cam_query.single().map_err(|_| "no camera")?;
+    clip.add_view_plane(cam.target, *transform.forward())?;
+    Ok(format!("{} view planes", clip.planes.len()))
+}
+
+fn clip_step_command(
+    In(args): In<CommandArgs>,
+    mut clip: ResMut<Clip>,
+) -> CommandResult {
+    let plates = args.int("plates").and_then(|plates| i32::try_from(plates).ok()).filter(|&plates| plates >= 1)
+        .ok_or("step must be at least 1 plate")?;
+    clip.step = plates;
+    Ok(format!("section step {plates} plates"))
+}
+
+fn clip_cap_command(
+    In(args): In<CommandArgs>,
+    mut clip: ResMut<Clip>,
+) -> CommandResult {
+    let hex = args.word("hex").unwrap_or_default();
+    let color = Srgba::hex(hex).map_err(|_| format!("bad color: {hex}"))?;
+    clip.cap_color = color.into();
+    Ok(format!("cap color {}", color.to_hex()))
+}
+
+// PageDown starts a section at the top of the build and lowers it, PageUp
+// raises it. A step is `Clip::step` plates, or one plate for the fine
+// actions (with shift held, by default).
+fn clip_keys(
+    actions: Actions,
+    game_state: Res<GameState>,
+    bvh_query: Query<&SaveBVH>,
+    mut clip: ResMut<Clip>,
+) {
+    if !game_state.input_listening() {
+        return;
+    }
+    let fine_down = actions.just_pressed(Action::SectionDownFine);
+    let fine_up = actions.just_pressed(Action::SectionUpFine);
+    let down = fine_down || actions.just_pressed(Action::SectionDown);
+    let up = fine_up || actions.just_pressed(Action::SectionUp);
+    if !down && !up {
+        return;
+    }
+    let Some(top) = bvh_query.iter().next().and_then(|save_bvh| save_bvh.generator.bvh.root_aabb()).map(|aabb| aabb.max().y) else {
+        return;
+    };
+
+    let plates = if fine_down || fine_up { 1 } else { clip.step };
+    let step = plates * PLATE_HEIGHT;
+    let top = snap_to_plate(top as f32);
+    let height = clip.height.unwrap_or(top);
+    let height = if down { height - step } else { (height + step).min(top) };
+    clip.height = Some(height);
+}
+
+fn sync_clip_materials(
+    clip: Res<Clip>,
+    assets: Res<SceneAssets>,
+    mut materials: ResMut<Assets<BrickMaterial>>,
+) {
+    if !clip.is_changed() && !assets.is_changed() {
+        return;
+    }
+    let uniform = clip.uniform();
+    for handle in assets.materials.bricks() {
+        if let Some(mut material) = materials.get_mut(handle) {
+            material.extension.clip = uniform;
+        }
+    }
+
+    // Opaque bricks draw their back faces only while there's a cut for them
+    // to show through; the shader paints them as the section cap. Glass keeps
+    // culling: its back faces show through the front ones and would all be
+    // painted as caps.
+    let sectioned = clip.is_active();
+    for handle in assets.materials.opaque_bricks() {
+        let Some(mut material) = materials.get_mut(handle) else { continue; };
+        if material.base.double_sided != sectioned {
+            material.base.cull_mode = if sectioned { None } else { Some(Face::Back) };
+            material.base.double_sided = sectioned;
+        }
+    }
+}
+
+// Bevy only runs a material's prepass shader, which draws the shadow maps,
+// when the material may discard. The opaque bricks are masked while a cut or
+// construction playback has the shader discarding, so shadows follow what's
+// drawn.
+fn sync_discarding_materials(
+    clip: Res<Clip>,
+    construction: Res<Construction>,
+    assets: Res<SceneAssets>,
+    mut materials: ResMut<Assets<BrickMaterial>>,
+) {
+    let discarding = clip.is_active() || construction.order.is_some();
+    // a zero cutoff keeps every fragment the shader doesn't discard itself
+    let alpha_mode = if discarding { AlphaMode::Mask(0.0) } else { AlphaMode::Opaque };
+    for handle in assets.materials.opaque_bricks() {
+        // checked first: `get_mut` alone would re-prepare the material every frame
+        if materials.get(handle).is_some_and(|material| material.base.alpha_mode != alpha_mode) {
+            if let Some(mut material) = materials.get_mut(handle) {
+                material.base.alpha_mode = alpha_mode;
+            }
+        }
+    }
+}
+
+// Outline of the horizontal cut around the build, so it's clear where the
+// section is even where it hits nothing.
+fn section_gizmos(
+    clip: Res<Clip>,
+    screenshotting: Res<Screenshotting>,
+    bvh_query: Query<&SaveBVH>,
+    mut gizmos: Gizmos,
+) {
+    let Some(height) = clip.height else { return; };
+    if screenshotting.0 {
+        return;
+    }
+    for save_bvh in &bvh_query {
+        let Some(aabb) = save_bvh.generator.bvh.root_aabb() else { continue; };
+        let (min, max) = (aabb.min().as_vec3(), aabb.max().as_vec3());
+        let y = height as f32;
+        gizmos.linestrip([
+            Vec3::new(min.x, y, min.z),
+            Vec3::new(max.x, y, min.z),
+            Vec3::new(max.x, y, max.z),
+            Vec3::new(min.x, y, max.z),
+            Vec3::new(min.x, y, min.z),
+        ], SECTION_LINE_COLOR);
+    }
+}
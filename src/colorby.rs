@@ -0,0 +1,294 @@
+use bevy::{platform::collections::HashMap, prelude::*};
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use rayon::prelude::*;
+
+use crate::{
+    bvh::{BVHMeshGenerator, CHUNK_SIZE},
+    console::{AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand},
+    state::Screenshotting,
+    utils::{asset_name, material_name, owner_name},
+    SaveBVH,
+};
+
+// Audit shading: recolor every brick by some property instead of its own
+// color. Faces and culling are reused as-is; the chunks whose colors change
+// are re-welded with a per-brick color override. Switched with `/colorby <mode>`.
+
+// Cell edge for the density heatmap: 16 studs.
+const DENSITY_CELL: i32 = 160;
+// Legend rows before the tail is folded into "others".
+const LEGEND_ROWS: usize = 12;
+// Gradient stops for the continuous modes, low to high.
+const GRADIENT: [[u8; 3]; 5] = [
+    [48, 18, 59],
+    [40, 140, 235],
+    [60, 220, 120],
+    [250, 200, 40],
+    [180, 20, 10],
+];
+// Folded legend rows and anything without a category.
+const OTHER_COLOR: [u8; 3] = [110, 110, 110];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorBy {
+    #[default]
+    Brick,
+    Owner,
+    Asset,
+    Material,
+    Height,
+    Density,
+    Chunk,
+    BvhDepth,
+}
+
+impl ColorBy {
+    // in declaration order, for `name`
+    pub const NAMES: [&'static str; 8] = ["brick", "owner", "asset", "material", "height", "density", "chunk", "depth"];
+    pub const ALL: [ColorBy; 8] = [
+        ColorBy::Brick,
+        ColorBy::Owner,
+        ColorBy::Asset,
+        ColorBy::Material,
+        ColorBy::Height,
+        ColorBy::Density,
+        ColorBy::Chunk,
+        ColorBy::BvhDepth,
+    ];
+
+    pub fn name(self) -> &'static str {
+        ColorBy::NAMES[self as usize]
+    }
+
+    pub fn parse(name: &str) -> Option<ColorBy> {
+        ColorBy::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ColorByMode(pub ColorBy);
+
+#[derive(Default)]
+enum Legend {
+    #[default]
+    None,
+    // (label, color, bricks), most common first
+    Categories(Vec<(String, [u8; 3], usize)>),
+    Gradient { low: String, high: String },
+}
+
+#[derive(Resource, Default)]
+struct ColorByLegend(Legend);
+
+pub struct ColorByPlugin;
+
+impl Plugin for ColorByPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ColorByMode>()
+            .init_resource::<ColorByLegend>()
+            .add_systems(Update, apply_color_by)
+            .add_systems(EguiPrimaryContextPass, legend_ui)
+            .add_console_command(ConsoleCommand::new("/colorby", "shade bricks by a property").arg(Arg::choice("mode", &ColorBy::NAMES)), color_by_command);
+    }
+}
+
+fn color_by_command(
+    In(args): In<CommandArgs>,
+    mut color_by: ResMut<ColorByMode>,
+) -> CommandResult {
+    let mode = args.word("mode").and_then(ColorBy::parse).ok_or("no such mode")?;
+    color_by.0 = mode;
+    Ok(format!("coloring by {}", mode.name()))
+}
+
+// Recolor when the mode changes or a new build arrives. Edits made while a
+// mode is active keep the colors computed here; added bricks show their own
+// color until the mode is picked again.
+fn apply_color_by(
+    mode: Res<ColorByMode>,
+    mut legend: ResMut<ColorByLegend>,
+    mut bvh_query: Query<&mut SaveBVH>,
+) {
+    let Ok(mut save_bvh) = bvh_query.single_mut() else { return; };
+    if !mode.is_changed() && !save_bvh.is_added() {
+        return;
+    }
+    // a fresh build already has its own colors
+    if mode.0 == ColorBy::Brick && save_bvh.is_added() {
+        legend.0 = Legend::None;
+        return;
+    }
+
+    let (colors, new_legend) = match mode.0 {
+        ColorBy::Brick => (None, Legend::None),
+        mode => {
+            let (colors, legend) = brick_colors(&save_bvh.generator, mode);
+            (Some(colors), legend)
+        }
+    };
+    save_bvh.generator.set_color_override(colors);
+    legend.0 = new_legend;
+}
+
+fn brick_colors(build: &BVHMeshGenerator, mode: ColorBy) -> (Vec<[u8; 4]>, Legend) {
+    let save_data = &build.save_data;
+    match mode {
+        ColorBy::Brick => unreachable!(),
+        ColorBy::Owner => by_category(build, |i| owner_name(save_data, &save_data.bricks[i]).to_string()),
+        ColorBy::Asset => by_category(build, |i| asset_name(save_data, &save_data.bricks[i]).to_string()),
+        ColorBy::Material => by_category(build, |i| material_name(save_data, &save_data.bricks[i]).to_string()),
+        ColorBy::Chunk => by_category(build, |i| {
+            let chunk = build.aabbs[i].center / CHUNK_SIZE;
+            // save axes (Z up)
+            format!("{}, {}, {}", chunk.x, chunk.z, chunk.y)
+        }),
+        ColorBy::Height => {
+            let heights: Vec<f32> = build.aabbs.iter().map(|aabb| aabb.center.y as f32).collect();
+            by_gradient(&heights, "")
+        },
+        ColorBy::Density => {
+            let mut cells: HashMap<IVec3, usize> = HashMap::default();
+            for (i, aabb) in build.aabbs.iter().enumerate() {
+                if !build.is_removed(i) {
+                    *cells.entry(aabb.center.div_euclid(IVec3::splat(DENSITY_CELL))).or_default() += 1;
+                }
+            }
+            let density: Vec<f32> = build.aabbs.iter()
+                .map(|aabb| cells.get(&aabb.center.div_euclid(IVec3::splat(DENSITY_CELL))).copied().unwrap_or(0) as f32)
+                .collect();
+            by_gradient(&density, " bricks/cell")
+        },
+        ColorBy::BvhDepth => {
+            let depths: Vec<f32> = build.bvh.brick_depths().into_iter().map(|depth| depth as f32).collect();
+            by_gradient(&depths, " deep")
+        },
+    }
+}
+
+// One distinct color per category, handed out most common first so the big
+// groups get the most separated hues.
+fn by_category(build: &BVHMeshGenerator, category: impl Fn(usize) -> String + Sync) -> (Vec<[u8; 4]>, Legend) {
+    let names: Vec<String> = (0..build.save_data.bricks.len()).into_par_iter().map(&category).collect();
+
+    let mut counts: HashMap<&str, usize> = HashMap::default();
+    for (i, name) in names.iter().enumerate() {
+        if !build.is_removed(i) {
+            *counts.entry(name.as_str()).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let palette: HashMap<&str, [u8; 3]> = counts.iter().enumerate()
+        .map(|(k, (name, _))| (*name, category_color(k)))
+        .collect();
+    let colors = names.iter()
+        .map(|name| {
+            let [r, g, b] = palette.get(name.as_str()).copied().unwrap_or(OTHER_COLOR);
+            [r, g, b, 0]
+        })
+        .collect();
+
+    let mut rows: Vec<(String, [u8; 3], usize)> = counts.iter()
+        .take(LEGEND_ROWS)
+        .map(|(name, count)| (name.to_string(), palette[name], *count))
+        .collect();
+    if counts.len() > LEGEND_ROWS {
+        let rest = &counts[LEGEND_ROWS..];
+        rows.push((format!("{} others", rest.len()), OTHER_COLOR, rest.iter().map(|(_, count)| count).sum()));
+    }
+    (colors, Legend::Categories(rows))
+}
+
+// Golden-ratio hue steps keep neighbouring categories far apart on the wheel.
+fn category_color(k: usize) -> [u8; 3] {
+    let hue = (k as f32 * 0.618_034).fract() * 360.0;
+    let value = if k % 2 == 0 { 0.95 } else { 0.75 };
+    let color = Color::hsv(hue, 0.65, value).to_srgba();
+    [
+        (color.red * 255.0).round() as u8,
+        (color.green * 255.0).round() as u8,
+        (color.blue * 255.0).round() as u8,
+    ]
+}
+
+fn by_gradient(values: &[f32], unit: &str) -> (Vec<[u8; 4]>, Legend) {
+    let (low, high) = values.iter().fold((f32::MAX, f32::MIN), |(low, high), &v| (low.min(v), high.max(v)));
+    let range = (high - low).max(f32::EPSILON);
+    let colors = values.iter()
+        .map(|&v| {
+            let [r, g, b] = gradient_color((v - low) / range);
+            [r, g, b, 0]
+        })
+        .collect();
+    let legend = if values.is_empty() {
+        Legend::None
+    } else {
+        Legend::Gradient { low: format!("{low}{unit}"), high: format!("{high}{unit}") }
+    };
+    (colors, legend)
+}
+
+fn gradient_color(t: f32) -> [u8; 3] {
+    let scaled = t.clamp(0.0, 1.0) * (GRADIENT.len() - 1) as f32;
+    let k = (scaled as usize).min(GRADIENT.len() - 2);
+    let f = scaled - k as f32;
+    let (a, b) = (GRADIENT[k], GRADIENT[k + 1]);
+    [0, 1, 2].map(|c| (a[c] as f32 + (b[c] as f32 - a[c] as f32) * f).round() as u8)
+}
+
+fn legend_ui(
+    mode: Res<ColorByMode>,
+    legend: Res<ColorByLegend>,
+    screenshotting: Res<Screenshotting>,
+    mut contexts: EguiContexts,
+) {
+    if mode.0 == ColorBy::Brick || screenshotting.0 {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return; };
+
+    egui::Window::new(format!("Color by {}", mode.0.name()))
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-12.0, -12.0))
+        .resizable(false)
+        .collapsible(true)
+        .show(ctx, |ui| match &legend.0 {
+            Legend::None => {
+                ui.weak("nothing to show");
+            },
+            Legend::Categories(rows) => {
+                for (label, [r, g, b], count) in rows {
+                    ui.horizontal(|ui| {
+                        swatch(ui, egui::Color32::from_rgb(*r, *g, *b));
+                        ui.label(format!("{count:>6}  {label}"));
+                    });
+                }
+            },
+            Legend::Gradient { low, high } => {
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(180.0, 14.0), egui::Sense::hover());
+                let steps = 36;
+                for s in 0..steps {
+                    let t0 = s as f32 / steps as f32;
+                    let t1 = (s + 1) as f32 / steps as f32;
+                    let [r, g, b] = gradient_color((t0 + t1) / 2.0);
+                    let cell = egui::Rect::from_min_max(
+                        egui::pos2(egui::lerp(rect.left()..=rect.right(), t0), rect.top()),
+                        egui::pos2(egui::lerp(rect.left()..=rect.right(), t1), rect.bottom()),
+                    );
+                    ui.painter().rect_filled(cell, 0.0, egui::Color32::from_rgb(r, g, b));
+                }
+                ui.horizontal(|ui| {
+                    ui.label(low);
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.label(high);
+                    });
+                });
+            },
+        });
+}
+
+fn swatch(ui: &mut egui::Ui, color: egui::Color32) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(12.0, 12.0), egui::Sense::hover());
+    ui.painter().rect_filled(rect, 2.0, color);
+}
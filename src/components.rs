@@ -5,10 +5,12 @@ use crate::utils::cc;
 
 const BRIGHTNESS_MULTIPLIER: f32 = 20000.0;
 
+// A brick's light; holds the brick index so edits can switch it off along
+// with the brick.
 #[derive(Component)]
-pub struct Light;
+pub struct Light(pub usize);
 
-pub fn gen_point_lights(save_data: &SaveData) -> Vec<(PointLight, Transform)> {
+pub fn gen_point_lights(save_data: &SaveData) -> Vec<(usize, PointLight, Transform)> {
     if !save_data.components.contains_key("BCD_PointLight") {
         return vec![];
     }
@@ -49,6 +51,7 @@ pub fn gen_point_lights(save_data: &SaveData) -> Vec<(PointLight, Transform)> {
         );
 
         point_lights.push((
+            *i as usize,
             PointLight {
                 color: Color::srgb(color[0], color[1], color[2]),
                 radius,
@@ -64,7 +67,7 @@ pub fn gen_point_lights(save_data: &SaveData) -> Vec<(PointLight, Transform)> {
     point_lights
 }
 
-pub fn gen_spot_lights(save_data: &SaveData) -> Vec<(SpotLight, Transform)> {
+pub fn gen_spot_lights(save_data: &SaveData) -> Vec<(usize, SpotLight, Transform)> {
     if !save_data.components.contains_key("BCD_SpotLight") {
         return vec![];
     }
@@ -114,6 +117,7 @@ pub fn gen_spot_lights(save_data: &SaveData) -> Vec<(SpotLight, Transform)> {
         transform.rotate_axis(Dir3::NEG_Y, rotation.y.to_radians());
 
         spot_lights.push((
+            *i as usize,
             SpotLight {
                 color: Color::srgb(color[0], color[1], color[2]),
                 radius,
@@ -0,0 +1,572 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use bevy::{ecs::system::SystemId, prelude::*};
+
+// Console commands. Each plugin registers its own with `add_console_command`:
+// a name, typed arguments, a line of help, and a one-shot system that gets
+// the parsed arguments and returns the line to print. The console parses,
+// completes and lists commands from here, so it doesn't need to know what
+// any of them do. A name can be more than one word (`/view save`); the
+// longest name the line starts with wins. `$name` and `${name}` are replaced
+// with variables set by `/set` before a line is parsed; `$$` is a dollar.
+// An optional argument a word doesn't fit is skipped, so `/animate sun 30fps`
+// leaves out the seconds.
+// Commands that write files where they're told, or run other commands, are
+// `local_only` and refused to the remote control.
+
+// The message to print, or what went wrong.
+pub type CommandResult = Result<String, String>;
+
+type Handler = SystemId<In<CommandArgs>, CommandResult>;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ArgKind {
+    Float,
+    Int,
+    // three numbers
+    Vec3,
+    // `<w>x<h>` in whole pixels
+    Size,
+    // one of these words
+    Choice(&'static [&'static str]),
+    // any one word, for names and for choices the handler parses itself
+    // (aliases, case); the list is only offered to Tab
+    Word(&'static [&'static str]),
+    // the rest of the line, spaces and all
+    Path,
+    // the rest of the line as one string, for names and text
+    Text,
+    // the rest of the line as words, for commands with a grammar of their own
+    // shared with the command line; the list is only offered to Tab
+    Words(&'static [&'static str]),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Arg {
+    name: &'static str,
+    kind: ArgKind,
+    optional: bool,
+    // a suffix a number is written with, like the `s` of `12s`
+    unit: Option<&'static str>,
+}
+
+impl Arg {
+    fn new(name: &'static str, kind: ArgKind) -> Self {
+        Self { name, kind, optional: false, unit: None }
+    }
+
+    pub fn float(name: &'static str) -> Self {
+        Self::new(name, ArgKind::Float)
+    }
+
+    pub fn int(name: &'static str) -> Self {
+        Self::new(name, ArgKind::Int)
+    }
+
+    pub fn vec3(name: &'static str) -> Self {
+        Self::new(name, ArgKind::Vec3)
+    }
+
+    pub fn size(name: &'static str) -> Self {
+        Self::new(name, ArgKind::Size)
+    }
+
+    pub fn choice(name: &'static str, choices: &'static [&'static str]) -> Self {
+        Self::new(name, ArgKind::Choice(choices))
+    }
+
+    pub fn word(name: &'static str, suggestions: &'static [&'static str]) -> Self {
+        Self::new(name, ArgKind::Word(suggestions))
+    }
+
+    pub fn path(name: &'static str) -> Self {
+        Self::new(name, ArgKind::Path)
+    }
+
+    pub fn text(name: &'static str) -> Self {
+        Self::new(name, ArgKind::Text)
+    }
+
+    pub fn words(name: &'static str, suggestions: &'static [&'static str]) -> Self {
+        Self::new(name, ArgKind::Words(suggestions))
+    }
+
+    // `[on|off]` for toggles, so scripts can set them either way; read it
+    // with `CommandArgs::switch`.
+    pub fn switch() -> Self {
+        Self::choice("state", &["on", "off"]).optional()
+    }
+
+    // Left out, it's missing from `CommandArgs`. Only trailing arguments
+    // can be optional.
+    pub fn optional(self) -> Self {
+        Self { optional: true, ..self }
+    }
+
+    // A number written with `unit` after it, `30fps`; it must be there.
+    pub fn unit(self, unit: &'static str) -> Self {
+        Self { unit: Some(unit), ..self }
+    }
+
+    fn usage(&self) -> String {
+        let inner = match self.kind {
+            ArgKind::Choice(choices) | ArgKind::Word(choices) if !choices.is_empty() => choices.join("|"),
+            ArgKind::Vec3 => "x y z".into(),
+            ArgKind::Words(_) => format!("{}...", self.name),
+            _ => self.name.into(),
+        };
+        match (self.kind, self.unit) {
+            (ArgKind::Size, _) if self.optional => "[<w>x<h>]".into(),
+            (ArgKind::Size, _) => "<w>x<h>".into(),
+            (_, Some(unit)) if self.optional => format!("[<{inner}>{unit}]"),
+            (_, Some(unit)) => format!("<{inner}>{unit}"),
+            _ if self.optional => format!("[{inner}]"),
+            _ => format!("<{inner}>"),
+        }
+    }
+
+    // How many words it takes; `None` for the rest of the line.
+    fn width(&self) -> Option<usize> {
+        match self.kind {
+            ArgKind::Vec3 => Some(3),
+            ArgKind::Path | ArgKind::Text | ArgKind::Words(_) => None,
+            _ => Some(1),
+        }
+    }
+
+    fn parse(&self, words: &[&str]) -> Result<ArgValue, String> {
+        let number = |word: &str| word.parse::<f32>().ok().filter(|n| n.is_finite())
+            .ok_or_else(|| format!("{}: expected a number, not {word}", self.name));
+        let first = match self.unit {
+            Some(unit) => words[0].strip_suffix(unit).ok_or_else(|| format!("{}: expected a number then {unit}, not {}", self.name, words[0]))?,
+            None => words[0],
+        };
+        match self.kind {
+            ArgKind::Float => number(first).map(ArgValue::Float),
+            ArgKind::Int => first.parse().map(ArgValue::Int)
+                .map_err(|_| format!("{}: expected a whole number, not {}", self.name, words[0])),
+            ArgKind::Vec3 => Ok(ArgValue::Vec3(Vec3::new(number(words[0])?, number(words[1])?, number(words[2])?))),
+            ArgKind::Size => words[0].split_once('x')
+                .and_then(|(width, height)| Some(UVec2::new(width.parse().ok()?, height.parse().ok()?)))
+                .filter(|size| size.min_element() > 0)
+                .map(ArgValue::Size)
+                .ok_or_else(|| format!("{}: expected <w>x<h> in pixels, not {}", self.name, words[0])),
+            ArgKind::Choice(choices) => choices.iter().find(|&&choice| choice == words[0])
+                .map(|choice| ArgValue::Word(choice.to_string()))
+                .ok_or_else(|| format!("{}: one of {}, not {}", self.name, choices.join(", "), words[0])),
+            ArgKind::Word(_) => Ok(ArgValue::Word(words[0].to_string())),
+            ArgKind::Path => Ok(ArgValue::Path(PathBuf::from(words.join(" ")))),
+            ArgKind::Text => Ok(ArgValue::Text(words.join(" "))),
+            ArgKind::Words(_) => Ok(ArgValue::Words(words.iter().map(|word| word.to_string()).collect())),
+        }
+    }
+
+    // What Tab offers for `partial`, the `position`th word of this argument.
+    fn complete(&self, position: usize, partial: &str) -> Vec<String> {
+        let starting = |words: &[&str]| -> Vec<String> { words.iter().filter(|word| word.starts_with(partial)).map(|word| word.to_string()).collect() };
+        match self.kind {
+            ArgKind::Choice(choices) | ArgKind::Word(choices) => starting(choices),
+            ArgKind::Words(suggestions) if position == 0 => starting(suggestions),
+            ArgKind::Path if position == 0 => complete_path(partial),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ArgValue {
+    Float(f32),
+    Int(i64),
+    Vec3(Vec3),
+    Size(UVec2),
+    Word(String),
+    Path(PathBuf),
+    Text(String),
+    Words(Vec<String>),
+}
+
+// A command's arguments, by name. Optional ones that were left out are
+// missing.
+#[derive(Debug, Clone, Default)]
+pub struct CommandArgs {
+    values: Vec<(&'static str, ArgValue)>,
+}
+
+impl CommandArgs {
+    fn get(&self, name: &str) -> Option<&ArgValue> {
+        self.values.iter().find(|(arg, _)| *arg == name).map(|(_, value)| value)
+    }
+
+    pub fn float(&self, name: &str) -> Option<f32> {
+        match self.get(name) {
+            Some(ArgValue::Float(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn int(&self, name: &str) -> Option<i64> {
+        match self.get(name) {
+            Some(ArgValue::Int(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn vec3(&self, name: &str) -> Option<Vec3> {
+        match self.get(name) {
+            Some(ArgValue::Vec3(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn size(&self, name: &str) -> Option<UVec2> {
+        match self.get(name) {
+            Some(ArgValue::Size(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // a choice or a word
+    pub fn word(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            Some(ArgValue::Word(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        match self.get(name) {
+            Some(ArgValue::Path(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            Some(ArgValue::Text(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    // What a toggle at `current` should become: as given, else flipped.
+    pub fn switch(&self, current: bool) -> bool {
+        match self.word("state") {
+            Some(state) => state == "on",
+            None => !current,
+        }
+    }
+
+    // empty if left out
+    pub fn words(&self, name: &str) -> Vec<&str> {
+        match self.get(name) {
+            Some(ArgValue::Words(words)) => words.iter().map(String::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+pub struct ConsoleCommand {
+    name: &'static str,
+    aliases: Vec<&'static str>,
+    args: Vec<Arg>,
+    help: &'static str,
+    local_only: bool,
+}
+
+impl ConsoleCommand {
+    // `name` as typed, slash and all: `/water`, `/view save`.
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Self { name, aliases: Vec::new(), args: Vec::new(), help, local_only: false }
+    }
+
+    pub fn arg(mut self, arg: Arg) -> Self {
+        self.args.push(arg);
+        self
+    }
+
+    pub fn alias(mut self, alias: &'static str) -> Self {
+        self.aliases.push(alias);
+        self
+    }
+
+    // Only typed in the console or run from scripts, never over the remote.
+    pub fn local_only(mut self) -> Self {
+        self.local_only = true;
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn help(&self) -> &'static str {
+        self.help
+    }
+
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for arg in &self.args {
+            usage.push(' ');
+            usage.push_str(&arg.usage());
+        }
+        usage
+    }
+
+    fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        std::iter::once(self.name).chain(self.aliases.iter().copied())
+    }
+
+    // How many of `words` its name or an alias takes up.
+    fn matches(&self, words: &[&str]) -> Option<usize> {
+        self.names()
+            .map(|name| name.split_whitespace().collect::<Vec<_>>())
+            .filter(|name| words.starts_with(name))
+            .map(|name| name.len())
+            .max()
+    }
+
+    fn parse(&self, mut words: &[&str]) -> Result<CommandArgs, String> {
+        let mut args = CommandArgs::default();
+        // why the first optional argument skipped didn't fit, in case
+        // nothing after it does either
+        let mut skipped = None;
+        for (i, arg) in self.args.iter().enumerate() {
+            if words.is_empty() {
+                if arg.optional {
+                    break;
+                }
+                return Err(format!("missing {}", arg.name));
+            }
+            let width = arg.width().unwrap_or(words.len());
+            if words.len() < width {
+                return Err(format!("{}: expected {width} numbers", arg.name));
+            }
+            match arg.parse(&words[..width]) {
+                Ok(value) => {
+                    args.values.push((arg.name, value));
+                    words = &words[width..];
+                },
+                Err(e) if arg.optional && i + 1 < self.args.len() => {
+                    skipped.get_or_insert(e);
+                },
+                Err(e) => return Err(skipped.unwrap_or(e)),
+            }
+        }
+        if let Some(extra) = words.first() {
+            return Err(skipped.unwrap_or_else(|| format!("unexpected {extra}")));
+        }
+        Ok(args)
+    }
+
+    // The argument the `index`th word after the name falls in, and which of
+    // its words it is.
+    fn arg_at(&self, mut index: usize) -> Option<(&Arg, usize)> {
+        for arg in &self.args {
+            match arg.width() {
+                Some(width) if index >= width => index -= width,
+                _ => return Some((arg, index)),
+            }
+        }
+        None
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ConsoleCommands {
+    commands: Vec<(ConsoleCommand, Handler)>,
+}
+
+impl ConsoleCommands {
+    // In the order they were registered.
+    pub fn iter(&self) -> impl Iterator<Item = &ConsoleCommand> {
+        self.commands.iter().map(|(command, _)| command)
+    }
+
+    // The command `words` start with, its handler, and the words after its name.
+    fn find<'a>(&self, words: &'a [&'a str]) -> Option<(&ConsoleCommand, Handler, &'a [&'a str])> {
+        self.commands.iter()
+            .filter_map(|(command, handler)| command.matches(words).map(|len| (command, *handler, len)))
+            .max_by_key(|(_, _, len)| *len)
+            .map(|(command, handler, len)| (command, handler, &words[len..]))
+    }
+
+    // What the last word of `line` could be: command names, the next word of
+    // longer ones, or an argument.
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        let partial = if line.ends_with(char::is_whitespace) { "" } else { words.pop().unwrap_or("") };
+
+        let mut candidates = Vec::new();
+        for name in self.iter().flat_map(ConsoleCommand::names) {
+            let name: Vec<&str> = name.split_whitespace().collect();
+            if name.len() > words.len() && name.starts_with(&words) && name[words.len()].starts_with(partial) {
+                candidates.push(name[words.len()].to_string());
+            }
+        }
+        if let Some((command, _, rest)) = self.find(&words) {
+            if let Some((arg, position)) = command.arg_at(rest.len()) {
+                candidates.extend(arg.complete(position, partial));
+            }
+        }
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+// `/set` variables.
+#[derive(Resource, Default)]
+pub struct ConsoleVariables(pub BTreeMap<String, String>);
+
+impl ConsoleVariables {
+    fn expand(&self, line: &str) -> Result<String, String> {
+        let mut expanded = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(i) = rest.find('$') {
+            expanded.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            let name = if rest.starts_with('$') {
+                expanded.push('$');
+                rest = &rest[1..];
+                continue;
+            } else if let Some(braced) = rest.strip_prefix('{') {
+                let end = braced.find('}').ok_or("unclosed ${")?;
+                rest = &braced[end + 1..];
+                &braced[..end]
+            } else {
+                let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+                let name = &rest[..end];
+                rest = &rest[end..];
+                name
+            };
+            let value = self.0.get(name).ok_or_else(|| format!("unknown variable ${name}"))?;
+            expanded.push_str(value);
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+}
+
+pub trait AddConsoleCommand {
+    fn add_console_command<M>(
+        &mut self,
+        command: ConsoleCommand,
+        handler: impl IntoSystem<In<CommandArgs>, CommandResult, M> + 'static,
+    ) -> &mut Self;
+}
+
+impl AddConsoleCommand for App {
+    fn add_console_command<M>(
+        &mut self,
+        command: ConsoleCommand,
+        handler: impl IntoSystem<In<CommandArgs>, CommandResult, M> + 'static,
+    ) -> &mut Self {
+        let handler = self.register_system(handler);
+        self.world_mut().get_resource_or_init::<ConsoleCommands>().commands.push((command, handler));
+        self
+    }
+}
+
+// Fill in variables, parse a typed line and run the command's handler.
+pub fn run_command(world: &mut World, line: &str) -> CommandResult {
+    run(world, line, false)
+}
+
+// The same for the remote control, which can't run `local_only` commands.
+pub fn run_remote_command(world: &mut World, line: &str) -> CommandResult {
+    run(world, line, true)
+}
+
+fn run(world: &mut World, line: &str, remote: bool) -> CommandResult {
+    let line = match world.get_resource::<ConsoleVariables>() {
+        Some(variables) => variables.expand(line)?,
+        None => line.to_string(),
+    };
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (handler, args) = {
+        let commands = world.get_resource::<ConsoleCommands>().ok_or("no console commands")?;
+        let (command, handler, rest) = commands.find(&words)
+            .ok_or_else(|| format!("unknown command: {line} (try /help)"))?;
+        if remote && command.local_only {
+            return Err(format!("{} can't be run over the remote control", command.name));
+        }
+        let args = command.parse(rest).map_err(|e| format!("{e}\nusage: {}", command.usage()))?;
+        (handler, args)
+    };
+    world.run_system_with(handler, args).map_err(|e| e.to_string())?
+}
+
+fn complete_path(partial: &str) -> Vec<String> {
+    let (directory, prefix) = match partial.rfind(['/', '\\']) {
+        Some(i) => partial.split_at(i + 1),
+        None => ("", partial),
+    };
+    let Ok(entries) = std::fs::read_dir(if directory.is_empty() { "." } else { directory }) else { return Vec::new(); };
+    entries.flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            name.starts_with(prefix).then(|| format!("{directory}{name}{slash}"))
+        })
+        .collect()
+}
+
+pub fn on_off(on: bool) -> &'static str {
+    if on { "on" } else { "off" }
+}
+
+/// Show or hide; returns `on`.
+pub fn set_visible(visibility: &mut Visibility, on: bool) -> bool {
+    *visibility = if on { Visibility::Visible } else { Visibility::Hidden };
+    on
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animate() -> ConsoleCommand {
+        ConsoleCommand::new("/animate", "")
+            .arg(Arg::choice("animation", &["turntable", "sun"]))
+            .arg(Arg::float("seconds").unit("s").optional())
+            .arg(Arg::int("fps").unit("fps").optional())
+            .arg(Arg::size("size").optional())
+            .arg(Arg::path("directory").optional())
+    }
+
+    #[test]
+    fn parses_typed_arguments() {
+        let args = animate().parse(&["sun", "20s", "60fps", "640x480", "my", "frames"]).unwrap();
+        assert_eq!(args.word("animation"), Some("sun"));
+        assert_eq!(args.float("seconds"), Some(20.0));
+        assert_eq!(args.int("fps"), Some(60));
+        assert_eq!(args.size("size"), Some(UVec2::new(640, 480)));
+        assert_eq!(args.path("directory"), Some(Path::new("my frames")));
+    }
+
+    #[test]
+    fn skips_optional_arguments_a_word_doesnt_fit() {
+        let args = animate().parse(&["turntable", "30fps"]).unwrap();
+        assert_eq!(args.float("seconds"), None);
+        assert_eq!(args.int("fps"), Some(30));
+        let args = animate().parse(&["turntable", "1920x1080", "out"]).unwrap();
+        assert_eq!(args.size("size"), Some(UVec2::new(1920, 1080)));
+        assert_eq!(args.path("directory"), Some(Path::new("out")));
+    }
+
+    #[test]
+    fn reports_what_didnt_fit() {
+        let sun = ConsoleCommand::new("/sun", "").arg(Arg::float("heading").optional()).arg(Arg::float("elevation").optional());
+        assert_eq!(sun.parse(&["10", "high"]).unwrap_err(), "elevation: expected a number, not high");
+        let construct = ConsoleCommand::new("/construct", "").arg(Arg::choice("order", &["save"]).optional()).arg(Arg::float("seconds").unit("s").optional());
+        assert_eq!(construct.parse(&["later"]).unwrap_err(), "order: one of save, not later");
+        assert!(animate().parse(&["spin"]).is_err());
+        assert!(animate().parse(&[]).is_err());
+    }
+
+    #[test]
+    fn usage_shows_units_and_sizes() {
+        assert_eq!(animate().usage(), "/animate <turntable|sun> [<seconds>s] [<fps>fps] [<w>x<h>] [directory]");
+        let text = ConsoleCommand::new("/screenshot name", "").arg(Arg::text("template"));
+        assert_eq!(text.parse(&["{save}", "{n}"]).unwrap().text("template"), Some("{save} {n}"));
+    }
+}
@@ -0,0 +1,261 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+
+use crate::{
+    asset_loader::SceneAssets,
+    bvh::BVHMeshGenerator,
+    clip::{BrickMaterial, RevealUniform},
+    console::{AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand},
+    state::Screenshotting,
+    SaveBVH,
+};
+
+// Construction playback: the build going up brick by brick, in save order,
+// bottom to top, or owner by owner. Turning it on re-welds the chunks once
+// with every face's range in the order (see `set_reveal_order`); after that
+// playing and scrubbing only move the brick shader's threshold. Started with
+// `/construct`; `/animate construction` renders it to frames.
+
+// A full play through takes this long, however big the build.
+const DEFAULT_PLAY_SECONDS: f32 = 20.0;
+const SCRUBBER_WIDTH: f32 = 320.0;
+
+pub struct ConstructPlugin;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildOrder {
+    // As placed: the order bricks are stored in the save or the `.brdb` chunks.
+    Save,
+    // Bottom to top by the bottom of each brick.
+    Height,
+    // One owner after another, in order of their first brick.
+    Owner,
+}
+
+impl BuildOrder {
+    // in declaration order, for `name`
+    pub const NAMES: [&'static str; 3] = ["save", "height", "owner"];
+    pub const ALL: [BuildOrder; 3] = [BuildOrder::Save, BuildOrder::Height, BuildOrder::Owner];
+
+    pub fn name(self) -> &'static str {
+        BuildOrder::NAMES[self as usize]
+    }
+
+    pub fn parse(name: &str) -> Option<BuildOrder> {
+        BuildOrder::ALL.into_iter().find(|order| order.name() == name)
+    }
+}
+
+#[derive(Resource)]
+pub struct Construction {
+    // `None` when off
+    pub order: Option<BuildOrder>,
+    // Bricks shown so far; fractional while playing.
+    pub shown: f32,
+    pub playing: bool,
+    // Seconds for a play through from empty to whole.
+    pub seconds: f32,
+    total: usize,
+}
+
+impl Default for Construction {
+    fn default() -> Self {
+        Self { order: None, shown: 0.0, playing: false, seconds: DEFAULT_PLAY_SECONDS, total: 0 }
+    }
+}
+
+impl Construction {
+    pub fn start(&mut self, order: BuildOrder) {
+        self.order = Some(order);
+        self.shown = 0.0;
+        self.playing = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.order = None;
+        self.playing = false;
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    // Show `t` of the way from nothing to the whole build.
+    pub fn seek(&mut self, t: f32) {
+        self.shown = t.clamp(0.0, 1.0) * self.total as f32;
+    }
+}
+
+impl Plugin for ConstructPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Construction>()
+            .add_systems(Update, (apply_build_order, play_construction, sync_reveal_materials).chain())
+            .add_systems(EguiPrimaryContextPass, construction_panel)
+            .add_console_command(
+                ConsoleCommand::new("/construct", "play the build going up in an order, over so many seconds (20s)")
+                    .arg(Arg::choice("order", &BuildOrder::NAMES).optional())
+                    .arg(Arg::float("seconds").unit("s").optional()),
+                construct_command,
+            )
+            .add_console_command(ConsoleCommand::new("/construct off", "end construction playback"), construct_off_command);
+    }
+}
+
+fn construct_command(
+    In(args): In<CommandArgs>,
+    mut construction: ResMut<Construction>,
+) -> CommandResult {
+    let order = match args.word("order") {
+        Some(name) => BuildOrder::parse(name).ok_or_else(|| format!("no build order {name}"))?,
+        None => construction.order.unwrap_or(BuildOrder::Save),
+    };
+    if let Some(seconds) = args.float("seconds") {
+        if seconds <= 0.0 {
+            return Err("seconds must be more than 0".into());
+        }
+        construction.seconds = seconds;
+    }
+    construction.start(order);
+    Ok(format!("playing the build in {} order over {}s", order.name(), construction.seconds))
+}
+
+fn construct_off_command(
+    In(_): In<CommandArgs>,
+    mut construction: ResMut<Construction>,
+) -> CommandResult {
+    construction.stop();
+    Ok("construction playback off".into())
+}
+
+// Rank the bricks when the order changes or a new build arrives. Like the
+// color-by modes, bricks added by later edits are there from the start.
+fn apply_build_order(
+    mut construction: ResMut<Construction>,
+    mut bvh_query: Query<&mut SaveBVH>,
+    mut applied: Local<Option<BuildOrder>>,
+) {
+    let Ok(mut save_bvh) = bvh_query.single_mut() else { return; };
+    if construction.order == *applied && !save_bvh.is_added() {
+        return;
+    }
+    *applied = construction.order;
+    construction.total = save_bvh.generator.save_data.bricks.len();
+    construction.shown = construction.shown.min(construction.total as f32);
+    // a fresh build has no order of its own
+    if construction.order.is_none() && save_bvh.is_added() {
+        return;
+    }
+    let ranks = construction.order.map(|order| reveal_ranks(&save_bvh.generator, order));
+    save_bvh.generator.set_reveal_order(ranks);
+}
+
+fn reveal_ranks(build: &BVHMeshGenerator, order: BuildOrder) -> Vec<u32> {
+    let bricks = &build.save_data.bricks;
+    let mut sequence: Vec<usize> = (0..bricks.len()).collect();
+    match order {
+        BuildOrder::Save => {},
+        BuildOrder::Height => sequence.sort_by_key(|&i| build.aabbs[i].min().y),
+        BuildOrder::Owner => {
+            let mut first_brick = vec![usize::MAX; build.save_data.header2.brick_owners.len() + 1];
+            for (i, brick) in bricks.iter().enumerate() {
+                if let Some(first) = first_brick.get_mut(brick.owner_index as usize) {
+                    *first = (*first).min(i);
+                }
+            }
+            sequence.sort_by_key(|&i| first_brick.get(bricks[i].owner_index as usize).copied().unwrap_or(usize::MAX));
+        },
+    }
+    let mut ranks = vec![0; bricks.len()];
+    for (rank, &i) in sequence.iter().enumerate() {
+        ranks[i] = rank as u32;
+    }
+    ranks
+}
+
+fn play_construction(
+    time: Res<Time>,
+    mut construction: ResMut<Construction>,
+) {
+    if !construction.playing || construction.order.is_none() {
+        return;
+    }
+    let total = construction.total as f32;
+    let step = total / construction.seconds.max(f32::EPSILON) * time.delta_secs();
+    construction.shown = (construction.shown + step).min(total);
+    if construction.shown >= total {
+        construction.playing = false;
+    }
+}
+
+fn sync_reveal_materials(
+    construction: Res<Construction>,
+    assets: Res<SceneAssets>,
+    mut materials: ResMut<Assets<BrickMaterial>>,
+) {
+    if !construction.is_changed() && !assets.is_changed() {
+        return;
+    }
+    // a brick is shown once any of it is
+    let reveal = RevealUniform { shown: construction.shown.ceil() as u32 };
+    for handle in assets.materials.bricks() {
+        if let Some(mut material) = materials.get_mut(handle) {
+            material.extension.reveal = reveal;
+        }
+    }
+}
+
+// Play/pause, the scrubber and the order. Closing it ends playback.
+fn construction_panel(
+    mut construction: ResMut<Construction>,
+    screenshotting: Res<Screenshotting>,
+    mut contexts: EguiContexts,
+) {
+    let Some(order) = construction.order else { return; };
+    if screenshotting.0 {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return; };
+
+    let total = construction.total as f32;
+    let mut open = true;
+    let mut shown = construction.shown;
+    let mut selected = order;
+    let mut toggle = false;
+
+    egui::Window::new("Construction")
+        .open(&mut open)
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -12.0))
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                toggle = ui.button(if construction.playing { "Pause" } else { "Play" }).clicked();
+                ui.spacing_mut().slider_width = SCRUBBER_WIDTH;
+                ui.add(egui::Slider::new(&mut shown, 0.0..=total).show_value(false));
+                ui.label(format!("{} / {}", shown as usize, construction.total));
+            });
+            ui.horizontal(|ui| {
+                for candidate in BuildOrder::ALL {
+                    ui.selectable_value(&mut selected, candidate, candidate.name());
+                }
+            });
+        });
+
+    if !open {
+        construction.stop();
+        return;
+    }
+    if selected != order {
+        construction.start(selected);
+    } else if shown != construction.shown {
+        construction.shown = shown;
+        construction.playing = false;
+    } else if toggle {
+        // play from the start again once it's all up
+        if !construction.playing && construction.shown >= total {
+            construction.shown = 0.0;
+        }
+        construction.playing = !construction.playing;
+    }
+}
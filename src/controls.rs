@@ -0,0 +1,633 @@
+use std::{collections::BTreeMap, fmt};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+// Key and mouse bindings. Systems ask `Actions` whether an action is held
+// or was just pressed instead of checking keys, and the bindings live in
+// `Settings`, so they're edited in the settings window and kept in
+// `settings.toml` as `action = ["Ctrl+KeyZ", ...]`. The console's line
+// editing and clicks on the view cube are fixed.
+//
+// A binding can need ctrl and shift held. When several bindings of the same
+// key match, only the one needing the most modifiers counts, so Ctrl+Z
+// undoes without also stepping the BVH depth bound to Z, while a binding
+// without shift still works with shift held for `Faster`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    // fly camera only
+    MoveUp,
+    MoveDown,
+    // held to move, turn and raise the water ten times faster
+    Faster,
+    RotateLeft,
+    RotateRight,
+    RotateDrag,
+    PanDrag,
+    Select,
+    BoxSelect,
+    ClearSelection,
+    Delete,
+    Undo,
+    Redo,
+    Recenter,
+    Frame,
+    CycleCamera,
+    SectionUp,
+    SectionDown,
+    // one plate at a time
+    SectionUpFine,
+    SectionDownFine,
+    SunLeft,
+    SunRight,
+    WaterUp,
+    WaterDown,
+    BvhDeeper,
+    BvhShallower,
+    LoadSave,
+    OpenConsole,
+    Screenshot,
+    HiResScreenshot,
+    ToggleFps,
+    // jump to the first nine camera bookmarks
+    RecallView1,
+    RecallView2,
+    RecallView3,
+    RecallView4,
+    RecallView5,
+    RecallView6,
+    RecallView7,
+    RecallView8,
+    RecallView9,
+}
+
+impl Action {
+    pub const ALL: [Action; 44] = [
+        Action::MoveForward, Action::MoveBack, Action::MoveLeft, Action::MoveRight, Action::MoveUp, Action::MoveDown, Action::Faster,
+        Action::RotateLeft, Action::RotateRight, Action::RotateDrag, Action::PanDrag,
+        Action::Select, Action::BoxSelect, Action::ClearSelection, Action::Delete, Action::Undo, Action::Redo,
+        Action::Recenter, Action::Frame, Action::CycleCamera,
+        Action::SectionUp, Action::SectionDown, Action::SectionUpFine, Action::SectionDownFine,
+        Action::SunLeft, Action::SunRight, Action::WaterUp, Action::WaterDown, Action::BvhDeeper, Action::BvhShallower,
+        Action::LoadSave, Action::OpenConsole, Action::Screenshot, Action::HiResScreenshot, Action::ToggleFps,
+        Action::RecallView1, Action::RecallView2, Action::RecallView3, Action::RecallView4, Action::RecallView5,
+        Action::RecallView6, Action::RecallView7, Action::RecallView8, Action::RecallView9,
+    ];
+    // in declaration order, for `name`
+    pub const NAMES: [&'static str; 44] = [
+        "move_forward", "move_back", "move_left", "move_right", "move_up", "move_down", "faster",
+        "rotate_left", "rotate_right", "rotate_drag", "pan_drag",
+        "select", "box_select", "clear_selection", "delete", "undo", "redo",
+        "recenter", "frame", "cycle_camera",
+        "section_up", "section_down", "section_up_fine", "section_down_fine",
+        "sun_left", "sun_right", "water_up", "water_down", "bvh_deeper", "bvh_shallower",
+        "load_save", "open_console", "screenshot", "hires_screenshot", "toggle_fps",
+        "recall_view_1", "recall_view_2", "recall_view_3", "recall_view_4", "recall_view_5",
+        "recall_view_6", "recall_view_7", "recall_view_8", "recall_view_9",
+    ];
+    // in bookmark order
+    pub const RECALL_VIEWS: [Action; 9] = [
+        Action::RecallView1, Action::RecallView2, Action::RecallView3, Action::RecallView4, Action::RecallView5,
+        Action::RecallView6, Action::RecallView7, Action::RecallView8, Action::RecallView9,
+    ];
+
+    pub fn name(self) -> &'static str {
+        Action::NAMES[self as usize]
+    }
+
+    pub fn parse(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    // For the settings window.
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move forward",
+            Action::MoveBack => "Move back",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::MoveUp => "Fly up",
+            Action::MoveDown => "Fly down",
+            Action::Faster => "Faster (hold)",
+            Action::RotateLeft => "Rotate left",
+            Action::RotateRight => "Rotate right",
+            Action::RotateDrag => "Rotate (drag)",
+            Action::PanDrag => "Pan (drag)",
+            Action::Select => "Select",
+            Action::BoxSelect => "Box select (drag)",
+            Action::ClearSelection => "Clear selection",
+            Action::Delete => "Delete selection",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::Recenter => "Recenter",
+            Action::Frame => "Frame build / selection",
+            Action::CycleCamera => "Iso / perspective / fly",
+            Action::SectionUp => "Raise section",
+            Action::SectionDown => "Lower section",
+            Action::SectionUpFine => "Raise section a plate",
+            Action::SectionDownFine => "Lower section a plate",
+            Action::SunLeft => "Turn sun left",
+            Action::SunRight => "Turn sun right",
+            Action::WaterUp => "Raise water",
+            Action::WaterDown => "Lower water",
+            Action::BvhDeeper => "BVH view deeper",
+            Action::BvhShallower => "BVH view shallower",
+            Action::LoadSave => "Load a build",
+            Action::OpenConsole => "Open console",
+            Action::Screenshot => "Screenshot",
+            Action::HiResScreenshot => "Hi-res screenshot",
+            Action::ToggleFps => "FPS counter",
+            Action::RecallView1 => "Camera view 1",
+            Action::RecallView2 => "Camera view 2",
+            Action::RecallView3 => "Camera view 3",
+            Action::RecallView4 => "Camera view 4",
+            Action::RecallView5 => "Camera view 5",
+            Action::RecallView6 => "Camera view 6",
+            Action::RecallView7 => "Camera view 7",
+            Action::RecallView8 => "Camera view 8",
+            Action::RecallView9 => "Camera view 9",
+        }
+    }
+
+    // A click and a drag on the same button are told apart by the drag.
+    fn can_share(self, other: Action) -> bool {
+        matches!((self, other), (Action::Select, Action::PanDrag) | (Action::PanDrag, Action::Select))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Trigger {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+// A key or mouse button, and the modifiers it needs held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub trigger: Trigger,
+    pub ctrl: bool,
+    pub shift: bool,
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        &[$((stringify!($key), KeyCode::$key)),*]
+    };
+}
+
+// Keys a binding can name, as `KeyCode`'s variants are spelled.
+const KEY_NAMES: &[(&str, KeyCode)] = key_names!(
+    KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM,
+    KeyN, KeyO, KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+    Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    ArrowUp, ArrowDown, ArrowLeft, ArrowRight, PageUp, PageDown, Home, End, Insert, Delete,
+    Space, Tab, Enter, Escape, Backspace, Backquote, Minus, Equal, BracketLeft, BracketRight,
+    Backslash, Semicolon, Quote, Comma, Period, Slash,
+    ShiftLeft, ShiftRight, ControlLeft, ControlRight, AltLeft, AltRight,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal, NumpadEnter,
+);
+
+const MOUSE_NAMES: [(&str, MouseButton); 5] = [
+    ("MouseLeft", MouseButton::Left),
+    ("MouseRight", MouseButton::Right),
+    ("MouseMiddle", MouseButton::Middle),
+    ("MouseBack", MouseButton::Back),
+    ("MouseForward", MouseButton::Forward),
+];
+
+const MODIFIER_KEYS: [KeyCode; 4] = [KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft, KeyCode::ControlRight];
+
+impl Binding {
+    pub fn key(key: KeyCode) -> Self {
+        Self { trigger: Trigger::Key(key), ctrl: false, shift: false }
+    }
+
+    pub fn mouse(button: MouseButton) -> Self {
+        Self { trigger: Trigger::Mouse(button), ctrl: false, shift: false }
+    }
+
+    pub fn ctrl(self) -> Self {
+        Self { ctrl: true, ..self }
+    }
+
+    pub fn shift(self) -> Self {
+        Self { shift: true, ..self }
+    }
+
+    fn modifiers(&self) -> usize {
+        self.ctrl as usize + self.shift as usize
+    }
+
+    // `Ctrl+Shift+KeyZ`, `MouseRight`: what the settings file holds.
+    pub fn parse(text: &str) -> Option<Binding> {
+        let mut binding = None;
+        let (mut ctrl, mut shift) = (false, false);
+        for part in text.split('+').map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => ctrl = true,
+                "shift" => shift = true,
+                _ if binding.is_some() => return None,
+                _ => {
+                    binding = KEY_NAMES.iter().find(|(name, _)| name.eq_ignore_ascii_case(part)).map(|&(_, key)| Binding::key(key))
+                        .or_else(|| MOUSE_NAMES.iter().find(|(name, _)| name.eq_ignore_ascii_case(part)).map(|&(_, button)| Binding::mouse(button)));
+                    if binding.is_none() {
+                        return None;
+                    }
+                },
+            }
+        }
+        binding.map(|binding| Binding { ctrl, shift, ..binding })
+    }
+
+    // Short, for the controls hint: `ctrl-Z`, `PgUp`, `right mouse`.
+    pub fn label(&self) -> String {
+        let trigger = match self.trigger {
+            Trigger::Key(key) => key_label(key),
+            Trigger::Mouse(MouseButton::Left) => "left mouse".into(),
+            Trigger::Mouse(MouseButton::Right) => "right mouse".into(),
+            Trigger::Mouse(MouseButton::Middle) => "middle mouse".into(),
+            Trigger::Mouse(button) => format!("{button:?} mouse").to_lowercase(),
+        };
+        format!("{}{}{trigger}", if self.ctrl { "ctrl-" } else { "" }, if self.shift { "shift-" } else { "" })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        match self.trigger {
+            Trigger::Key(key) => match KEY_NAMES.iter().find(|&&(_, named)| named == key) {
+                Some((name, _)) => write!(f, "{name}"),
+                None => write!(f, "{key:?}"),
+            },
+            Trigger::Mouse(button) => match MOUSE_NAMES.iter().find(|&&(_, named)| named == button) {
+                Some((name, _)) => write!(f, "{name}"),
+                None => write!(f, "Mouse{button:?}"),
+            },
+        }
+    }
+}
+
+fn key_label(key: KeyCode) -> String {
+    let label = match key {
+        KeyCode::Slash => "/",
+        KeyCode::Backquote => "`",
+        KeyCode::Minus => "-",
+        KeyCode::Equal => "=",
+        KeyCode::Comma => ",",
+        KeyCode::Period => ".",
+        KeyCode::Delete => "Del",
+        KeyCode::PageUp => "PgUp",
+        KeyCode::PageDown => "PgDn",
+        KeyCode::Escape => "Esc",
+        KeyCode::ArrowLeft => "Left",
+        KeyCode::ArrowRight => "Right",
+        KeyCode::ArrowUp => "Up",
+        KeyCode::ArrowDown => "Down",
+        KeyCode::ShiftLeft | KeyCode::ShiftRight => "shift",
+        KeyCode::ControlLeft | KeyCode::ControlRight => "ctrl",
+        KeyCode::Space => "space",
+        _ => {
+            let name = format!("{key:?}");
+            return name.strip_prefix("Key").or_else(|| name.strip_prefix("Digit")).unwrap_or(&name).to_string();
+        },
+    };
+    label.into()
+}
+
+// Every action's bindings, in the settings file as `action = ["KeyW"]`.
+// Actions the file leaves out keep their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<String, Vec<String>>", into = "BTreeMap<String, Vec<String>>")]
+pub struct Bindings(BTreeMap<Action, Vec<Binding>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Action::*;
+        let key = Binding::key;
+        let bindings = [
+            (MoveForward, vec![key(KeyCode::KeyW)]),
+            (MoveBack, vec![key(KeyCode::KeyS)]),
+            (MoveLeft, vec![key(KeyCode::KeyA)]),
+            (MoveRight, vec![key(KeyCode::KeyD)]),
+            (MoveUp, vec![key(KeyCode::Space)]),
+            (MoveDown, vec![key(KeyCode::KeyC)]),
+            (Faster, vec![key(KeyCode::ShiftLeft)]),
+            (RotateLeft, vec![key(KeyCode::KeyQ)]),
+            (RotateRight, vec![key(KeyCode::KeyE)]),
+            (RotateDrag, vec![Binding::mouse(MouseButton::Right)]),
+            (PanDrag, vec![Binding::mouse(MouseButton::Left)]),
+            (Select, vec![Binding::mouse(MouseButton::Left)]),
+            (BoxSelect, vec![Binding::mouse(MouseButton::Left).ctrl()]),
+            (ClearSelection, vec![key(KeyCode::Escape)]),
+            (Delete, vec![key(KeyCode::Delete)]),
+            (Undo, vec![key(KeyCode::KeyZ).ctrl()]),
+            (Redo, vec![key(KeyCode::KeyY).ctrl(), key(KeyCode::KeyZ).ctrl().shift()]),
+            (Recenter, vec![key(KeyCode::KeyH)]),
+            (Frame, vec![key(KeyCode::KeyF)]),
+            (CycleCamera, vec![key(KeyCode::KeyP)]),
+            (SectionUp, vec![key(KeyCode::PageUp)]),
+            (SectionDown, vec![key(KeyCode::PageDown)]),
+            (SectionUpFine, vec![key(KeyCode::PageUp).shift()]),
+            (SectionDownFine, vec![key(KeyCode::PageDown).shift()]),
+            (SunLeft, vec![key(KeyCode::ArrowLeft)]),
+            (SunRight, vec![key(KeyCode::ArrowRight)]),
+            (WaterUp, vec![key(KeyCode::KeyI)]),
+            (WaterDown, vec![key(KeyCode::KeyK)]),
+            (BvhDeeper, vec![key(KeyCode::KeyX)]),
+            (BvhShallower, vec![key(KeyCode::KeyZ)]),
+            (LoadSave, vec![key(KeyCode::KeyL), key(KeyCode::KeyO)]),
+            (OpenConsole, vec![key(KeyCode::Slash)]),
+            (Screenshot, vec![key(KeyCode::F2)]),
+            (HiResScreenshot, vec![key(KeyCode::F3)]),
+            (ToggleFps, vec![key(KeyCode::F12)]),
+            (RecallView1, vec![key(KeyCode::Digit1)]),
+            (RecallView2, vec![key(KeyCode::Digit2)]),
+            (RecallView3, vec![key(KeyCode::Digit3)]),
+            (RecallView4, vec![key(KeyCode::Digit4)]),
+            (RecallView5, vec![key(KeyCode::Digit5)]),
+            (RecallView6, vec![key(KeyCode::Digit6)]),
+            (RecallView7, vec![key(KeyCode::Digit7)]),
+            (RecallView8, vec![key(KeyCode::Digit8)]),
+            (RecallView9, vec![key(KeyCode::Digit9)]),
+        ];
+        Bindings(bindings.into_iter().collect())
+    }
+}
+
+impl From<BTreeMap<String, Vec<String>>> for Bindings {
+    fn from(table: BTreeMap<String, Vec<String>>) -> Self {
+        let mut bindings = Bindings::default();
+        for (name, texts) in table {
+            let Some(action) = Action::parse(&name) else {
+                warn!("Ignoring bindings for unknown action {name}");
+                continue;
+            };
+            let parsed = texts.iter().filter_map(|text| {
+                let binding = Binding::parse(text);
+                if binding.is_none() {
+                    warn!("Ignoring binding {text} for {name}");
+                }
+                binding
+            });
+            bindings.0.insert(action, parsed.collect());
+        }
+        bindings
+    }
+}
+
+impl From<Bindings> for BTreeMap<String, Vec<String>> {
+    fn from(bindings: Bindings) -> Self {
+        bindings.0.into_iter()
+            .map(|(action, bindings)| (action.name().to_string(), bindings.iter().map(Binding::to_string).collect()))
+            .collect()
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn set(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.0.insert(action, bindings);
+    }
+
+    fn all(&self) -> impl Iterator<Item = (Action, &Binding)> {
+        self.0.iter().flat_map(|(&action, bindings)| bindings.iter().map(move |binding| (action, binding)))
+    }
+
+    // Bindings two actions both use, with the actions.
+    pub fn conflicts(&self) -> Vec<(Binding, Action, Action)> {
+        let mut conflicts = Vec::new();
+        for (i, (a, binding)) in self.all().enumerate() {
+            for (b, other) in self.all().skip(i + 1) {
+                if a != b && binding == other && !a.can_share(b) {
+                    conflicts.push((*binding, a, b));
+                }
+            }
+        }
+        conflicts
+    }
+
+    // The first binding of each action, for the controls hint: `WASD` when
+    // they're all single keys, `1-9` when those run in order, else
+    // `Q / E / right mouse`.
+    pub fn describe(&self, actions: &[Action]) -> Option<String> {
+        let labels: Vec<String> = actions.iter().filter_map(|&action| self.get(action).first()).map(Binding::label).collect();
+        if labels.is_empty() {
+            return None;
+        }
+        if labels.len() > 1 && labels.iter().all(|label| label.chars().count() == 1) {
+            let keys: Vec<char> = labels.iter().filter_map(|label| label.chars().next()).collect();
+            if keys.len() > 2 && keys.windows(2).all(|pair| pair[1] as u32 == pair[0] as u32 + 1) {
+                return Some(format!("{}-{}", keys[0], keys[keys.len() - 1]));
+            }
+            Some(labels.concat())
+        } else {
+            Some(labels.join(" / "))
+        }
+    }
+}
+
+// The controls overlay, from the active bindings. Lines without actions are
+// for fixed controls.
+const HINT_LINES: &[(&[Action], &str)] = &[
+    (&[Action::MoveForward, Action::MoveLeft, Action::MoveBack, Action::MoveRight], "move"),
+    (&[Action::RotateLeft, Action::RotateRight, Action::RotateDrag], "rotate"),
+    (&[], "scroll  zoom"),
+    (&[Action::Faster], "faster"),
+    (&[Action::Select, Action::BoxSelect], "select"),
+    (&[Action::Delete], "delete selection"),
+    (&[Action::Undo, Action::Redo], "undo / redo"),
+    (&[Action::SectionUp, Action::SectionDown], "section height"),
+    (&[Action::Recenter], "recenter"),
+    (&[Action::Frame], "frame build / selection"),
+    (&[Action::CycleCamera], "iso / perspective / fly"),
+    (&Action::RECALL_VIEWS, "camera bookmarks"),
+    (&[Action::Screenshot], "screenshot"),
+    (&[Action::HiResScreenshot], "hi-res screenshot"),
+];
+
+pub fn controls_hint(bindings: &Bindings) -> String {
+    let lines: Vec<String> = HINT_LINES.iter()
+        .filter_map(|&(actions, text)| {
+            if actions.is_empty() {
+                return Some(text.to_string());
+            }
+            bindings.describe(actions).map(|keys| format!("{keys}  {text}"))
+        })
+        .collect();
+    lines.join("\n")
+}
+
+// Set while the settings window waits for a key to bind, when no action
+// fires, so the key pressed doesn't also do what it's bound to.
+#[derive(Resource, Default)]
+pub struct Rebinding(pub bool);
+
+// Whether actions are held or were just pressed, by their bindings.
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    settings: Res<'w, Settings>,
+    rebinding: Res<'w, Rebinding>,
+}
+
+impl Actions<'_> {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.matches(action, |trigger| self.trigger_pressed(trigger))
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.matches(action, |trigger| match trigger {
+            Trigger::Key(key) => self.keys.just_pressed(key),
+            Trigger::Mouse(button) => self.mouse.just_pressed(button),
+        })
+    }
+
+    // Let go of, whatever the modifiers are doing by now, so a drag ends.
+    pub fn just_released(&self, action: Action) -> bool {
+        self.settings.bindings.get(action).iter().any(|binding| match binding.trigger {
+            Trigger::Key(key) => self.keys.just_released(key),
+            Trigger::Mouse(button) => self.mouse.just_released(button),
+        })
+    }
+
+    fn trigger_pressed(&self, trigger: Trigger) -> bool {
+        match trigger {
+            Trigger::Key(key) => self.keys.pressed(key),
+            Trigger::Mouse(button) => self.mouse.pressed(button),
+        }
+    }
+
+    fn modifiers_held(&self, binding: &Binding) -> bool {
+        (!binding.ctrl || self.keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]))
+            && (!binding.shift || self.keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]))
+    }
+
+    // A binding of `action` is down with its modifiers, and no binding of
+    // the same key needing more modifiers is too.
+    fn matches(&self, action: Action, down: impl Fn(Trigger) -> bool) -> bool {
+        if self.rebinding.0 {
+            return false;
+        }
+        let bindings = &self.settings.bindings;
+        bindings.get(action).iter().any(|binding| {
+            down(binding.trigger)
+                && self.modifiers_held(binding)
+                && !bindings.all().any(|(_, other)| {
+                    other.trigger == binding.trigger && other.modifiers() > binding.modifiers() && self.modifiers_held(other)
+                })
+        })
+    }
+}
+
+// For exclusive systems, which can't take `Actions`.
+pub fn just_pressed(world: &mut World, action: Action) -> bool {
+    world.run_system_cached_with(|In(action): In<Action>, actions: Actions| actions.just_pressed(action), action)
+        .unwrap_or(false)
+}
+
+// The key or button being pressed to rebind an action, with the modifiers
+// held. Modifier keys bind on their own when let go of without another
+// key; Esc gives `Some(None)` to cancel.
+pub fn captured_binding(keys: &ButtonInput<KeyCode>, mouse: &ButtonInput<MouseButton>) -> Option<Option<Binding>> {
+    if keys.just_pressed(KeyCode::Escape) {
+        return Some(None);
+    }
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let with_modifiers = |binding: Binding| Binding { ctrl, shift, ..binding };
+    if let Some(&key) = keys.get_just_pressed().find(|key| !MODIFIER_KEYS.contains(key)) {
+        return Some(Some(with_modifiers(Binding::key(key))));
+    }
+    if let Some(&button) = mouse.get_just_pressed().next() {
+        return Some(Some(with_modifiers(Binding::mouse(button))));
+    }
+    if keys.get_pressed().next().is_none() {
+        if let Some(&key) = keys.get_just_released().find(|key| MODIFIER_KEYS.contains(key)) {
+            return Some(Some(Binding::key(key)));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_names_parse_back() {
+        for action in Action::ALL {
+            assert_eq!(Action::parse(action.name()), Some(action));
+        }
+        assert_eq!(Action::parse("recall_view_5"), Some(Action::RecallView5));
+        assert_eq!(Action::parse("fly"), None);
+    }
+
+    #[test]
+    fn every_named_binding_round_trips() {
+        let triggers = KEY_NAMES.iter().map(|&(_, key)| Binding::key(key))
+            .chain(MOUSE_NAMES.iter().map(|&(_, button)| Binding::mouse(button)));
+        for binding in triggers {
+            for binding in [binding, binding.ctrl(), binding.shift(), binding.ctrl().shift()] {
+                assert_eq!(Binding::parse(&binding.to_string()), Some(binding), "{binding}");
+            }
+        }
+    }
+
+    #[test]
+    fn bindings_parse_loosely() {
+        assert_eq!(Binding::parse("Ctrl+Shift+KeyZ"), Some(Binding::key(KeyCode::KeyZ).ctrl().shift()));
+        assert_eq!(Binding::parse("shift + control + keyz"), Some(Binding::key(KeyCode::KeyZ).ctrl().shift()));
+        assert_eq!(Binding::parse("mouseright"), Some(Binding::mouse(MouseButton::Right)));
+        assert_eq!(Binding::key(KeyCode::PageUp).shift().to_string(), "Shift+PageUp");
+    }
+
+    #[test]
+    fn bad_bindings_dont_parse() {
+        for text in ["", "Ctrl", "Ctrl+", "KeyZ+KeyX", "Z", "Alt+KeyZ", "MouseSide"] {
+            assert_eq!(Binding::parse(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn bindings_round_trip_through_the_settings_table() {
+        let mut bindings = Bindings::default();
+        bindings.set(Action::Undo, vec![Binding::key(KeyCode::KeyU).ctrl(), Binding::mouse(MouseButton::Back)]);
+        bindings.set(Action::Frame, Vec::new());
+        let table: BTreeMap<String, Vec<String>> = bindings.clone().into();
+        assert_eq!(table["undo"], ["Ctrl+KeyU", "MouseBack"]);
+        assert_eq!(Bindings::from(table), bindings);
+    }
+
+    #[test]
+    fn default_bindings_only_share_where_allowed() {
+        assert!(Bindings::default().conflicts().is_empty());
+    }
+
+    #[test]
+    fn hint_shows_runs_of_keys_as_ranges() {
+        let mut bindings = Bindings::default();
+        assert_eq!(bindings.describe(&Action::RECALL_VIEWS).as_deref(), Some("1-9"));
+        assert_eq!(bindings.describe(&[Action::MoveForward, Action::MoveLeft, Action::MoveBack, Action::MoveRight]).as_deref(), Some("WASD"));
+        bindings.set(Action::RecallView5, vec![Binding::key(KeyCode::KeyV)]);
+        assert_eq!(bindings.describe(&Action::RECALL_VIEWS).as_deref(), Some("1234V6789"));
+    }
+}
@@ -0,0 +1,371 @@
+use std::{path::{Path, PathBuf}, thread};
+
+use bevy::{ecs::entity_disabling::Disabled, platform::collections::{HashMap, HashSet}, prelude::*};
+use brickadia::save::{Brick, BrickColor, SaveData};
+
+use crate::{asset_loader::SceneAssets, bvh::{BVHMeshGenerator, ChunkKey}, components::Light, console::{AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand}, controls::{Action, Actions}, selection::Selection, state::GameState, default_build_directory, ChunkEntity, ChunkMesh, SaveBVH};
+
+// Brick edits after load. Anything that changes the build writes a
+// `BrickEdits` batch; `apply_brick_edits` applies it to the loaded build's
+// generator, records the inverse for undo, and swaps in new meshes for just
+// the chunks it touched.
+
+pub struct EditPlugin;
+
+#[derive(Debug, Clone)]
+pub enum BrickEdit {
+    Remove(usize),
+    Restore(usize),
+    Add(Brick),
+    SetColor(usize, BrickColor),
+    SetVisible(usize, bool),
+    SetMaterial(usize, u32),
+}
+
+// One user action: applied together and undone as a single step.
+#[derive(Message, Debug, Clone, Default)]
+pub struct BrickEdits(pub Vec<BrickEdit>);
+
+// Sent when applied edits add or remove bricks or change their owner, asset,
+// material or color, so the filter catalog is rebuilt only then.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct BricksRecataloged;
+
+#[derive(Message, Debug, Clone, Copy)]
+pub enum HistoryStep {
+    Undo,
+    Redo,
+}
+
+// Each entry holds the edits that reverse one applied batch, already in the
+// order they need to run.
+#[derive(Resource, Default)]
+pub struct EditHistory {
+    undo: Vec<Vec<BrickEdit>>,
+    redo: Vec<Vec<BrickEdit>>,
+}
+
+impl EditHistory {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+impl BrickEdit {
+    fn target(&self) -> Option<usize> {
+        match self {
+            BrickEdit::Remove(i)
+            | BrickEdit::Restore(i)
+            | BrickEdit::SetColor(i, _)
+            | BrickEdit::SetVisible(i, _)
+            | BrickEdit::SetMaterial(i, _) => Some(*i),
+            BrickEdit::Add(_) => None,
+        }
+    }
+}
+
+impl Plugin for EditPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<EditHistory>()
+            .add_message::<BrickEdits>()
+            .add_message::<HistoryStep>()
+            .add_message::<BricksRecataloged>()
+            .add_systems(Update, (edit_keys, apply_brick_edits).chain())
+            .add_console_command(ConsoleCommand::new("/restore", "bring back removed bricks"), restore_command)
+            .add_console_command(ConsoleCommand::new("/unhide", "show every hidden brick"), unhide_command)
+            .add_console_command(ConsoleCommand::new("/undo", "undo the last brick edit"), undo_command)
+            .add_console_command(ConsoleCommand::new("/redo", "redo an undone brick edit"), redo_command)
+            .add_console_command(
+                ConsoleCommand::new("/export", "save the edited build as .brs/.brz, asking where unless given a path")
+                    .arg(Arg::path("path").optional())
+                    .local_only(),
+                export_command,
+            );
+    }
+}
+
+fn restore_command(
+    In(_): In<CommandArgs>,
+    bvh_query: Query<&SaveBVH>,
+    mut edits: MessageWriter<BrickEdits>,
+) -> CommandResult {
+    let removed: Vec<BrickEdit> = bvh_query.iter()
+        .flat_map(|save_bvh| save_bvh.generator.removed_bricks())
+        .map(BrickEdit::Restore)
+        .collect();
+    let message = format!("restored {} bricks", removed.len());
+    edits.write(BrickEdits(removed));
+    Ok(message)
+}
+
+// The selection panel's Show only reaches bricks still selected; this
+// brings back every one, including bricks the save itself had hidden.
+fn unhide_command(
+    In(_): In<CommandArgs>,
+    bvh_query: Query<&SaveBVH>,
+    mut edits: MessageWriter<BrickEdits>,
+) -> CommandResult {
+    let hidden: Vec<BrickEdit> = bvh_query.iter()
+        .flat_map(|save_bvh| save_bvh.generator.hidden_bricks())
+        .map(|i| BrickEdit::SetVisible(i, true))
+        .collect();
+    let message = format!("showing {} hidden bricks", hidden.len());
+    edits.write(BrickEdits(hidden));
+    Ok(message)
+}
+
+fn undo_command(
+    In(_): In<CommandArgs>,
+    mut steps: MessageWriter<HistoryStep>,
+) -> CommandResult {
+    steps.write(HistoryStep::Undo);
+    Ok(String::new())
+}
+
+fn redo_command(
+    In(_): In<CommandArgs>,
+    mut steps: MessageWriter<HistoryStep>,
+) -> CommandResult {
+    steps.write(HistoryStep::Redo);
+    Ok(String::new())
+}
+
+fn export_command(
+    In(args): In<CommandArgs>,
+    bvh_query: Query<&SaveBVH>,
+) -> CommandResult {
+    let save_bvh = bvh_query.single().map_err(|_| "no build loaded")?;
+    let save_data = save_bvh.generator.export_save();
+    match args.path("path") {
+        Some(path) => {
+            let path = path.to_path_buf();
+            let message = format!("exporting {} bricks to {}", save_data.bricks.len(), path.display());
+            thread::spawn(move || write_export(save_data, &path));
+            Ok(message)
+        },
+        None => {
+            export_with_dialog(save_data, default_build_directory());
+            Ok("choose where to save the export".into())
+        },
+    }
+}
+
+// Delete removes the selection; Ctrl+Z / Ctrl+Y (or Ctrl+Shift+Z) step
+// through history, by default.
+fn edit_keys(
+    actions: Actions,
+    game_state: Res<GameState>,
+    selection: Res<Selection>,
+    mut edits: MessageWriter<BrickEdits>,
+    mut steps: MessageWriter<HistoryStep>,
+) {
+    if !game_state.input_listening() {
+        return;
+    }
+    if actions.just_pressed(Action::Delete) && !selection.is_empty() {
+        edits.write(BrickEdits(selection.sorted().into_iter().map(BrickEdit::Remove).collect()));
+    }
+    if actions.just_pressed(Action::Redo) {
+        steps.write(HistoryStep::Redo);
+    } else if actions.just_pressed(Action::Undo) {
+        steps.write(HistoryStep::Undo);
+    }
+}
+
+pub fn apply_brick_edits(
+    mut commands: Commands,
+    mut batches: MessageReader<BrickEdits>,
+    mut steps: MessageReader<HistoryStep>,
+    mut history: ResMut<EditHistory>,
+    mut bvh_query: Query<&mut SaveBVH>,
+    mut chunks: Query<(Entity, &ChunkMesh, &mut Mesh3d)>,
+    mut pending: Query<&mut ChunkEntity>,
+    lights: Query<(Entity, &Light, Has<Disabled>)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    assets: Res<SceneAssets>,
+    mut recataloged: MessageWriter<BricksRecataloged>,
+) {
+    // history belongs to the build it was recorded on
+    let stale = bvh_query.iter_mut().next().is_none_or(|save_bvh| save_bvh.is_added());
+    if stale && (history.can_undo() || history.can_redo()) {
+        *history = EditHistory::default();
+    }
+
+    let Ok(mut save_bvh) = bvh_query.single_mut() else {
+        batches.clear();
+        steps.clear();
+        return;
+    };
+    // Other systems (color-by) can leave chunks dirty without an edit; only
+    // take the build mutably when there's work, so it isn't flagged changed
+    // every frame.
+    if batches.is_empty() && steps.is_empty() && !save_bvh.generator.has_dirty() {
+        return;
+    }
+    let build = &mut save_bvh.generator;
+    let history = &mut *history;
+
+    let mut touched = HashSet::default();
+    let mut catalog_changed = false;
+    for batch in batches.read() {
+        let inverse = apply_batch(build, &batch.0, &mut touched);
+        catalog_changed |= changes_catalog(&inverse);
+        if !inverse.is_empty() {
+            history.undo.push(inverse);
+            history.redo.clear();
+        }
+    }
+    for step in steps.read() {
+        let (from, to) = match step {
+            HistoryStep::Undo => (&mut history.undo, &mut history.redo),
+            HistoryStep::Redo => (&mut history.redo, &mut history.undo),
+        };
+        let Some(batch) = from.pop() else { continue; };
+        let inverse = apply_batch(build, &batch, &mut touched);
+        catalog_changed |= changes_catalog(&inverse);
+        if !inverse.is_empty() {
+            to.push(inverse);
+        }
+    }
+    if catalog_changed {
+        recataloged.write(BricksRecataloged);
+    }
+
+    sync_brick_lights(&mut commands, &lights, build, |i| touched.contains(&i));
+
+    let mut rebuilt: HashMap<ChunkKey, Option<Mesh>> = build.take_dirty_meshes().into_iter().collect();
+    if rebuilt.is_empty() {
+        return;
+    }
+    info!("Re-meshed {} chunks", rebuilt.len());
+
+    // Chunks still queued in `spawn_chunks` take the new mesh there, otherwise
+    // the stale one would be spawned after the edit.
+    for mut chunk_entity in &mut pending {
+        chunk_entity.meshes.retain_mut(|(key, mesh)| match rebuilt.remove(key) {
+            Some(Some(new_mesh)) => {
+                *mesh = new_mesh;
+                true
+            },
+            Some(None) => false,
+            None => true,
+        });
+    }
+
+    for (entity, chunk, mut mesh3d) in &mut chunks {
+        match rebuilt.remove(&chunk.0) {
+            Some(Some(mesh)) => mesh3d.0 = meshes.add(mesh),
+            Some(None) => commands.entity(entity).despawn(),
+            None => {}
+        }
+    }
+
+    // whatever's left is a chunk that had no mesh before the edit
+    for (key, mesh) in rebuilt {
+        let Some(mesh) = mesh else { continue; };
+        commands.spawn((
+            Mesh3d(meshes.add(mesh)),
+            MeshMaterial3d(assets.materials.for_slot(key.0)),
+            ChunkMesh(key),
+        ));
+    }
+}
+
+// Lights follow their brick: off while it's removed, hidden or filtered out.
+// Disabling rather than hiding leaves `/lights`' on/off state alone. Only
+// lights whose brick passes `check` are looked at.
+pub fn sync_brick_lights(
+    commands: &mut Commands,
+    lights: &Query<(Entity, &Light, Has<Disabled>)>,
+    build: &BVHMeshGenerator,
+    check: impl Fn(usize) -> bool,
+) {
+    for (entity, light, disabled) in lights {
+        if !check(light.0) {
+            continue;
+        }
+        let off = !build.is_shown(light.0);
+        if off && !disabled {
+            commands.entity(entity).insert(Disabled);
+        } else if !off && disabled {
+            commands.entity(entity).remove::<Disabled>();
+        }
+    }
+}
+
+// Apply a batch and return the edits that undo it, in reverse order. Edits
+// that change nothing (removing an already removed brick, say) leave nothing
+// to undo.
+fn apply_batch(build: &mut BVHMeshGenerator, batch: &[BrickEdit], touched: &mut HashSet<usize>) -> Vec<BrickEdit> {
+    let mut inverse = Vec::new();
+    for edit in batch {
+        if edit.target().is_some_and(|i| i >= build.save_data.bricks.len()) {
+            warn!("Ignoring edit for a brick that doesn't exist: {:?}", edit);
+            continue;
+        }
+        let undo = match edit.clone() {
+            BrickEdit::Remove(i) => build.remove_brick(i).then_some(BrickEdit::Restore(i)),
+            BrickEdit::Restore(i) => build.restore_brick(i).then_some(BrickEdit::Remove(i)),
+            BrickEdit::Add(brick) => Some(BrickEdit::Remove(build.add_brick(brick))),
+            BrickEdit::SetColor(i, color) => {
+                (build.save_data.bricks[i].color != color).then(|| BrickEdit::SetColor(i, build.set_color(i, color)))
+            },
+            BrickEdit::SetVisible(i, visible) => {
+                let old = build.set_visible(i, visible);
+                (old != visible).then_some(BrickEdit::SetVisible(i, old))
+            },
+            BrickEdit::SetMaterial(i, material) => {
+                let old = build.set_material(i, material);
+                (old != material).then_some(BrickEdit::SetMaterial(i, old))
+            },
+        };
+        if let Some(undo) = undo {
+            if let Some(i) = undo.target() {
+                touched.insert(i);
+            }
+            inverse.push(undo);
+        }
+    }
+    inverse.reverse();
+    inverse
+}
+
+// Whether applied edits, given by their inverse, touched anything the filter
+// catalogs; hiding a brick doesn't.
+fn changes_catalog(inverse: &[BrickEdit]) -> bool {
+    inverse.iter().any(|edit| !matches!(edit, BrickEdit::SetVisible(..)))
+}
+
+// Ask where to save the edited build and write it off the main thread.
+// `save_data` should come from `BVHMeshGenerator::export_save`.
+fn export_with_dialog(save_data: SaveData, directory: Option<PathBuf>) {
+    thread::spawn(move || {
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("Brickadia Save", &["brs"])
+            .add_filter("Brickadia Prefab", &["brz"])
+            .set_file_name("export.brs");
+        if let Some(directory) = directory {
+            dialog = dialog.set_directory(directory);
+        }
+        let Some(path) = dialog.save_file() else { return; };
+        write_export(save_data, &path);
+    });
+}
+
+fn write_export(save_data: SaveData, path: &Path) {
+    let bricks = save_data.bricks.len();
+    if crate::save_extension(path) == "brz" {
+        for loss in crate::brdb_load::brz_losses(&save_data) {
+            warn!("Export to {}: {loss}", path.display());
+        }
+    }
+    match crate::write_save_data(save_data, path) {
+        Ok(()) => info!("Exported {} bricks to {}", bricks, path.display()),
+        Err(e) => error!("Export to {} failed: {}", path.display(), e),
+    }
+}
@@ -74,11 +74,7 @@ impl Face {
     }
 
     pub fn calc_2d(&mut self) {
-        let v = Vec3::new(666.0, 69.0, 420.0);
-
-        // Calculate the cross product U = N X V
-        let u = self.normal.abs().cross(v).normalize();
-        let v = v.normalize();
+        let (u, v) = projection_axes(self.normal);
 
         self.verts_2d.reserve_exact(self.verts.len());
         for p in &self.verts {
@@ -86,6 +82,27 @@ impl Face {
         }
     }
 
+    // Distance along the ray to where it crosses this face, if it does. The
+    // plane comes from the verts rather than the quantized `normal` so sloped
+    // faces are hit exactly; the polygon test reuses the `calc_2d` projection.
+    pub fn ray_distance(&self, ray: Ray3d) -> Option<f32> {
+        let a = self.verts[0];
+        let plane_normal = (self.verts[1] - a).cross(self.verts[2] - a);
+        let denom = plane_normal.dot(*ray.direction);
+        if denom.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let t = (a - ray.origin).dot(plane_normal) / denom;
+        if t < 0.0 {
+            return None;
+        }
+
+        let point = ray.origin + *ray.direction * t;
+        let (u, v) = projection_axes(self.normal);
+        point_inside_face(&Vec2::new(u.dot(point), v.dot(point)), &self.verts_2d).then_some(t)
+    }
+
     pub fn inside(&self, other: &Face) -> bool {
         // check opposite coplanar and coincident
         if self.normal != other.normal.neg() || !self.coincident_planes(other) {
@@ -147,6 +164,14 @@ impl Eq for Face {
 
 }
 
+fn projection_axes(normal: Vec3) -> (Vec3, Vec3) {
+    let v = Vec3::new(666.0, 69.0, 420.0);
+
+    // Calculate the cross product U = N X V
+    let u = normal.abs().cross(v).normalize();
+    (u, v.normalize())
+}
+
 fn point_inside_face(point: &Vec2, face: &Vec<Vec2>) -> bool {
     let n = face.len();
     let mut num_intersections = 0;
@@ -0,0 +1,268 @@
+use bevy::{ecs::entity_disabling::Disabled, platform::collections::{HashMap, HashSet}, prelude::*};
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use rayon::prelude::*;
+
+use crate::{
+    bvh::BVHMeshGenerator,
+    components::Light,
+    console::{on_off, AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand},
+    edit::{apply_brick_edits, sync_brick_lights, BricksRecataloged},
+    state::Screenshotting,
+    utils::{asset_name, brick_color, hex_color, material_name, owner_name},
+    SaveBVH,
+};
+
+// View filters: hide or isolate bricks by owner, asset, material or color.
+// Filtered bricks stay in the save (and in exports) but leave the BVH and the
+// meshes, so their neighbors re-cull as if they weren't there. Opened with
+// `/filter`.
+
+const KINDS: [&str; 4] = ["Owners", "Assets", "Materials", "Colors"];
+const COLOR: usize = 3;
+const LIST_HEIGHT: f32 = 180.0;
+
+pub struct FilterPlugin;
+
+// Names hidden per kind, so a filter survives edits and re-cataloguing. A
+// new build starts unfiltered.
+#[derive(Resource, Default)]
+pub struct BrickFilter {
+    hidden: [HashSet<String>; 4],
+}
+
+impl BrickFilter {
+    pub fn is_empty(&self) -> bool {
+        self.hidden.iter().all(|names| names.is_empty())
+    }
+
+    pub fn clear(&mut self) {
+        for names in &mut self.hidden {
+            names.clear();
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct FilterPanelOpen(pub bool);
+
+// One filterable property: its distinct values with brick counts, most
+// common first, and which value each brick has.
+#[derive(Default)]
+struct Category {
+    names: Vec<String>,
+    counts: Vec<usize>,
+    of_brick: Vec<u32>,
+}
+
+#[derive(Resource, Default)]
+struct FilterCatalog {
+    categories: [Category; 4],
+}
+
+impl Plugin for FilterPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<BrickFilter>()
+            .init_resource::<FilterPanelOpen>()
+            .init_resource::<FilterCatalog>()
+            .add_systems(Update, (catalog_bricks, apply_filter).chain().after(apply_brick_edits))
+            .add_systems(EguiPrimaryContextPass, filter_panel)
+            .add_console_command(ConsoleCommand::new("/filter", "toggle the owner/asset/material/color filter panel").arg(Arg::switch()), filter_command)
+            .add_console_command(ConsoleCommand::new("/filter reset", "show every filtered brick again"), filter_reset_command);
+    }
+}
+
+fn filter_command(
+    In(args): In<CommandArgs>,
+    mut panel: ResMut<FilterPanelOpen>,
+) -> CommandResult {
+    panel.0 = args.switch(panel.0);
+    Ok(format!("filter panel {}", on_off(panel.0)))
+}
+
+fn filter_reset_command(
+    In(_): In<CommandArgs>,
+    mut filter: ResMut<BrickFilter>,
+) -> CommandResult {
+    filter.clear();
+    Ok("showing all bricks".into())
+}
+
+// Catalog a new build, and again after edits that add or remove bricks or
+// change their color or material.
+fn catalog_bricks(
+    bvh_query: Query<Ref<SaveBVH>>,
+    mut recataloged: MessageReader<BricksRecataloged>,
+    mut catalog: ResMut<FilterCatalog>,
+    mut filter: ResMut<BrickFilter>,
+) {
+    let edited = recataloged.read().count() > 0;
+    let Ok(save_bvh) = bvh_query.single() else {
+        if !catalog.categories[0].of_brick.is_empty() {
+            *catalog = FilterCatalog::default();
+        }
+        return;
+    };
+    if save_bvh.is_added() && !filter.is_empty() {
+        filter.clear();
+    }
+    if !save_bvh.is_added() && !edited {
+        return;
+    }
+    let build = &save_bvh.generator;
+    let save_data = &build.save_data;
+    catalog.categories = [
+        categorize(build, |i| owner_name(save_data, &save_data.bricks[i]).to_string()),
+        categorize(build, |i| asset_name(save_data, &save_data.bricks[i]).to_string()),
+        categorize(build, |i| material_name(save_data, &save_data.bricks[i]).to_string()),
+        categorize(build, |i| hex_color(brick_color(save_data, &save_data.bricks[i]))),
+    ];
+}
+
+fn categorize(build: &BVHMeshGenerator, name_of: impl Fn(usize) -> String + Sync) -> Category {
+    let names: Vec<String> = (0..build.save_data.bricks.len()).into_par_iter().map(&name_of).collect();
+
+    let mut ids: HashMap<&str, u32> = HashMap::default();
+    let mut unsorted: Vec<(&str, usize)> = Vec::new();
+    let of_brick: Vec<u32> = names.iter().enumerate()
+        .map(|(i, name)| {
+            let id = *ids.entry(name.as_str()).or_insert_with(|| {
+                unsorted.push((name.as_str(), 0));
+                (unsorted.len() - 1) as u32
+            });
+            if !build.is_removed(i) {
+                unsorted[id as usize].1 += 1;
+            }
+            id
+        })
+        .collect();
+
+    // renumber so ids follow the sorted order
+    let mut order: Vec<usize> = (0..unsorted.len()).collect();
+    order.sort_unstable_by(|&a, &b| unsorted[b].1.cmp(&unsorted[a].1).then_with(|| unsorted[a].0.cmp(unsorted[b].0)));
+    let mut rank = vec![0u32; order.len()];
+    for (new, &old) in order.iter().enumerate() {
+        rank[old] = new as u32;
+    }
+
+    Category {
+        names: order.iter().map(|&old| unsorted[old].0.to_string()).collect(),
+        counts: order.iter().map(|&old| unsorted[old].1).collect(),
+        of_brick: of_brick.into_iter().map(|id| rank[id as usize]).collect(),
+    }
+}
+
+fn apply_filter(
+    mut commands: Commands,
+    filter: Res<BrickFilter>,
+    catalog: Res<FilterCatalog>,
+    mut bvh_query: Query<&mut SaveBVH>,
+    lights: Query<(Entity, &Light, Has<Disabled>)>,
+) {
+    if !filter.is_changed() && !catalog.is_changed() {
+        return;
+    }
+    let Ok(mut save_bvh) = bvh_query.single_mut() else { return; };
+
+    let hidden: Vec<Vec<bool>> = catalog.categories.iter().zip(&filter.hidden)
+        .map(|(category, names)| category.names.iter().map(|name| names.contains(name)).collect())
+        .collect();
+    let mask: Vec<bool> = (0..save_bvh.generator.save_data.bricks.len())
+        .map(|i| {
+            catalog.categories.iter().zip(&hidden)
+                .any(|(category, hidden)| category.of_brick.get(i).is_some_and(|&id| hidden[id as usize]))
+        })
+        .collect();
+    // only borrow the build mutably when something moves, so it isn't flagged
+    // changed for nothing
+    if save_bvh.generator.filter() == mask.as_slice() {
+        return;
+    }
+
+    let build = &mut save_bvh.generator;
+    build.set_filter(mask);
+    sync_brick_lights(&mut commands, &lights, build, |_| true);
+    info!("Filtering out {} bricks", build.filter().iter().filter(|&&hidden| hidden).count());
+}
+
+fn filter_panel(
+    mut open: ResMut<FilterPanelOpen>,
+    mut filter: ResMut<BrickFilter>,
+    catalog: Res<FilterCatalog>,
+    bvh_query: Query<&SaveBVH>,
+    screenshotting: Res<Screenshotting>,
+    mut contexts: EguiContexts,
+) {
+    if !open.0 || screenshotting.0 {
+        return;
+    }
+    let Ok(save_bvh) = bvh_query.single() else { return; };
+    let Ok(ctx) = contexts.ctx_mut() else { return; };
+
+    let filtered = save_bvh.generator.filter().iter().filter(|&&hidden| hidden).count();
+    // (kind, name, isolate): isolating hides every other value of that kind
+    let mut toggle: Option<(usize, String, bool)> = None;
+    let mut reset = false;
+
+    egui::Window::new("Filter")
+        .open(&mut open.0)
+        .default_pos(egui::pos2(12.0, 360.0))
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("{filtered} bricks hidden"));
+                if ui.add_enabled(!filter.is_empty(), egui::Button::new("Show all")).clicked() {
+                    reset = true;
+                }
+            });
+            for (kind, title) in KINDS.iter().enumerate() {
+                let category = &catalog.categories[kind];
+                ui.collapsing(format!("{title} ({})", category.names.len()), |ui| {
+                    egui::ScrollArea::vertical()
+                        .id_salt(title)
+                        .max_height(LIST_HEIGHT)
+                        .show(ui, |ui| {
+                            for (name, count) in category.names.iter().zip(&category.counts) {
+                                ui.horizontal(|ui| {
+                                    let mut shown = !filter.hidden[kind].contains(name);
+                                    if kind == COLOR {
+                                        if let Ok(color) = egui::Color32::from_hex(name) {
+                                            swatch(ui, color);
+                                        }
+                                    }
+                                    if ui.checkbox(&mut shown, format!("{name} ({count})")).changed() {
+                                        toggle = Some((kind, name.clone(), false));
+                                    }
+                                    if ui.small_button("only").clicked() {
+                                        toggle = Some((kind, name.clone(), true));
+                                    }
+                                });
+                            }
+                        });
+                });
+            }
+        });
+
+    if reset {
+        filter.clear();
+    }
+    match toggle {
+        Some((kind, name, true)) => {
+            filter.hidden[kind] = catalog.categories[kind].names.iter()
+                .filter(|other| **other != name)
+                .cloned()
+                .collect();
+        },
+        Some((kind, name, false)) => {
+            if !filter.hidden[kind].remove(&name) {
+                filter.hidden[kind].insert(name);
+            }
+        },
+        None => {}
+    }
+}
+
+fn swatch(ui: &mut egui::Ui, color: egui::Color32) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(12.0, 12.0), egui::Sense::hover());
+    ui.painter().rect_filled(rect, 2.0, color);
+}
@@ -2,6 +2,8 @@ use bevy::prelude::*;
 use bevy::diagnostic::DiagnosticsStore;
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
 
+use crate::controls::{Action, Actions};
+
 /// Marker to find the container entity so we can show/hide the FPS counter
 #[derive(Component)]
 struct FpsRoot;
@@ -89,12 +91,12 @@ fn fps_text_update_system(
     }
 }
 
-/// Toggle the FPS counter when pressing F12
+/// Toggle the FPS counter when pressing F12 (or what it's rebound to)
 fn fps_counter_showhide(
     mut q: Query<&mut Visibility, With<FpsRoot>>,
-    kbd: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
 ) {
-    if kbd.just_pressed(KeyCode::F12) {
+    if actions.just_pressed(Action::ToggleFps) {
         let Ok(mut vis) = q.single_mut() else { return; };
         *vis = match *vis {
             Visibility::Hidden => Visibility::Visible,
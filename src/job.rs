@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::{anim::AnimationRequest, cam::{CameraTween, FrameView}, poster::PosterRequest, queue_load, LoadFailed, SaveBVH};
+
+// Renders run from the command line: `brs_iso poster` and `brs_iso animate`
+// open the viewer on one save, wait for it to load and be framed, then ask
+// for the render as if it had been typed into the console. Whoever finishes
+// the render quits the app when a job is present. A poster is framed for its
+// own aspect rather than the window's. A save that won't load fails the job.
+
+// Frames to let the loaded build settle (meshes spawned, framing tween done)
+// before the render starts.
+const JOB_SETTLE_FRAMES: u32 = 30;
+
+pub struct JobPlugin;
+
+#[derive(Debug, Clone)]
+pub enum JobRender {
+    Poster(PosterRequest),
+    Animation(AnimationRequest),
+}
+
+// The save to load and what to render from it.
+#[derive(Resource, Debug, Clone)]
+pub struct RenderJob {
+    pub save: PathBuf,
+    pub render: JobRender,
+}
+
+#[derive(Default)]
+enum JobPhase {
+    #[default]
+    Loading,
+    Settling(u32),
+    Rendering,
+}
+
+impl Plugin for JobPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Startup, start_render_job)
+            .add_systems(Update, run_render_job);
+    }
+}
+
+// Queue the job's save the same way the L key's file picker does.
+fn start_render_job(world: &mut World) {
+    let Some(job) = world.get_resource::<RenderJob>() else { return; };
+    let save = job.save.clone();
+    queue_load(world, save);
+}
+
+fn run_render_job(
+    job: Option<Res<RenderJob>>,
+    mut phase: Local<JobPhase>,
+    bvh_query: Query<(), With<SaveBVH>>,
+    tween_query: Query<(), With<CameraTween>>,
+    mut frame: MessageWriter<FrameView>,
+    mut posters: MessageWriter<PosterRequest>,
+    mut animations: MessageWriter<AnimationRequest>,
+    (mut failed, mut exit): (MessageReader<LoadFailed>, MessageWriter<AppExit>),
+) {
+    let Some(job) = job else { return; };
+    match *phase {
+        JobPhase::Loading => {
+            if failed.read().next().is_some() {
+                exit.write(AppExit::error());
+                return;
+            }
+            if bvh_query.is_empty() {
+                return;
+            }
+            if let JobRender::Poster(request) = &job.render {
+                let aspect = request.size.x as f32 / request.size.y as f32;
+                frame.write(FrameView { whole_build: true, aspect: Some(aspect) });
+            }
+            *phase = JobPhase::Settling(JOB_SETTLE_FRAMES);
+        },
+        JobPhase::Settling(n) => {
+            if !tween_query.is_empty() {
+                return;
+            }
+            *phase = if n > 0 {
+                JobPhase::Settling(n - 1)
+            } else {
+                match &job.render {
+                    JobRender::Poster(request) => { posters.write(request.clone()); },
+                    JobRender::Animation(request) => { animations.write(request.clone()); },
+                }
+                JobPhase::Rendering
+            };
+        },
+        JobPhase::Rendering => {}
+    }
+}
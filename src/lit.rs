@@ -1,13 +1,13 @@
 use bevy::{prelude::*, light::{cluster::GlobalClusterSettings, DirectionalLightShadowMap, CascadeShadowConfig, CascadeShadowConfigBuilder}};
 
-use crate::{bvh::BVHNode, cam::IsoCamera, state::{GameState, InputState}, SaveBVH};
+use crate::{cam::IsoCamera, components::Light, console::{on_off, set_visible, AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand}, controls::{Action, Actions}, settings::Settings, sky::Lighting, state::{GameState, InputState}, SaveBVH};
 
-const SHADOW_MAP_SIZE: usize = 8192;
-const SUN_ILLUMINANCE: f32 = 20000.0;
-// PCSS blocker-search radius in world units; tune for softer/harder shadow edges.
-const SUN_SOFT_SHADOW_SIZE: f32 = 20.0;
 // Depth range used before a save is loaded and cascade fitting kicks in.
 const DEFAULT_SHADOW_DISTANCE: f32 = 4000000.0;
+// A perspective frustum widens with depth, so it needs cascades to keep
+// shadows near the camera sharp. The first one covers this much of the range.
+const PERSPECTIVE_CASCADES: usize = 4;
+const PERSPECTIVE_FIRST_CASCADE: f32 = 0.04;
 
 pub struct LightPlugin;
 
@@ -17,13 +17,34 @@ pub struct Sun;
 impl Plugin for LightPlugin {
     fn build(&self, app: &mut App) {
         app
-            .insert_resource(DirectionalLightShadowMap { size: SHADOW_MAP_SIZE })
             // AmbientLight is a component in Bevy 0.19; it's placed on the camera.
             .add_systems(Startup, (spawn_light, raise_cluster_capacity))
-            .add_systems(Update, (animate_light_direction, fit_shadow_cascades));
+            .add_systems(Update, (animate_light_direction, fit_shadow_cascades, apply_light_settings))
+            .add_console_command(ConsoleCommand::new("/lights", "toggle brick lights").arg(Arg::switch()), lights_command)
+            .add_console_command(ConsoleCommand::new("/shadows", "toggle sun shadows").arg(Arg::switch()), shadows_command)
+            .add_console_command(
+                ConsoleCommand::new("/sun", "where the sun is, or put it at a heading and elevation in degrees")
+                    .arg(Arg::float("heading").optional())
+                    .arg(Arg::float("elevation").optional()),
+                sun_command,
+            );
     }
 }
 
+// The way the sun shines from `heading` and `elevation` in degrees. Headings
+// turn the same way as the camera's, so at the same heading the sun is
+// behind the camera.
+pub fn sun_direction(heading: f32, elevation: f32) -> Vec3 {
+    let (heading, elevation) = (heading.to_radians(), elevation.to_radians());
+    -Vec3::new(elevation.cos() * heading.cos(), elevation.sin(), elevation.cos() * heading.sin())
+}
+
+// Heading and elevation in degrees of a sun shining along `direction`.
+pub fn sun_angles(direction: Vec3) -> (f32, f32) {
+    let toward = -direction.normalize_or(Vec3::NEG_Y);
+    (toward.z.atan2(toward.x).to_degrees().rem_euclid(360.0), toward.y.clamp(-1.0, 1.0).asin().to_degrees())
+}
+
 // Saves can spawn hundreds of point/spot lights, which overflows the GPU
 // clustering lists sized for typical scenes; Bevy grows them on demand but
 // warns and may corrupt lighting for a few frames each time. Preallocate
@@ -36,7 +57,11 @@ fn raise_cluster_capacity(mut settings: ResMut<GlobalClusterSettings>) {
     }
 }
 
-fn spawn_light(mut commands: Commands) {
+fn spawn_light(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    lighting: Res<Lighting>,
+) {
 
     let mut shadow_light_transform = Transform::from_rotation(Quat::from_rotation_x(-1.079));
     shadow_light_transform.rotate_y(0.303);
@@ -45,14 +70,16 @@ fn spawn_light(mut commands: Commands) {
     commands.spawn((
         DirectionalLight {
             shadow_maps_enabled: true,
-            illuminance: SUN_ILLUMINANCE,
-            soft_shadow_size: Some(SUN_SOFT_SHADOW_SIZE),
+            color: lighting.sun_color(),
+            illuminance: lighting.illuminance,
+            soft_shadow_size: Some(settings.sun_soft_shadow_size),
             ..default()
         },
         // A single cascade: with an orthographic camera the view frustum has the
         // same cross-section at every depth, so extra cascades add shadow passes
         // without adding any texel density. The depth range is refit to the
-        // loaded scene every frame by fit_shadow_cascades.
+        // loaded scene every frame by fit_shadow_cascades, which also splits it
+        // into cascades for the perspective camera.
         CascadeShadowConfig::from(CascadeShadowConfigBuilder {
             num_cascades: 1,
             minimum_distance: 0.1,
@@ -68,15 +95,31 @@ fn spawn_light(mut commands: Commands) {
     // pre-0.14 model did). Ambient light on the camera provides gentle fill instead.
 }
 
+// The sun's shadows follow the settings window; its color and brightness
+// are the sky's.
+fn apply_light_settings(
+    settings: Res<Settings>,
+    mut shadow_map: ResMut<DirectionalLightShadowMap>,
+    mut sun_query: Query<&mut DirectionalLight, With<Sun>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    shadow_map.size = settings.shadow_map_size;
+    for mut sun in sun_query.iter_mut() {
+        sun.soft_shadow_size = Some(settings.sun_soft_shadow_size);
+    }
+}
+
 // Fit the shadow cascade's depth range to the loaded scene. The camera orbits at
 // a distance proportional to the scene size, so a static range either clips
 // casters or wastes depth precision on empty air.
 fn fit_shadow_cascades(
     mut sun_query: Query<&mut CascadeShadowConfig, With<Sun>>,
-    cam_query: Query<&Transform, With<IsoCamera>>,
+    cam_query: Query<(&Transform, &Projection), With<IsoCamera>>,
     bvh_query: Query<&SaveBVH>,
 ) {
-    let Ok(cam_transform) = cam_query.single() else { return; };
+    let Ok((cam_transform, projection)) = cam_query.single() else { return; };
     let Ok(mut config) = sun_query.single_mut() else { return; };
 
     let forward = cam_transform.forward().as_vec3();
@@ -84,7 +127,7 @@ fn fit_shadow_cascades(
     let mut max_depth = f32::MIN;
 
     for save_bvh in bvh_query.iter() {
-        let BVHNode::Internal { aabb, .. } = &save_bvh.bvh[0] else { continue; };
+        let Some(aabb) = save_bvh.generator.bvh.root_aabb() else { continue; };
         let center = aabb.center.as_vec3();
         let halfwidths = aabb.halfwidths.as_vec3();
 
@@ -109,22 +152,39 @@ fn fit_shadow_cascades(
     let new_min = (min_depth - margin).max(0.1);
     let new_max = max_depth + margin;
 
+    let cascades = match projection {
+        Projection::Orthographic(_) => 1,
+        _ => PERSPECTIVE_CASCADES,
+    };
+
     let tolerance = (new_max - new_min) * 0.01;
-    let unchanged = config.bounds.len() == 1
+    let unchanged = config.bounds.len() == cascades
         && (config.minimum_distance - new_min).abs() < tolerance
-        && (config.bounds[0] - new_max).abs() < tolerance;
+        && config.bounds.last().is_some_and(|&far| (far - new_max).abs() < tolerance);
     if unchanged {
         return;
     }
 
-    config.minimum_distance = new_min;
-    config.bounds = vec![new_max];
+    if cascades == 1 {
+        config.minimum_distance = new_min;
+        config.bounds = vec![new_max];
+    } else {
+        // inside a build the near corners are behind the eye, so the range
+        // starts at the clamp above and the first cascade hugs the camera
+        *config = CascadeShadowConfigBuilder {
+            num_cascades: cascades,
+            minimum_distance: new_min,
+            maximum_distance: new_max,
+            first_cascade_far_bound: new_min + (new_max - new_min) * PERSPECTIVE_FIRST_CASCADE,
+            ..default()
+        }.build();
+    }
 }
 
 fn animate_light_direction(
     time: Res<Time>,
     mut query: Query<&mut Transform, With<DirectionalLight>>,
-    keycode: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
     game_state: Res<GameState>,
 ) {
     match game_state.input {
@@ -135,9 +195,9 @@ fn animate_light_direction(
     }
 
     let mut dir = 0.0;
-    if keycode.pressed(KeyCode::ArrowLeft) {
+    if actions.pressed(Action::SunLeft) {
         dir = 1.;
-    } else if keycode.pressed(KeyCode::ArrowRight) {
+    } else if actions.pressed(Action::SunRight) {
         dir = -1.;
     }
 
@@ -149,3 +209,77 @@ fn animate_light_direction(
         transform.rotate_y(time.delta_secs() * dir);
     }
 }
+
+fn lights_command(
+    In(args): In<CommandArgs>,
+    mut light_query: Query<&mut Visibility, With<Light>>,
+) -> CommandResult {
+    let on = args.switch(light_query.iter().any(|visibility| *visibility == Visibility::Visible));
+    for mut visibility in light_query.iter_mut() {
+        set_visible(&mut visibility, on);
+    }
+    Ok(format!("lights {}", on_off(on)))
+}
+
+fn shadows_command(
+    In(args): In<CommandArgs>,
+    mut sun_query: Query<&mut DirectionalLight, With<Sun>>,
+) -> CommandResult {
+    let mut sun = sun_query.single_mut().map_err(|_| "no sun")?;
+    sun.shadow_maps_enabled = args.switch(sun.shadow_maps_enabled);
+    Ok(format!("shadows {}", on_off(sun.shadow_maps_enabled)))
+}
+
+// `/sun 30 45`; leaving out the elevation keeps the current one.
+fn sun_command(
+    In(args): In<CommandArgs>,
+    mut sun_query: Query<&mut Transform, With<Sun>>,
+) -> CommandResult {
+    let mut transform = sun_query.single_mut().map_err(|_| "no sun")?;
+    let (heading, elevation) = sun_angles(*transform.forward());
+    let Some(new_heading) = args.float("heading") else {
+        return Ok(format!("sun at heading {heading:.1}°, elevation {elevation:.1}°"));
+    };
+    let new_elevation = args.float("elevation").unwrap_or(elevation);
+    if !(-90.0..=90.0).contains(&new_elevation) {
+        return Err("elevation must be between -90° and 90°".into());
+    }
+    transform.look_to(sun_direction(new_heading, new_elevation), Vec3::Y);
+    Ok(format!("sun at heading {new_heading}°, elevation {new_elevation}°"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn sun_direction_shines_down_from_above() {
+        assert!(sun_direction(0.0, 90.0).abs_diff_eq(Vec3::NEG_Y, 1e-6));
+        assert!(sun_direction(0.0, 0.0).abs_diff_eq(Vec3::NEG_X, 1e-6));
+        assert!(sun_direction(90.0, 0.0).abs_diff_eq(Vec3::NEG_Z, 1e-6));
+        assert!(sun_direction(123.0, 45.0).y < 0.0);
+        assert!(close(sun_direction(200.0, -30.0).length(), 1.0));
+    }
+
+    #[test]
+    fn sun_angles_undo_sun_direction() {
+        for heading in [0.0, 45.0, 135.0, 200.0, 359.0] {
+            for elevation in [-60.0, 0.0, 30.0, 89.0] {
+                let (h, e) = sun_angles(sun_direction(heading, elevation));
+                assert!(close(h, heading) && close(e, elevation), "{heading} {elevation} -> {h} {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn sun_angles_take_any_length_and_default_to_noon() {
+        let (h, e) = sun_angles(sun_direction(70.0, 20.0) * 5.0);
+        assert!(close(h, 70.0) && close(e, 20.0));
+        assert_eq!(sun_angles(Vec3::ZERO).1, 90.0);
+        assert!(sun_angles(Vec3::new(1.0, 0.0, 0.0)).0 >= 0.0);
+    }
+}
@@ -1,61 +1,87 @@
 mod aabb;
+mod anim;
 mod asset_loader;
+mod bookmark;
 mod brdb_load;
 mod bvh;
 mod cam;
 mod chat;
+mod cli;
+mod clip;
+mod colorby;
 mod components;
+mod console;
+mod construct;
+mod controls;
+mod edit;
 mod faces;
+mod filter;
 mod icon;
+mod job;
 mod pos;
+mod poster;
+mod remote;
+mod screenshot;
+mod script;
+mod selection;
 mod state;
 mod settings;
+mod sky;
 mod fps;
 mod lit;
 mod utils;
 mod viewcube;
 
-use std::{path::PathBuf, io::BufReader, fs::File, sync::mpsc::{Receiver, self}, thread};
+use std::{error::Error, path::{Path, PathBuf}, io::{BufReader, BufWriter}, fs::File, sync::mpsc::{Receiver, self}, thread};
 
-use aabb::AABB;
 use asset_loader::{AssetLoaderPlugin, SceneAssets};
-use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, pbr::DefaultOpaqueRendererMethod, prelude::*, tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task}, window::{PrimaryWindow, WindowResolution}, winit::WinitWindows};
+use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, log::LogPlugin, pbr::DefaultOpaqueRendererMethod, prelude::*, tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task}, window::{PrimaryWindow, WindowResolution}, winit::WinitWindows};
 use bevy_egui::{egui, EguiContexts, EguiPlugin, EguiPrimaryContextPass};
 use bevy_embedded_assets::EmbeddedAssetPlugin;
-use brickadia::{save::SaveData, read::SaveReader};
+use brickadia::{save::SaveData, read::SaveReader, write::SaveWriter};
 use bvh::{BVHNode, BVH};
 use cam::{IsoCamera, IsoCameraPlugin};
 use chat::ChatPlugin;
+use controls::{Action, Actions};
 use fps::FPSPlugin;
 use lit::LightPlugin;
-use settings::SettingsPlugin;
+use settings::{Settings, SettingsPlugin};
 use state::{BVHView, GameState, InputState};
 use winit::window::Icon;
 
-use crate::{components::{gen_point_lights, gen_spot_lights, Light}, bvh::BVHMeshGenerator};
+use crate::{components::{gen_point_lights, gen_spot_lights, Light}, bvh::{BVHMeshGenerator, ChunkKey}};
 
 #[derive(Component, Debug)]
 struct ChunkEntity {
-    meshes: Vec<Mesh>,
-    material: Handle<StandardMaterial>,
+    meshes: Vec<(ChunkKey, Mesh)>,
+    material: Handle<clip::BrickMaterial>,
 }
 
 #[derive(Component)]
 struct SaveBVH {
-    save_data: SaveData,
-    pub bvh: BVH,
-    aabbs: Vec<AABB>,
-    com: Vec3
+    // the save plus its faces, boxes and BVH; kept after meshing for picking
+    // and edits
+    generator: BVHMeshGenerator,
+    com: Vec3,
+    // where it was loaded from, for sidecar files like camera bookmarks
+    path: PathBuf,
 }
 
 
 // Result of the off-thread mesh-gen task; consumed by `poll_gen_task`.
 struct LoadedBuild {
-    save_data: SaveData,
-    material_meshes: Vec<Vec<Mesh>>,
+    generator: BVHMeshGenerator,
+    material_meshes: Vec<Vec<(ChunkKey, Mesh)>>,
     com: Vec3,
-    bvh: BVH,
-    aabbs: Vec<AABB>,
+    path: PathBuf,
+}
+
+// A picked or queued save that couldn't be read; loading is over, so
+// scripts and jobs waiting on it give up.
+#[derive(Message, Debug, Clone)]
+pub struct LoadFailed {
+    pub path: PathBuf,
+    pub error: String,
 }
 
 // Holds the in-flight gen task so the main thread stays responsive (spinner
@@ -69,8 +95,9 @@ struct Water;
 #[derive(Component)]
 struct Ground;
 
+// One spawned chunk mesh, keyed so edits can swap it out.
 #[derive(Component)]
-struct ChunkMesh;
+struct ChunkMesh(ChunkKey);
 
 #[derive(Component)]
 struct LoadPrompt;
@@ -82,23 +109,48 @@ struct LoadPromptText;
 
 // ASCII spinner frames (braille glyphs aren't in the default font), cycled while loading.
 const SPINNER_FRAMES: [&str; 4] = ["|", "/", "-", "\\"];
-const LOAD_PROMPT_IDLE: &str = "Press L to load a build";
 
-// Bottom-left controls hint, shown once a build is loaded.
+// Bottom-left controls hint, shown once a build is loaded. Its text follows
+// the bindings in the settings.
 #[derive(Component)]
 struct ControlsHint;
 
-const CONTROLS_HINT: &str = "\
-WASD  move
-Q / E or right-drag  rotate
-scroll  zoom
-shift  faster
-H  recenter
-F2  screenshot
-F3  hi-res screenshot";
+fn load_prompt_idle(settings: &Settings) -> String {
+    match settings.bindings.get(Action::LoadSave).first() {
+        Some(binding) => format!("Press {} to load a build", binding.label()),
+        None => "Type /load to load a build".into(),
+    }
+}
 
 fn main() {
-    App::new()
+    // Subcommands (`info`, `convert`, `dump`) run without a window and exit;
+    // `poster` and `animate` run the viewer on one save and exit when the
+    // render is written. `--exec <script>` and `--remote <port>` can come
+    // before any of them.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (args, options) = match cli::split_options(args) {
+        Ok(split) => split,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        }
+    };
+    let job = match cli::viewer_job(&args) {
+        Some(Ok(job)) => Some(job),
+        Some(Err(e)) => {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        }
+        None => None,
+    };
+    if job.is_none() {
+        if let Some(code) = cli::run(&args) {
+            std::process::exit(code);
+        }
+    }
+
+    let mut app = App::new();
+    app
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "Brickadia Isometric Viewer".into(),
@@ -112,6 +164,10 @@ fn main() {
                 ..default()
             }),
             ..default()
+        }).set(LogPlugin {
+            // so `/log mirror` can show it in the console
+            custom_layer: chat::log_layer,
+            ..default()
         }))
         // MSAA is disabled per-camera (Msaa::Off) since it's incompatible with
         // deferred rendering; FXAA is used instead.
@@ -121,24 +177,30 @@ fn main() {
         .init_resource::<state::Loading>()
         .init_resource::<state::BrickInfoEnabled>()
         .init_resource::<state::Screenshotting>()
-        .insert_resource(GlobalVolume::new(bevy::audio::Volume::Linear(0.2)))
-        .add_plugins((LightPlugin, AssetLoaderPlugin, ChatPlugin, SettingsPlugin, IsoCameraPlugin, viewcube::ViewCubePlugin))
-        .add_plugins((FrameTimeDiagnosticsPlugin::default(), FPSPlugin))
+        .add_message::<LoadFailed>()
+        .add_plugins((LightPlugin, AssetLoaderPlugin, ChatPlugin, SettingsPlugin, IsoCameraPlugin, viewcube::ViewCubePlugin, edit::EditPlugin, selection::SelectionPlugin, colorby::ColorByPlugin, clip::ClipPlugin, construct::ConstructPlugin, filter::FilterPlugin, bookmark::BookmarkPlugin))
+        .add_plugins((FrameTimeDiagnosticsPlugin::default(), FPSPlugin, poster::PosterPlugin, job::JobPlugin, anim::AnimPlugin, script::ScriptPlugin, remote::RemotePlugin, sky::SkyPlugin))
         .add_plugins(EguiPlugin::default())
         .add_plugins(EmbeddedAssetPlugin::default())
         .add_systems(Update, set_window_icon)
         .add_systems(PostStartup, setup)
         .add_systems(Update, (pick_path, load_brs, load_save, poll_gen_task, spawn_chunks, move_water))
-        .add_systems(Update, (bvh_gizmos, change_depth, spotlight_gizmos, light_gizmos, toggle_load_prompt))
+        .add_systems(Update, (bvh_gizmos, change_depth, spotlight_gizmos, light_gizmos, toggle_load_prompt, update_controls_hint))
         // egui UI must run in the primary-context pass under bevy_egui's multi-pass mode
-        .add_systems(EguiPrimaryContextPass, brick_info)
-        .run();
+        .add_systems(EguiPrimaryContextPass, brick_info);
+    if let Some(job) = job {
+        app.insert_resource(job);
+    }
+    app.insert_resource(script::StartupScripts(options.scripts));
+    app.insert_resource(remote::StartupRemote(options.remote));
+    app.run();
 }
 
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     assets: Res<SceneAssets>,
+    settings: Res<Settings>,
 ) {
     commands.spawn((
         AudioPlayer::new(assets.sounds.startup.clone()),
@@ -176,7 +238,7 @@ fn setup(
         Pickable::IGNORE,
     )).with_child((
         LoadPromptText,
-        Text::new(LOAD_PROMPT_IDLE),
+        Text::new(load_prompt_idle(&settings)),
         TextFont { font_size: FontSize::Px(28.0), ..default() },
         TextColor(Color::WHITE),
     ));
@@ -193,7 +255,7 @@ fn setup(
             ..default()
         },
         Pickable::IGNORE,
-        Text::new(CONTROLS_HINT),
+        Text::new(controls::controls_hint(&settings.bindings)),
         TextFont { font_size: FontSize::Px(14.0), ..default() },
         TextColor(Color::srgba(1., 1., 1., 0.65)),
     ));
@@ -205,6 +267,7 @@ fn toggle_load_prompt(
     build_loaded: Res<state::BuildLoaded>,
     loading: Res<state::Loading>,
     time: Res<Time>,
+    settings: Res<Settings>,
     mut prompt: Query<&mut Visibility, (With<LoadPrompt>, Without<ControlsHint>)>,
     mut label: Query<&mut Text, With<LoadPromptText>>,
     mut controls: Query<&mut Visibility, (With<ControlsHint>, Without<LoadPrompt>)>,
@@ -226,10 +289,25 @@ fn toggle_load_prompt(
         let frame = (time.elapsed_secs() * 12.0) as usize % SPINNER_FRAMES.len();
         text.0 = SPINNER_FRAMES[frame].to_string();
     } else {
-        text.0 = LOAD_PROMPT_IDLE.to_string();
+        text.0 = load_prompt_idle(&settings);
+    }
+}
+
+fn update_controls_hint(
+    settings: Res<Settings>,
+    mut hint: Query<&mut Text, With<ControlsHint>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = hint.single_mut() {
+        text.0 = controls::controls_hint(&settings.bindings);
     }
 }
 
+// Outline of the face under the cursor in the brick info view.
+const PICK_COLOR: Color = Color::srgb(1.0, 0.62, 0.15);
+
 fn brick_info(
     window_query: Query<&Window, With<PrimaryWindow>>,
     cameras: Query<(&Camera, &GlobalTransform), With<IsoCamera>>,
@@ -237,6 +315,7 @@ fn brick_info(
     viewcube_hover: Res<viewcube::ViewCubeHover>,
     brick_info_enabled: Res<state::BrickInfoEnabled>,
     screenshotting: Res<state::Screenshotting>,
+    clip: Res<clip::Clip>,
     mut contexts: EguiContexts,
     mut gizmos: Gizmos,
 ) {
@@ -265,33 +344,40 @@ fn brick_info(
             let ray = camera.viewport_to_world(camera_transform, mouse_pos);
 
             if let Ok(ray) = ray {
-                let brick_index = save_bvh.bvh.intersection(ray, &save_bvh.aabbs);
-                if let Some(brick_index) = brick_index {
-                    let brick = &save_bvh.save_data.bricks[brick_index];
-                    let asset_name = &save_bvh.save_data.header2.brick_assets[brick.asset_name_index as usize];
-
-                    let owner = if brick.owner_index == 0 {
-                        "PUBLIC"
-                    } else {
-                        save_bvh.save_data.header2.brick_owners[brick.owner_index as usize - 1].name.as_str()
-                    };
+                let build = &save_bvh.generator;
+                // skip whatever the section planes cut away
+                let section = clip.frustum();
+                let hit = build.bvh.raycast(ray, &build.aabbs, &build.faces, |point| section.contains_point(point));
+                if let Some(hit) = hit {
+                    let brick = &build.save_data.bricks[hit.brick];
+                    let asset_name = &build.save_data.header2.brick_assets[brick.asset_name_index as usize];
+                    let owner = utils::owner_name(&build.save_data, brick);
 
                     if let Ok(ctx) = contexts.ctx_mut() {
                         egui::Window::new("Brick Info").show(ctx, |ui| {
-                            ui.label(format!("Brick index: {}", brick_index));
+                            ui.label(format!("Brick index: {}", hit.brick));
                             ui.label(format!("Brick position: {:?}", brick.position));
                             ui.label(format!("Brick size: {:?}", brick.size));
                             ui.label(format!("Brick asset: {}", asset_name));
                             ui.label(format!("Brick owner: {}", owner));
+                            // save axes (Z up), to match the brick position
+                            ui.label(format!("Hit point: ({:.1}, {:.1}, {:.1})", hit.point.x, hit.point.z, hit.point.y));
+                            ui.label(format!("Face: {} (normal {:.2}, {:.2}, {:.2})", hit.face, hit.normal.x, hit.normal.z, hit.normal.y));
                         });
                     }
-    
-                    let aabb = save_bvh.aabbs[brick_index];
+
+                    let aabb = build.aabbs[hit.brick];
                     gizmos.primitive_3d(
                         &Cuboid { half_size: aabb.halfwidths.as_vec3() },
                         aabb.center.as_vec3(),
                         Color::WHITE,
                     );
+
+                    // outline the exact face under the cursor
+                    let face = &build.faces[hit.brick][hit.face];
+                    let outline = face.verts.iter().chain(face.verts.first()).copied();
+                    gizmos.linestrip(outline, PICK_COLOR);
+                    gizmos.arrow(hit.point, hit.point + hit.normal * 10.0, PICK_COLOR);
                 }
             }
         }
@@ -320,7 +406,7 @@ fn set_window_icon(
 
 fn move_water(
     mut query: Query<&mut Transform, With<Water>>,
-    keycode: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
     game_state: Res<GameState>,
     time: Res<Time>,
 ) {
@@ -329,13 +415,13 @@ fn move_water(
     }
 
     let mut movement = Vec3::ZERO;
-    if keycode.pressed(KeyCode::KeyI) {
+    if actions.pressed(Action::WaterUp) {
         movement += Vec3::Y;
-    } else if keycode.pressed(KeyCode::KeyK) {
+    } else if actions.pressed(Action::WaterDown) {
         movement += Vec3::NEG_Y;
     }
 
-    if keycode.pressed(KeyCode::ShiftLeft) {
+    if actions.pressed(Action::Faster) {
         movement *= 10.0;
     }
 
@@ -353,8 +439,7 @@ fn pick_path(
             return;
         }
     }
-    let keycode = world.resource::<ButtonInput<KeyCode>>();
-    if keycode.just_pressed(KeyCode::KeyL) || keycode.just_pressed(KeyCode::KeyO) {
+    if controls::just_pressed(world, Action::LoadSave) {
         let (tx, rx) = mpsc::channel();
         world.insert_non_send(rx);
         thread::spawn(move || {
@@ -384,8 +469,8 @@ fn load_brs(
         let (tx, rx) = mpsc::channel();
         world.insert_non_send(rx);
         thread::spawn(move || {
-            let save_data = load_save_data(path);
-            tx.send(save_data).unwrap();
+            let save_data = load_save_data(&path).map_err(|e| e.to_string());
+            tx.send((path, save_data)).unwrap();
         });
     }
 }
@@ -394,26 +479,32 @@ fn load_brs(
 // stays live during the heavy face/BVH/mesh build.
 fn load_save(
     mut commands: Commands,
-    save_receiver: Option<NonSend<Receiver<SaveData>>>,
+    save_receiver: Option<NonSend<Receiver<(PathBuf, Result<SaveData, String>)>>>,
+    mut loading: ResMut<state::Loading>,
+    mut failed: MessageWriter<LoadFailed>,
 ) {
     let Some(save_receiver) = save_receiver else {
         return;
     };
-    let Ok(save_data) = save_receiver.try_recv() else {
+    let Ok((path, save_data)) = save_receiver.try_recv() else {
         return;
     };
+    let save_data = match save_data {
+        Ok(save_data) => save_data,
+        Err(error) => {
+            error!("Cannot load {}: {error}", path.display());
+            loading.0 = false;
+            failed.write(LoadFailed { path, error });
+            return;
+        },
+    };
     info!("Loaded {:?} bricks", &save_data.bricks.len());
 
     let task = AsyncComputeTaskPool::get().spawn(async move {
-        // generator borrows save_data; move the owned results out and let it
-        // drop before handing save_data back to the main world
-        let (material_meshes, com, bvh, aabbs) = {
-            let generator = BVHMeshGenerator::new(&save_data);
-            let material_meshes = generator.gen_mesh();
-            let com = generator.center_of_mass();
-            (material_meshes, com, generator.bvh, generator.aabbs)
-        };
-        LoadedBuild { save_data, material_meshes, com, bvh, aabbs }
+        let mut generator = BVHMeshGenerator::new(save_data);
+        let material_meshes = generator.gen_mesh();
+        let com = generator.center_of_mass();
+        LoadedBuild { generator, material_meshes, com, path }
     });
     commands.insert_resource(GenTask(task));
 }
@@ -422,10 +513,11 @@ fn load_save(
 // clear the loading state.
 fn poll_gen_task(
     mut commands: Commands,
-    mut cam_query: Query<&mut IsoCamera>,
     mut build_loaded: ResMut<state::BuildLoaded>,
     mut loading: ResMut<state::Loading>,
     assets: Res<SceneAssets>,
+    settings: Res<Settings>,
+    lighting: Res<sky::Lighting>,
     task: Option<ResMut<GenTask>>,
 ) {
     let Some(mut task) = task else {
@@ -436,55 +528,42 @@ fn poll_gen_task(
     };
     commands.remove_resource::<GenTask>();
 
-    let LoadedBuild { save_data, material_meshes, com, bvh, aabbs } = loaded;
+    let LoadedBuild { generator, material_meshes, com, path } = loaded;
 
-    let point_lights = gen_point_lights(&save_data);
-    let spot_lights = gen_spot_lights(&save_data);
+    let point_lights = gen_point_lights(&generator.save_data);
+    let spot_lights = gen_spot_lights(&generator.save_data);
 
     // Big builds can have thousands of lights that tank the framerate, so start
-    // them hidden past a threshold; `/lights` toggles them back on.
+    // them hidden past the settings' limit, unless it's night; `/lights`
+    // toggles them back on.
     let total_lights = point_lights.len() + spot_lights.len();
-    let visibility = if total_lights > 1000 {
+    let visibility = if total_lights > settings.light_limit && !lighting.night {
         Visibility::Hidden
     } else {
         Visibility::Visible
     };
 
     info!("Spawning {} point lights", point_lights.len());
-    for light in point_lights {
-        commands.spawn((light, Light, visibility));
+    for (brick, light, transform) in point_lights {
+        commands.spawn((light, transform, Light(brick), visibility));
     }
 
     info!("Spawning {} spot lights", spot_lights.len());
-    for light in spot_lights {
-        commands.spawn((light, Light, visibility));
+    for (brick, light, transform) in spot_lights {
+        commands.spawn((light, transform, Light(brick), visibility));
     }
 
-    if let Ok(mut cam) = cam_query.single_mut() {
-        cam.target = com;
-    }
-
-    let mut i = 0;
-    for meshes in material_meshes.into_iter() {
-        let material = match i {
-            0 => assets.materials.plastic.clone(),
-            1 => assets.materials.glow.clone(),
-            2 => assets.materials.glass.clone(),
-            3 => assets.materials.metal.clone(),
-            _ => assets.materials.plastic.clone(),
-        };
+    for (slot, meshes) in material_meshes.into_iter().enumerate() {
         commands.spawn(ChunkEntity {
             meshes,
-            material,
+            material: assets.materials.for_slot(slot),
         });
-        i += 1;
     }
 
     commands.spawn(SaveBVH {
-        bvh,
-        save_data,
-        aabbs,
+        generator,
         com,
+        path,
     });
 
     commands.spawn((
@@ -508,11 +587,11 @@ fn spawn_chunks(
         }
 
         for _ in 0..10 {
-            if let Some(mesh) = chunk_entity.meshes.pop() {
+            if let Some((key, mesh)) = chunk_entity.meshes.pop() {
                 commands.spawn((
                     Mesh3d(meshes.add(mesh)),
                     MeshMaterial3d(chunk_entity.material.clone()),
-                    ChunkMesh,
+                    ChunkMesh(key),
                 ));
             }
         }
@@ -527,21 +606,49 @@ fn ask_save_path() -> PathBuf {
         .unwrap()
 }
 
-fn load_save_data(path: PathBuf) -> SaveData {
-    let ext = path
+fn load_save_data(path: &Path) -> Result<SaveData, Box<dyn Error>> {
+    match save_extension(path).as_str() {
+        "brdb" => brdb_load::load_brdb_world(path),
+        "brz" => brdb_load::load_brz_prefab(path),
+        _ => Ok(SaveReader::new(BufReader::new(File::open(path)?))?.read_all()?),
+    }
+}
+
+// Writes `.brs` with the save writer or `.brz` through brdb, picked by
+// extension.
+fn write_save_data(mut save_data: SaveData, path: &Path) -> Result<(), Box<dyn Error>> {
+    match save_extension(path).as_str() {
+        "brs" => {
+            save_data.header1.brick_count = save_data.bricks.len() as u32;
+            SaveWriter::new(BufWriter::new(File::create(path)?), save_data).write()?;
+        }
+        "brz" => brdb_load::write_brz_prefab(&save_data, path)?,
+        ext => return Err(format!("can't write '.{ext}' files, expected .brs or .brz").into()),
+    }
+    Ok(())
+}
+
+fn save_extension(path: &Path) -> String {
+    path
         .extension()
         .and_then(|e| e.to_str())
         .unwrap_or("")
-        .to_lowercase();
+        .to_lowercase()
+}
 
-    match ext.as_str() {
-        "brdb" => brdb_load::load_brdb_world(&path).expect("failed to load .brdb world"),
-        "brz" => brdb_load::load_brz_prefab(&path).expect("failed to load .brz prefab"),
-        _ => SaveReader::new(BufReader::new(File::open(path).unwrap()))
-            .unwrap()
-            .read_all()
-            .unwrap(),
-    }
+// Where the viewer keeps its own files, like `autoexec.cfg`.
+fn config_directory() -> Option<PathBuf> {
+    dirs::config_dir().map(|path| path.join("brs_iso"))
+}
+
+// Load `path` as if it had been picked with L.
+fn queue_load(world: &mut World, path: PathBuf) {
+    let (tx, rx) = mpsc::channel();
+    tx.send(path).unwrap();
+    world.insert_non_send(rx);
+    // loading from now rather than from when `load_brs` picks it up, so a
+    // script holds from the next line
+    world.resource_mut::<state::Loading>().0 = true;
 }
 
 fn default_build_directory() -> Option<PathBuf> {
@@ -596,7 +703,7 @@ fn bvh_gizmos (
     for save_bvh in &query {
         match game_state.bvh_view {
             BVHView::On(depth) => {
-                aabb_gizmos_recursive(&save_bvh.bvh, 0, &mut gizmos, 0, depth);
+                aabb_gizmos_recursive(&save_bvh.generator.bvh, 0, &mut gizmos, 0, depth);
             },
             BVHView::Off => {}
         }
@@ -615,36 +722,37 @@ fn aabb_gizmos_recursive(bvh: &BVH, node: usize, gizmos: &mut Gizmos, depth: u8,
         _ => Color::WHITE,
     };
 
-    match &bvh[node] {
-        BVHNode::Internal { aabb, left, right } => {
-            if depth == target_depth {
-                gizmos.primitive_3d(
-                    &Cuboid { half_size: aabb.halfwidths.as_vec3() },
-                    aabb.center.as_vec3(),
-                    color,
-                );
-                return;
-            }
+    let Some(node) = bvh.arena.get(node) else { return; };
+    // leaves above the target depth are drawn too, otherwise shallow branches
+    // of an SAH tree would vanish from the view
+    if depth == target_depth || matches!(node, BVHNode::Leaf { .. }) {
+        let aabb = node.aabb();
+        gizmos.primitive_3d(
+            &Cuboid { half_size: aabb.halfwidths.as_vec3() },
+            aabb.center.as_vec3(),
+            color,
+        );
+        return;
+    }
 
-            aabb_gizmos_recursive(bvh, *left, gizmos, depth + 1, target_depth);
-            aabb_gizmos_recursive(bvh, *right, gizmos, depth + 1, target_depth);
-        },
-        _ => {}
+    if let BVHNode::Internal { left, right, .. } = node {
+        aabb_gizmos_recursive(bvh, *left, gizmos, depth + 1, target_depth);
+        aabb_gizmos_recursive(bvh, *right, gizmos, depth + 1, target_depth);
     }
 }
 
 fn change_depth(
-    keycode: Res<ButtonInput<KeyCode>>,
+    actions: Actions,
     mut game_state: ResMut<GameState>,
 ) {
     match game_state.input {
         InputState::Listen => {
             match &mut game_state.bvh_view {
                 BVHView::On(depth) => {
-                    if keycode.just_pressed(KeyCode::KeyX) {
+                    if actions.just_pressed(Action::BvhDeeper) {
                         *depth += 1;
                     }
-                    if keycode.just_pressed(KeyCode::KeyZ) {
+                    if actions.just_pressed(Action::BvhShallower) {
                         *depth -= 1;
                     }
                 }
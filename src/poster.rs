@@ -0,0 +1,334 @@
+use std::{path::{Path, PathBuf}, thread};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    camera::ScalingMode,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::{cam::IsoCamera, console::{AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand}, job::RenderJob, screenshot::ShotOutput};
+
+// Tiled poster renders, for prints bigger than one GPU texture. The poster
+// is split into a grid of equal tiles, each an off-center slice of the iso
+// camera's orthographic frustum. The F3 capture sequence renders them one
+// after another and they're pasted into a single canvas, written off the
+// main thread in the screenshot format. `/poster` renders the current view;
+// `brs_iso poster` loads a save, frames it, renders it and quits.
+
+// Well under the 8192 texture limit, so each tile reads back quickly.
+const MAX_TILE: u32 = 4096;
+// The canvas is held in memory at 4 bytes a pixel, so it's capped at 1 GiB:
+// 36x24in at 300dpi is about a third of that.
+const MAX_POSTER_DIM: u32 = 32768;
+const MAX_POSTER_PIXELS: u64 = 1 << 28;
+const DEFAULT_DPI: f32 = 300.0;
+const CM_PER_INCH: f32 = 2.54;
+
+const POSTER_USAGE: &str = "poster size: <width>x<height>[px|in|cm] [<dpi>dpi] [<file.png>]";
+
+pub struct PosterPlugin;
+
+#[derive(Message, Debug, Clone)]
+pub struct PosterRequest {
+    pub size: UVec2,
+    // `None` names it like any other screenshot
+    pub path: Option<PathBuf>,
+}
+
+impl PosterRequest {
+    // `20000x12000`, `36x24in 300dpi` or `90x60cm`, then an optional output
+    // file. Print sizes default to 300 dpi.
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let (size, rest) = args.split_first().ok_or(POSTER_USAGE)?;
+        let mut dpi = None;
+        let mut path = None;
+        for arg in rest {
+            match arg.strip_suffix("dpi") {
+                Some(value) => dpi = Some(value.parse().map_err(|_| format!("bad dpi: {arg}"))?),
+                None if path.is_none() => path = Some(PathBuf::from(arg)),
+                None => return Err(POSTER_USAGE.into()),
+            }
+        }
+        Self::new(size, dpi, path)
+    }
+
+    // `size` as `parse` takes it, and what's given of the rest.
+    pub fn new(size: &str, dpi: Option<f32>, path: Option<PathBuf>) -> Result<Self, String> {
+        let dims = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let unit = &size[dims.len()..];
+        let (width, height) = dims.split_once('x').ok_or(POSTER_USAGE)?;
+        let width: f32 = width.parse().map_err(|_| format!("bad width: {width}"))?;
+        let height: f32 = height.parse().map_err(|_| format!("bad height: {height}"))?;
+        let dpi = dpi.unwrap_or(DEFAULT_DPI);
+        if dpi <= 0.0 || !dpi.is_finite() {
+            return Err(format!("bad dpi: {dpi}"));
+        }
+
+        let pixels_per_unit = match unit {
+            "" | "px" => 1.0,
+            "in" => dpi,
+            "cm" => dpi / CM_PER_INCH,
+            _ => return Err(format!("unknown unit: {unit}")),
+        };
+        let size = (Vec2::new(width, height) * pixels_per_unit).round();
+        if size.min_element() < 1.0 || size.max_element() > MAX_POSTER_DIM as f32 {
+            return Err(format!("poster must be 1 to {MAX_POSTER_DIM} pixels a side, not {}x{}", size.x, size.y));
+        }
+        let size = size.as_uvec2();
+        if size.x as u64 * size.y as u64 > MAX_POSTER_PIXELS {
+            return Err(format!("poster must be at most {} megapixels, not {}x{}", MAX_POSTER_PIXELS >> 20, size.x, size.y));
+        }
+        Ok(Self { size, path })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TileGrid {
+    pub size: UVec2,
+    pub tile: UVec2,
+    cols: u32,
+    rows: u32,
+}
+
+impl TileGrid {
+    pub fn new(size: UVec2) -> Self {
+        let cols = size.x.div_ceil(MAX_TILE);
+        let rows = size.y.div_ceil(MAX_TILE);
+        Self {
+            size,
+            tile: UVec2::new(size.x.div_ceil(cols), size.y.div_ceil(rows)),
+            cols,
+            rows,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        (self.cols * self.rows) as usize
+    }
+
+    // Equal tiles overshoot `size` by less than a tile; the overshoot is
+    // split evenly and cropped off.
+    fn crop(&self) -> UVec2 {
+        (UVec2::new(self.cols, self.rows) * self.tile - self.size) / 2
+    }
+
+    fn cell(&self, index: usize) -> UVec2 {
+        UVec2::new(index as u32 % self.cols, index as u32 / self.cols)
+    }
+
+    // Tile `index`, row by row from the top left, as a slice of what `ortho`
+    // shows vertically. The camera stays put at the middle of the poster;
+    // moving the viewport origin by whole tiles shifts each slice.
+    pub fn projection(&self, index: usize, ortho: &OrthographicProjection) -> Projection {
+        let cell = self.cell(index);
+        let pixel = ortho.area.height() / self.size.y as f32;
+        Projection::Orthographic(OrthographicProjection {
+            scale: 1.0,
+            scaling_mode: ScalingMode::Fixed {
+                width: self.tile.x as f32 * pixel,
+                height: self.tile.y as f32 * pixel,
+            },
+            viewport_origin: Vec2::new(
+                self.cols as f32 / 2.0 - cell.x as f32,
+                (cell.y + 1) as f32 - self.rows as f32 / 2.0,
+            ),
+            ..ortho.clone()
+        })
+    }
+}
+
+pub struct PosterCanvas {
+    grid: TileGrid,
+    // RGBA
+    pixels: Vec<u8>,
+    pasted: usize,
+    output: ShotOutput,
+}
+
+impl PosterCanvas {
+    pub fn new(grid: TileGrid, output: ShotOutput) -> Self {
+        Self {
+            pixels: vec![0; grid.size.x as usize * grid.size.y as usize * 4],
+            grid,
+            pasted: 0,
+            output,
+        }
+    }
+
+    // Copy in a captured tile, minus whatever lands in the cropped margin.
+    // True once every tile is in.
+    pub fn paste(&mut self, index: usize, tile: &Image) -> bool {
+        let Some(data) = &tile.data else { return false; };
+        let bgra = matches!(tile.texture_descriptor.format, TextureFormat::Bgra8UnormSrgb | TextureFormat::Bgra8Unorm);
+        let origin = (self.grid.cell(index) * self.grid.tile).as_ivec2() - self.grid.crop().as_ivec2();
+        let size = self.grid.size.as_ivec2();
+        let width = tile.width() as usize;
+
+        for y in 0..tile.height() as i32 {
+            let canvas_y = origin.y + y;
+            if !(0..size.y).contains(&canvas_y) {
+                continue;
+            }
+            for x in 0..width as i32 {
+                let canvas_x = origin.x + x;
+                if !(0..size.x).contains(&canvas_x) {
+                    continue;
+                }
+                let src = (y as usize * width + x as usize) * 4;
+                let dst = (canvas_y as usize * size.x as usize + canvas_x as usize) * 4;
+                let px = &data[src..src + 4];
+                let rgba = if bgra { [px[2], px[1], px[0], px[3]] } else { [px[0], px[1], px[2], px[3]] };
+                self.pixels[dst..dst + 4].copy_from_slice(&rgba);
+            }
+        }
+        self.pasted += 1;
+        self.pasted == self.grid.len()
+    }
+
+    // Encoding an image this size takes a while, so it happens on its own thread.
+    pub fn save(self) -> thread::JoinHandle<Result<PathBuf, String>> {
+        let PosterCanvas { grid, pixels, output, .. } = self;
+        thread::spawn(move || {
+            let image = Image::new(
+                Extent3d { width: grid.size.x, height: grid.size.y, depth_or_array_layers: 1 },
+                TextureDimension::D2,
+                pixels,
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::MAIN_WORLD,
+            );
+            output.write(image).map(|()| output.path)
+        })
+    }
+}
+
+// Posters still being written, reported as they finish.
+#[derive(Resource, Default)]
+pub struct PosterWrites(Vec<thread::JoinHandle<Result<PathBuf, String>>>);
+
+impl PosterWrites {
+    pub fn push(&mut self, write: thread::JoinHandle<Result<PathBuf, String>>) {
+        self.0.push(write);
+    }
+}
+
+impl Plugin for PosterPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_message::<PosterRequest>()
+            .init_resource::<PosterWrites>()
+            .add_systems(Update, report_poster_writes)
+            .add_console_command(
+                ConsoleCommand::new("/poster", "tiled render past 8192px: 20000x12000, 36x24in 300dpi, 90x60cm")
+                    .arg(Arg::word("size", &[]))
+                    .arg(Arg::float("dpi").unit("dpi").optional())
+                    .arg(Arg::path("file").optional())
+                    .local_only(),
+                poster_command,
+            );
+    }
+}
+
+fn poster_command(
+    In(args): In<CommandArgs>,
+    mut posters: MessageWriter<PosterRequest>,
+    projection_query: Query<&Projection, With<IsoCamera>>,
+) -> CommandResult {
+    let request = PosterRequest::new(args.word("size").unwrap_or_default(), args.float("dpi"), args.path("file").map(Path::to_path_buf))?;
+    if !matches!(projection_query.single(), Ok(Projection::Orthographic(_))) {
+        return Err("posters need the iso camera: /camera iso".into());
+    }
+    let message = format!("rendering a {}x{} poster", request.size.x, request.size.y);
+    posters.write(request);
+    Ok(message)
+}
+
+fn report_poster_writes(
+    mut writes: ResMut<PosterWrites>,
+    job: Option<Res<RenderJob>>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    if !writes.0.iter().any(|write| write.is_finished()) {
+        return;
+    }
+    let (finished, pending): (Vec<_>, Vec<_>) = writes.0.drain(..).partition(|write| write.is_finished());
+    writes.0 = pending;
+    for write in finished {
+        let result = write.join().unwrap_or_else(|_| Err("writer thread panicked".into()));
+        let failed = result.is_err();
+        match result {
+            Ok(path) => info!("Poster saved to {}", path.display()),
+            Err(e) => error!("Cannot save poster: {e}"),
+        }
+        if job.is_some() {
+            exit.write(if failed { AppExit::error() } else { AppExit::Success });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What a tile's projection shows, in the camera's view plane.
+    fn tile_area(projection: &Projection) -> Rect {
+        let Projection::Orthographic(ortho) = projection else { panic!("not orthographic") };
+        let ScalingMode::Fixed { width, height } = ortho.scaling_mode else { panic!("not fixed") };
+        let origin = ortho.viewport_origin * Vec2::new(width, height);
+        Rect::new(-origin.x, -origin.y, width - origin.x, height - origin.y)
+    }
+
+    #[test]
+    fn parses_print_sizes() {
+        assert_eq!(PosterRequest::parse(&["36x24in"]).unwrap().size, UVec2::new(10800, 7200));
+        assert_eq!(PosterRequest::parse(&["2.54x5.08cm", "100dpi"]).unwrap().size, UVec2::new(100, 200));
+        let request = PosterRequest::parse(&["640x480px", "out.png"]).unwrap();
+        assert_eq!(request.size, UVec2::new(640, 480));
+        assert_eq!(request.path, Some(PathBuf::from("out.png")));
+    }
+
+    #[test]
+    fn refuses_oversized_posters() {
+        assert!(PosterRequest::parse(&["40000x10"]).is_err());
+        assert!(PosterRequest::parse(&["20000x20000"]).is_err());
+        assert!(PosterRequest::parse(&["0x100"]).is_err());
+        assert!(PosterRequest::parse(&["16384x16384"]).is_ok());
+        assert!(PosterRequest::parse(&["36x24in", "0dpi"]).is_err());
+    }
+
+    #[test]
+    fn splits_into_equal_tiles() {
+        let grid = TileGrid::new(UVec2::new(10000, 5000));
+        assert_eq!(grid.len(), 6);
+        assert_eq!(grid.tile, UVec2::new(3334, 2500));
+        assert_eq!(grid.crop(), UVec2::new(1, 0));
+
+        let single = TileGrid::new(UVec2::new(MAX_TILE, 100));
+        assert_eq!(single.len(), 1);
+        assert_eq!(single.crop(), UVec2::ZERO);
+    }
+
+    #[test]
+    fn tiles_cover_the_view_row_by_row() {
+        let grid = TileGrid::new(UVec2::new(10000, 5000));
+        let ortho = OrthographicProjection {
+            area: Rect::new(-150.0, -50.0, 150.0, 50.0),
+            ..OrthographicProjection::default_3d()
+        };
+        let areas: Vec<Rect> = (0..grid.len()).map(|i| tile_area(&grid.projection(i, &ortho))).collect();
+
+        // top left first, then along the row
+        assert!(areas[0].min.x < areas[1].min.x && areas[0].min.y > areas[3].min.y);
+        for pair in areas.windows(2).filter(|pair| pair[0].min.y == pair[1].min.y) {
+            assert!((pair[0].max.x - pair[1].min.x).abs() < 1e-3);
+        }
+
+        // the whole grid is the live view's height, at the poster's aspect,
+        // plus the cropped overshoot
+        let bounds = areas.iter().fold(areas[0], |bounds, area| bounds.union(*area));
+        let pixel = 100.0 / 5000.0;
+        assert!((bounds.height() - 100.0).abs() < 1e-3);
+        assert!((bounds.width() - 10002.0 * pixel).abs() < 1e-3);
+        assert!(bounds.center().length() < 1e-3);
+    }
+}
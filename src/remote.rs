@@ -0,0 +1,667 @@
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc, Mutex},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use serde_json::{json, Value};
+
+use crate::{
+    cam::{CameraPose, CameraTween, IsoCamera, TakeScreenshot, ViewSettings},
+    chat::{print_echo, print_result},
+    cli::brick_record,
+    console::{run_command, run_remote_command, AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand},
+    lit::{sun_angles, sun_direction, Sun},
+    screenshot::ScreenshotSaved,
+    state::Loading,
+    config_directory, queue_load, SaveBVH,
+};
+
+// Remote control for build pipelines: JSON-RPC 2.0 over TCP, one request
+// per line and one response line per request (notifications, without an
+// id, get none). Off unless started with `--remote <port>` or
+// `/remote start`, and only bound to 127.0.0.1. Methods:
+//
+//   command {line}           run a console command; {output}
+//   load {path}              answers once the build is up; {path, bricks}
+//   camera.get               {mode, target, heading, elevation, scale, fov}
+//   camera.set {...}         any of those; the camera moves like /goto
+//   sun.get                  {heading, elevation}
+//   sun.set {heading, elevation?}
+//   screenshot               answers once the file is written; {path}
+//   brick.at {position}      the bricks whose boxes hold a point; {bricks}
+//
+// Positions are in the save's axes (Z up), like the brick info window and
+// `/goto`, and angles are in degrees. A screenshot waits for loading and
+// camera moves to finish, so `load`, `camera.set` then `screenshot` in a
+// row captures the settled view.
+//
+// Anything on this machine can reach the port, web pages included, so every
+// request carries a `token` member, a fresh one written to `remote_token` in
+// the config directory each time the server starts. The first line that
+// isn't a well-formed, authorized request (an HTTP header, say) is answered
+// with an error and the connection closed. `command` can't run commands
+// that write files where they're told or run other commands (`local_only`
+// ones, like `/export` and `/exec`). With netcat:
+//
+//   echo '{"jsonrpc":"2.0","id":1,"token":"…","method":"command","params":{"line":"/water off"}}' | nc 127.0.0.1 7878
+
+const DEFAULT_PORT: u16 = 7878;
+const TOKEN_FILE: &str = "remote_token";
+// Longer request lines are refused.
+const MAX_LINE: usize = 64 * 1024;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// the command or operation itself failed
+const FAILED: i64 = -32000;
+const UNAUTHORIZED: i64 = -32001;
+
+pub struct RemotePlugin;
+
+// `--remote <port>`: start serving at startup.
+#[derive(Resource, Default)]
+pub struct StartupRemote(pub Option<u16>);
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+impl From<String> for RpcError {
+    fn from(message: String) -> Self {
+        RpcError::new(FAILED, message)
+    }
+}
+
+impl From<&str> for RpcError {
+    fn from(message: &str) -> Self {
+        RpcError::new(FAILED, message)
+    }
+}
+
+type Reply = Result<Value, RpcError>;
+
+// The way back to whoever asked.
+struct Responder {
+    id: Value,
+    out: mpsc::Sender<String>,
+}
+
+impl Responder {
+    fn send(&self, reply: Reply) {
+        let response = match reply {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": self.id, "result": result }),
+            Err(e) => json!({ "jsonrpc": "2.0", "id": self.id, "error": { "code": e.code, "message": e.message } }),
+        };
+        // the client may have hung up
+        let _ = self.out.send(response.to_string());
+    }
+}
+
+struct Call {
+    method: String,
+    params: Value,
+    // `None` for notifications
+    responder: Option<Responder>,
+}
+
+// What a reply is held for.
+enum Waiting {
+    Load,
+    // Taken once nothing's loading or moving, then answered by the
+    // `ScreenshotSaved` with its ticket.
+    Screenshot { ticket: u64, taken: bool },
+}
+
+enum Answer {
+    Now(Reply),
+    Later(Waiting),
+}
+
+struct Server {
+    port: u16,
+    calls: Mutex<mpsc::Receiver<Call>>,
+    stop: Arc<AtomicBool>,
+}
+
+#[derive(Resource, Default)]
+struct Remote {
+    server: Option<Server>,
+    waiting: Vec<(Waiting, Option<Responder>)>,
+    // for `TakeScreenshot::ticket`
+    next_ticket: u64,
+}
+
+fn token_path() -> Option<PathBuf> {
+    config_directory().map(|directory| directory.join(TOKEN_FILE))
+}
+
+// 128 bits from std's hasher keys, which are seeded from the OS's random
+// source, so there's no need for a random number crate.
+fn new_token() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos());
+    let half = |salt: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(salt);
+        hasher.write_u128(nanos);
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", half(1), half(2))
+}
+
+// Readable by this user only, where that can be said.
+fn write_token(token: &str) -> Result<(), String> {
+    let path = token_path().ok_or("no config directory for the remote token")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .map_err(|e| format!("couldn't write {}: {e}", path.display()))
+}
+
+impl Remote {
+    // Port 0 picks a free one. Returns the port listened on.
+    fn start(&mut self, port: u16) -> Result<u16, String> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).map_err(|e| format!("couldn't listen on port {port}: {e}"))?;
+        let port = listener.local_addr().map_or(port, |address| address.port());
+        let token: Arc<str> = new_token().into();
+        write_token(&token)?;
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = stop.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopping.load(Ordering::Relaxed) {
+                    break;
+                }
+                match stream {
+                    Ok(stream) => {
+                        let calls = tx.clone();
+                        let token = token.clone();
+                        thread::spawn(move || serve_connection(stream, &token, calls));
+                    },
+                    Err(e) => warn!("Remote connection failed: {e}"),
+                }
+            }
+        });
+        self.server = Some(Server { port, calls: Mutex::new(rx), stop });
+        Ok(port)
+    }
+
+    // Open connections close at their next request.
+    fn stop(&mut self) {
+        let Some(server) = self.server.take() else { return; };
+        server.stop.store(true, Ordering::Relaxed);
+        if let Some(path) = token_path() {
+            let _ = fs::remove_file(path);
+        }
+        // wake the accept loop so it sees the flag
+        let _ = TcpStream::connect((Ipv4Addr::LOCALHOST, server.port));
+    }
+}
+
+impl Plugin for RemotePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Remote>()
+            .add_systems(Startup, start_remote)
+            .add_systems(Update, (answer_calls, take_screenshots, finish_waiting).chain())
+            .add_console_command(ConsoleCommand::new("/remote", "whether the remote control is listening"), remote_command)
+            .add_console_command(
+                ConsoleCommand::new("/remote start", "serve the JSON-RPC remote control on localhost (7878)").arg(Arg::int("port").optional()),
+                remote_start_command,
+            )
+            .add_console_command(ConsoleCommand::new("/remote stop", "stop the remote control"), remote_stop_command);
+    }
+}
+
+fn start_remote(
+    mut remote: ResMut<Remote>,
+    startup: Option<Res<StartupRemote>>,
+) {
+    let Some(port) = startup.and_then(|startup| startup.0) else { return; };
+    match remote.start(port) {
+        Ok(port) => info!("Remote control listening on 127.0.0.1:{port}, token in {TOKEN_FILE}"),
+        Err(e) => error!("{e}"),
+    }
+}
+
+fn remote_command(
+    In(_): In<CommandArgs>,
+    remote: Res<Remote>,
+) -> CommandResult {
+    Ok(match &remote.server {
+        Some(server) => format!("remote control on 127.0.0.1:{}", server.port),
+        None => "remote control off (/remote start [port])".into(),
+    })
+}
+
+fn remote_start_command(
+    In(args): In<CommandArgs>,
+    mut remote: ResMut<Remote>,
+) -> CommandResult {
+    if let Some(server) = &remote.server {
+        return Err(format!("remote control is already on port {} (/remote stop first)", server.port));
+    }
+    let port = match args.int("port") {
+        Some(port) => u16::try_from(port).map_err(|_| format!("{port} isn't a port"))?,
+        None => DEFAULT_PORT,
+    };
+    let port = remote.start(port)?;
+    Ok(format!("remote control on 127.0.0.1:{port}, token in {TOKEN_FILE} in the config directory"))
+}
+
+fn remote_stop_command(
+    In(_): In<CommandArgs>,
+    mut remote: ResMut<Remote>,
+) -> CommandResult {
+    if remote.server.is_none() {
+        return Err("remote control isn't running".into());
+    }
+    remote.stop();
+    Ok("remote control off".into())
+}
+
+// One client. Responses go out from a thread of their own, since a
+// screenshot can be answered after requests that came in behind it. The
+// socket closes once the reading stops and the answers still owed are sent.
+fn serve_connection(stream: TcpStream, token: &str, calls: mpsc::Sender<Call>) {
+    let Ok(mut writer) = stream.try_clone() else { return; };
+    let (out, responses) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        for response in responses {
+            if writeln!(writer, "{response}").is_err() {
+                break;
+            }
+        }
+    });
+    let mut reader = BufReader::new(stream);
+    loop {
+        let line = match read_line(&mut reader) {
+            Ok(Some(line)) => line,
+            Ok(None) | Err(_) => break,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_call(&line, token) {
+            Ok((id, method, params)) => {
+                let responder = id.map(|id| Responder { id, out: out.clone() });
+                // a closed channel means the server stopped
+                if calls.send(Call { method, params, responder }).is_err() {
+                    break;
+                }
+            },
+            Err((id, e)) => {
+                Responder { id, out: out.clone() }.send(Err(e));
+                break;
+            },
+        }
+    }
+}
+
+// The next line, without its newline; `None` at the end. Lines past
+// `MAX_LINE` bytes, or that aren't UTF-8, are errors.
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let read = reader.take(MAX_LINE as u64 + 1).read_until(b'\n', &mut line)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    } else if line.len() > MAX_LINE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request line too long"));
+    }
+    String::from_utf8(line).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// A request's id (`None` for notifications), method and params, or the
+// error to answer it with before hanging up.
+fn parse_call(line: &str, token: &str) -> Result<(Option<Value>, String, Value), (Value, RpcError)> {
+    let request: Value = serde_json::from_str(line).map_err(|e| (Value::Null, RpcError::new(PARSE_ERROR, e.to_string())))?;
+    let id = request.get("id").cloned();
+    let fail = |code, message: &str| (id.clone().unwrap_or(Value::Null), RpcError::new(code, message));
+    if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(fail(INVALID_REQUEST, "expected a JSON-RPC 2.0 request object"));
+    }
+    if request.get("token").and_then(Value::as_str) != Some(token) {
+        return Err(fail(UNAUTHORIZED, "missing or wrong token (see remote_token in the config directory)"));
+    }
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return Err(fail(INVALID_REQUEST, "expected a method"));
+    };
+    Ok((id, method.to_string(), request.get("params").cloned().unwrap_or(Value::Null)))
+}
+
+fn answer_calls(world: &mut World) {
+    let calls: Vec<Call> = match &world.resource::<Remote>().server {
+        Some(server) => server.calls.lock().map(|calls| calls.try_iter().collect()).unwrap_or_default(),
+        None => return,
+    };
+    for Call { method, params, responder } in calls {
+        match call(world, &method, &params) {
+            Answer::Now(reply) => {
+                if let Some(responder) = responder {
+                    responder.send(reply);
+                }
+            },
+            Answer::Later(waiting) => world.resource_mut::<Remote>().waiting.push((waiting, responder)),
+        }
+    }
+}
+
+fn call(world: &mut World, method: &str, params: &Value) -> Answer {
+    let reply = match method {
+        "command" => command(world, params),
+        "load" => match load(world, params) {
+            Ok(()) => return Answer::Later(Waiting::Load),
+            Err(e) => Err(e),
+        },
+        "camera.get" => camera_get(world),
+        "camera.set" => camera_set(world, params),
+        "sun.get" => sun_get(world),
+        "sun.set" => sun_set(world, params),
+        "screenshot" => {
+            let mut remote = world.resource_mut::<Remote>();
+            remote.next_ticket += 1;
+            return Answer::Later(Waiting::Screenshot { ticket: remote.next_ticket, taken: false });
+        },
+        "brick.at" => brick_at(world, params),
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("no method {method}"))),
+    };
+    Answer::Now(reply)
+}
+
+// One shot at a time, each once the last is written and the view has
+// settled.
+fn take_screenshots(
+    mut remote: ResMut<Remote>,
+    loading: Res<Loading>,
+    tween_query: Query<(), With<CameraTween>>,
+    mut shots: MessageWriter<TakeScreenshot>,
+) {
+    if loading.0 || !tween_query.is_empty() {
+        return;
+    }
+    let mut screenshots = remote.waiting.iter_mut().filter_map(|(waiting, _)| match waiting {
+        Waiting::Screenshot { ticket, taken } => Some((*ticket, taken)),
+        _ => None,
+    });
+    if let Some((ticket, taken)) = screenshots.next().filter(|(_, taken)| !**taken) {
+        *taken = true;
+        shots.write(TakeScreenshot { ticket: Some(ticket) });
+    }
+}
+
+fn finish_waiting(
+    mut remote: ResMut<Remote>,
+    mut saved: MessageReader<ScreenshotSaved>,
+    loading: Res<Loading>,
+    bvh_query: Query<&SaveBVH>,
+) {
+    let saved: Vec<&ScreenshotSaved> = saved.read().filter(|shot| shot.ticket.is_some()).collect();
+    remote.waiting.retain(|(waiting, responder)| {
+        let reply = match waiting {
+            Waiting::Load if !loading.0 => match bvh_query.iter().next() {
+                Some(save_bvh) => Ok(json!({
+                    "path": save_bvh.path.display().to_string(),
+                    "bricks": save_bvh.generator.save_data.bricks.len(),
+                })),
+                None => Err("the build didn't load".into()),
+            },
+            Waiting::Screenshot { ticket, taken: true } => match saved.iter().find(|shot| shot.ticket == Some(*ticket)) {
+                Some(ScreenshotSaved { path, result, .. }) => result.clone()
+                    .map(|()| json!({ "path": path.display().to_string() }))
+                    .map_err(RpcError::from),
+                None => return true,
+            },
+            _ => return true,
+        };
+        if let Some(responder) = responder {
+            responder.send(reply);
+        }
+        false
+    });
+}
+
+// Save axes (Z up) to the renderer's (Y up) and back.
+fn world_axes(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, v.z, v.y)
+}
+
+fn save_axes(v: Vec3) -> [f32; 3] {
+    [v.x, v.z, v.y]
+}
+
+fn invalid(name: &str, expected: &str) -> RpcError {
+    RpcError::new(INVALID_PARAMS, format!("{name}: expected {expected}"))
+}
+
+fn required<T>(value: Option<T>, name: &str) -> Result<T, RpcError> {
+    value.ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("missing {name}")))
+}
+
+fn float(params: &Value, name: &str) -> Result<Option<f32>, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value.as_f64().map(|value| Some(value as f32)).ok_or_else(|| invalid(name, "a number")),
+    }
+}
+
+fn string<'a>(params: &'a Value, name: &str) -> Result<Option<&'a str>, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value.as_str().map(Some).ok_or_else(|| invalid(name, "a string")),
+    }
+}
+
+fn vec3(params: &Value, name: &str) -> Result<Option<Vec3>, RpcError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => {
+            let numbers: Option<Vec<f32>> = value.as_array()
+                .and_then(|items| items.iter().map(|item| item.as_f64().map(|n| n as f32)).collect());
+            match numbers.as_deref() {
+                Some(&[x, y, z]) => Ok(Some(Vec3::new(x, y, z))),
+                _ => Err(invalid(name, "[x, y, z]")),
+            }
+        },
+    }
+}
+
+// Typed into the console, and echoed there like it was.
+fn command(world: &mut World, params: &Value) -> Reply {
+    let line = required(string(params, "line")?, "line")?;
+    let line = if line.starts_with('/') { line.to_string() } else { format!("/{line}") };
+    print_echo(world, &line);
+    let result = run_remote_command(world, &line);
+    print_result(world, result.clone());
+    result.map(|output| json!({ "output": output })).map_err(RpcError::from)
+}
+
+fn load(world: &mut World, params: &Value) -> Result<(), RpcError> {
+    let path = PathBuf::from(required(string(params, "path")?, "path")?);
+    if !path.is_file() {
+        return Err(format!("no such file: {}", path.display()).into());
+    }
+    queue_load(world, path);
+    Ok(())
+}
+
+fn current_pose(world: &mut World) -> Result<(Entity, CameraPose), RpcError> {
+    world.query::<(Entity, &IsoCamera, &Projection)>()
+        .single(world)
+        .map(|(entity, cam, projection)| (entity, CameraPose::current(cam, projection)))
+        .map_err(|_| "no camera".into())
+}
+
+fn camera_json(pose: &CameraPose, view: &ViewSettings) -> Value {
+    json!({
+        "mode": view.mode.name(),
+        "target": save_axes(pose.target),
+        "heading": pose.horizontal_angle,
+        "elevation": 90.0 - pose.vertical_angle,
+        "scale": pose.scale,
+        "fov": view.fov,
+    })
+}
+
+fn camera_get(world: &mut World) -> Reply {
+    let (_, pose) = current_pose(world)?;
+    Ok(camera_json(&pose, world.resource::<ViewSettings>()))
+}
+
+// Mode and fov go through their console commands for the same checks.
+// Answers with the pose the camera is moving to.
+fn camera_set(world: &mut World, params: &Value) -> Reply {
+    if let Some(mode) = string(params, "mode")? {
+        run_command(world, &format!("/camera {mode}"))?;
+    }
+    if let Some(fov) = float(params, "fov")? {
+        run_command(world, &format!("/fov {fov}"))?;
+    }
+    let (entity, mut pose) = current_pose(world)?;
+    if let Some(target) = vec3(params, "target")? {
+        pose.target = world_axes(target);
+    }
+    if let Some(heading) = float(params, "heading")? {
+        pose.horizontal_angle = heading.rem_euclid(360.0);
+    }
+    if let Some(elevation) = float(params, "elevation")? {
+        if !(-90.0..=90.0).contains(&elevation) {
+            return Err(invalid("elevation", "-90 to 90"));
+        }
+        pose.vertical_angle = 90.0 - elevation;
+    }
+    if let Some(scale) = float(params, "scale")? {
+        if scale <= 0.0 {
+            return Err(invalid("scale", "a positive number"));
+        }
+        pose.scale = scale;
+    }
+    world.entity_mut(entity).insert(CameraTween::to(pose));
+    Ok(camera_json(&pose, world.resource::<ViewSettings>()))
+}
+
+fn sun_get(world: &mut World) -> Reply {
+    let (heading, elevation) = sun_angles(sun_forward(world)?);
+    Ok(json!({ "heading": heading, "elevation": elevation }))
+}
+
+fn sun_set(world: &mut World, params: &Value) -> Reply {
+    let heading = required(float(params, "heading")?, "heading")?;
+    let (_, current) = sun_angles(sun_forward(world)?);
+    let elevation = float(params, "elevation")?.unwrap_or(current);
+    if !(-90.0..=90.0).contains(&elevation) {
+        return Err(invalid("elevation", "-90 to 90"));
+    }
+    let mut query = world.query_filtered::<&mut Transform, With<Sun>>();
+    let mut transform = query.single_mut(world).map_err(|_| "no sun")?;
+    transform.look_to(sun_direction(heading, elevation), Vec3::Y);
+    Ok(json!({ "heading": heading, "elevation": elevation }))
+}
+
+fn sun_forward(world: &mut World) -> Result<Vec3, RpcError> {
+    world.query_filtered::<&Transform, With<Sun>>()
+        .single(world)
+        .map(|transform| *transform.forward())
+        .map_err(|_| "no sun".into())
+}
+
+// Each brick as `dump` writes it.
+fn brick_at(world: &mut World, params: &Value) -> Reply {
+    let point = world_axes(required(vec3(params, "position")?, "position")?);
+    let mut query = world.query::<&SaveBVH>();
+    let save_bvh = query.iter(world).next().ok_or("no build loaded")?;
+    let build = &save_bvh.generator;
+    let bricks: Vec<Value> = build.bvh.query_point(point, &build.aabbs)
+        .into_iter()
+        .map(|i| brick_record(&build.save_data, i))
+        .collect();
+    Ok(json!({ "bricks": bricks }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    fn error_code(line: &str) -> i64 {
+        match parse_call(line, TOKEN) {
+            Ok(_) => panic!("{line} parsed"),
+            Err((_, e)) => e.code,
+        }
+    }
+
+    #[test]
+    fn parses_an_authorized_call() {
+        let (id, method, params) = parse_call(r#"{"jsonrpc":"2.0","id":7,"token":"0123456789abcdef","method":"command","params":{"line":"/water off"}}"#, TOKEN).ok().unwrap();
+        assert_eq!(id, Some(json!(7)));
+        assert_eq!(method, "command");
+        assert_eq!(params["line"], "/water off");
+    }
+
+    #[test]
+    fn notifications_have_no_id() {
+        let (id, _, params) = parse_call(r#"{"jsonrpc":"2.0","token":"0123456789abcdef","method":"sun.get"}"#, TOKEN).ok().unwrap();
+        assert_eq!(id, None);
+        assert_eq!(params, Value::Null);
+    }
+
+    #[test]
+    fn refuses_bad_lines() {
+        assert_eq!(error_code("POST / HTTP/1.1"), PARSE_ERROR);
+        assert_eq!(error_code(r#"{"id":1,"token":"0123456789abcdef","method":"sun.get"}"#), INVALID_REQUEST);
+        assert_eq!(error_code(r#"{"jsonrpc":"2.0","id":1,"token":"0123456789abcdef"}"#), INVALID_REQUEST);
+        assert_eq!(error_code(r#"[{"jsonrpc":"2.0","id":1,"token":"0123456789abcdef","method":"sun.get"}]"#), INVALID_REQUEST);
+    }
+
+    #[test]
+    fn refuses_a_wrong_or_missing_token() {
+        assert_eq!(error_code(r#"{"jsonrpc":"2.0","id":1,"method":"sun.get"}"#), UNAUTHORIZED);
+        assert_eq!(error_code(r#"{"jsonrpc":"2.0","id":1,"token":"guess","method":"sun.get"}"#), UNAUTHORIZED);
+    }
+
+    #[test]
+    fn caps_line_length() {
+        let mut long = vec![b'x'; MAX_LINE + 10];
+        long.push(b'\n');
+        assert!(read_line(&mut &long[..]).is_err());
+
+        let mut reader = &b"first\nsecond"[..];
+        assert_eq!(read_line(&mut reader).unwrap().as_deref(), Some("first"));
+        assert_eq!(read_line(&mut reader).unwrap().as_deref(), Some("second"));
+        assert_eq!(read_line(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn tokens_differ() {
+        let token = new_token();
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, new_token());
+    }
+}
@@ -0,0 +1,413 @@
+use std::{fs::{self, File}, io::BufWriter, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use bevy::{camera::ClearColorConfig, prelude::*, render::view::screenshot::ScreenshotCaptured};
+use serde::{Deserialize, Serialize};
+
+use crate::{bookmark::Bookmark, cam::{CameraMode, CameraPose, IsoCamera, ViewSettings}, console::{on_off, CommandArgs, CommandResult}, settings::Settings};
+
+// Where screenshots go and how they're written. Names come from a template,
+// backgrounds can be left transparent for compositing, and PNGs carry the
+// save and camera in text chunks so a shot can be set up again: the
+// "Camera" chunk is a view in the same form as a bookmark sidecar entry.
+// `/screenshot` and the settings window change the settings, which are kept
+// in settings.toml under `[screenshots]`.
+
+const DEFAULT_TEMPLATE: &str = "{kind}-{n}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShotFormat {
+    Png,
+    Jpeg,
+    WebP,
+}
+
+impl ShotFormat {
+    // in declaration order, for `name`
+    pub const NAMES: [&'static str; 3] = ["png", "jpeg", "webp"];
+
+    pub fn name(self) -> &'static str {
+        ShotFormat::NAMES[self as usize]
+    }
+
+    pub fn parse(name: &str) -> Option<ShotFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Some(ShotFormat::Png),
+            "jpeg" | "jpg" => Some(ShotFormat::Jpeg),
+            "webp" => Some(ShotFormat::WebP),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ShotFormat::Png => "png",
+            ShotFormat::Jpeg => "jpg",
+            ShotFormat::WebP => "webp",
+        }
+    }
+
+    // JPEG has no alpha channel.
+    pub fn has_alpha(self) -> bool {
+        self != ShotFormat::Jpeg
+    }
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenshotSettings {
+    pub directory: PathBuf,
+    // `{save}` (the save's file name), `{time}` (UTC, to the second), `{n}`
+    // (the shot counter) and `{kind}` are filled in; the extension follows
+    // the format.
+    pub template: String,
+    pub format: ShotFormat,
+    // Clear to transparent instead of the background color.
+    pub transparent: bool,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("."),
+            template: DEFAULT_TEMPLATE.into(),
+            format: ShotFormat::Png,
+            transparent: false,
+        }
+    }
+}
+
+impl ScreenshotSettings {
+    // A name the settings file gave that's empty or a path goes back to
+    // the default, as `/screenshot name` would refuse it.
+    pub fn clamped(self) -> Self {
+        if self.template.trim().is_empty() || self.template.contains(['/', '\\']) {
+            Self { template: DEFAULT_TEMPLATE.into(), ..self }
+        } else {
+            self
+        }
+    }
+
+    pub fn output(&self, kind: &str, n: u32, metadata: Option<ShotMetadata>) -> ShotOutput {
+        let save = metadata.as_ref()
+            .and_then(|metadata| metadata.save.as_deref())
+            .and_then(Path::file_stem)
+            .map_or("untitled".into(), |stem| stem.to_string_lossy());
+        let name = self.template
+            .replace("{save}", &save)
+            .replace("{time}", &timestamp())
+            .replace("{n}", &n.to_string())
+            .replace("{kind}", kind);
+        ShotOutput {
+            path: unused_path(&self.directory, &name, self.format.extension()),
+            format: self.format,
+            transparent: self.transparent && self.format.has_alpha(),
+            metadata,
+        }
+    }
+
+    pub fn clear_color(&self) -> ClearColorConfig {
+        if self.transparent && self.format.has_alpha() {
+            ClearColorConfig::Custom(Color::NONE)
+        } else {
+            ClearColorConfig::Default
+        }
+    }
+}
+
+// Keeps the settings' copy current, so saving the settings saves it;
+// resetting or reverting the settings, or the settings window, puts it back.
+pub fn sync_screenshot_settings(
+    mut settings: ResMut<Settings>,
+    mut shots: ResMut<ScreenshotSettings>,
+) {
+    if settings.screenshots == *shots {
+        return;
+    }
+    if shots.is_changed() {
+        settings.screenshots = shots.clone();
+    } else if settings.is_changed() {
+        *shots = settings.screenshots.clone();
+    }
+}
+
+// What a PNG records about the shot.
+#[derive(Debug, Clone)]
+pub struct ShotMetadata {
+    pub save: Option<PathBuf>,
+    pub pose: CameraPose,
+    pub sun: Vec3,
+    pub mode: CameraMode,
+    pub fov: f32,
+}
+
+impl ShotMetadata {
+    pub fn new(save: Option<&Path>, cam: &IsoCamera, projection: &Projection, sun: Option<&Transform>, view: &ViewSettings) -> Self {
+        Self {
+            save: save.map(Path::to_path_buf),
+            pose: CameraPose::current(cam, projection),
+            sun: sun.map_or(Vec3::NEG_Y, |transform| *transform.forward()),
+            mode: view.mode,
+            fov: view.fov,
+        }
+    }
+
+    fn text_chunks(&self) -> Vec<(&'static str, String)> {
+        let name = self.save.as_deref()
+            .and_then(Path::file_stem)
+            .map_or("shot".into(), |stem| stem.to_string_lossy().into_owned());
+        let view = Bookmark { name, pose: self.pose, sun: self.sun, lighting: None };
+        let projection = match self.mode {
+            CameraMode::Iso => "iso".to_string(),
+            mode => format!("{} {}", mode.name(), self.fov),
+        };
+        let mut chunks = vec![
+            ("Software", "brs_iso".to_string()),
+            ("Camera", serde_json::to_string(&view).unwrap_or_default()),
+            ("Projection", projection),
+        ];
+        if let Some(save) = &self.save {
+            chunks.push(("Source", save.display().to_string()));
+        }
+        chunks
+    }
+}
+
+// One shot's file, fixed when the shot is taken.
+#[derive(Debug, Clone)]
+pub struct ShotOutput {
+    pub path: PathBuf,
+    pub format: ShotFormat,
+    pub transparent: bool,
+    pub metadata: Option<ShotMetadata>,
+}
+
+impl ShotOutput {
+    // Opaque shots drop the alpha channel: after tonemapping and FXAA it isn't
+    // coverage, and viewers would show the build partly see-through.
+    pub fn write(&self, image: Image) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let image = image.try_into_dynamic().map_err(|e| e.to_string())?;
+        match (self.format, self.transparent) {
+            (ShotFormat::Png, true) => self.write_png(image.width(), image.height(), png::ColorType::Rgba, &image.to_rgba8().into_raw()),
+            (ShotFormat::Png, false) => self.write_png(image.width(), image.height(), png::ColorType::Rgb, &image.to_rgb8().into_raw()),
+            (_, true) => image.to_rgba8().save(&self.path).map_err(|e| e.to_string()),
+            (_, false) => image.to_rgb8().save(&self.path).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn write_png(&self, width: u32, height: u32, color: png::ColorType, pixels: &[u8]) -> Result<(), String> {
+        let file = File::create(&self.path).map_err(|e| e.to_string())?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        for (keyword, text) in self.metadata.iter().flat_map(ShotMetadata::text_chunks) {
+            encoder.add_text_chunk(keyword.to_string(), text).map_err(|e| e.to_string())?;
+        }
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(pixels).map_err(|e| e.to_string())?;
+        writer.finish().map_err(|e| e.to_string())
+    }
+
+    // Write to `path` instead, in whatever format its extension names.
+    pub fn at(mut self, path: PathBuf) -> Self {
+        if let Some(format) = path.extension().and_then(|ext| ext.to_str()).and_then(ShotFormat::parse) {
+            self.format = format;
+            self.transparent &= format.has_alpha();
+        }
+        self.path = path;
+        self
+    }
+
+    // Screenshot observer, in place of Bevy's `save_to_disk`.
+    pub fn on_captured(self) -> impl FnMut(On<ScreenshotCaptured>) {
+        move |captured| {
+            let _ = self.save(captured.image.clone());
+        }
+    }
+
+    // The same for F2 shots, which also say where they went, tagged with
+    // the ticket of the request that asked for the shot.
+    pub fn on_shot_captured(self, ticket: Option<u64>) -> impl FnMut(On<ScreenshotCaptured>, MessageWriter<ScreenshotSaved>) {
+        move |captured, mut saved| {
+            let result = self.save(captured.image.clone());
+            saved.write(ScreenshotSaved { path: self.path.clone(), result, ticket });
+        }
+    }
+
+    fn save(&self, image: Image) -> Result<(), String> {
+        let result = self.write(image);
+        match &result {
+            Ok(()) => info!("Screenshot saved to {}", self.path.display()),
+            Err(e) => error!("Cannot save screenshot {}: {e}", self.path.display()),
+        }
+        result
+    }
+}
+
+// An F2 screenshot written, or not, in the order they were taken.
+#[derive(Message, Debug, Clone)]
+pub struct ScreenshotSaved {
+    pub path: PathBuf,
+    pub result: Result<(), String>,
+    // `TakeScreenshot::ticket`; `None` for F2 and `/screenshot take`
+    pub ticket: Option<u64>,
+}
+
+// UTC as `YYYYMMDD-HHMMSS`, from the Unix clock.
+// `{time}` only changes once a second and `{n}` starts over every run, so a
+// name that's already taken gets a counter rather than overwriting the shot.
+fn unused_path(directory: &Path, name: &str, extension: &str) -> PathBuf {
+    let path = directory.join(format!("{name}.{extension}"));
+    if !path.exists() {
+        return path;
+    }
+    (2..)
+        .map(|k| directory.join(format!("{name}-{k}.{extension}")))
+        .find(|path| !path.exists())
+        .unwrap_or(path)
+}
+
+fn timestamp() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let (days, time) = ((secs / 86400) as i64, secs % 86400);
+    // days since 1970 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{year:04}{month:02}{day:02}-{:02}{:02}{:02}", time / 3600, time % 3600 / 60, time % 60)
+}
+
+// `/screenshot` and its settings; registered with the camera plugin, which
+// owns `ScreenshotSettings`.
+
+pub fn screenshot_command(
+    In(_): In<CommandArgs>,
+    settings: Res<ScreenshotSettings>,
+) -> CommandResult {
+    Ok(format!(
+        "{} as {}.{}{} ({{save}} {{time}} {{n}} {{kind}})",
+        settings.directory.display(),
+        settings.template,
+        settings.format.name(),
+        if settings.transparent { ", transparent" } else { "" },
+    ))
+}
+
+// Not saved until `/settings save` or the settings window closes, like
+// `/bind`.
+pub fn screenshot_dir_command(
+    In(args): In<CommandArgs>,
+    mut settings: ResMut<ScreenshotSettings>,
+) -> CommandResult {
+    settings.directory = args.path("path").map(Path::to_path_buf).unwrap_or_default();
+    Ok(format!("screenshots go to {}", settings.directory.display()))
+}
+
+pub fn screenshot_name_command(
+    In(args): In<CommandArgs>,
+    mut settings: ResMut<ScreenshotSettings>,
+) -> CommandResult {
+    let template = args.text("template").unwrap_or_default().to_string();
+    // a name, not a path; `/screenshot dir` says where
+    if template.contains(['/', '\\']) {
+        return Err("the name can't have / or \\ in it (use /screenshot dir)".into());
+    }
+    settings.template = template;
+    Ok(format!("screenshots named {}", settings.template))
+}
+
+pub fn screenshot_format_command(
+    In(args): In<CommandArgs>,
+    mut settings: ResMut<ScreenshotSettings>,
+) -> CommandResult {
+    let name = args.word("format").unwrap_or_default();
+    settings.format = ShotFormat::parse(name).ok_or_else(|| format!("unknown format {name}; one of {}", ShotFormat::NAMES.join(", ")))?;
+    Ok(format!("screenshots saved as {}", settings.format.name()))
+}
+
+pub fn screenshot_transparent_command(
+    In(_): In<CommandArgs>,
+    mut settings: ResMut<ScreenshotSettings>,
+) -> CommandResult {
+    settings.transparent = !settings.transparent;
+    let note = if settings.transparent && !settings.format.has_alpha() { " (not for jpeg)" } else { "" };
+    Ok(format!("transparent background {}{note}", on_off(settings.transparent)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use bevy::{asset::RenderAssetUsages, render::render_resource::{Extent3d, TextureDimension, TextureFormat}};
+
+    use super::*;
+
+    // Two pixels whose alpha post-processing left as something other than
+    // coverage.
+    fn shot() -> Image {
+        let size = Extent3d { width: 2, height: 1, depth_or_array_layers: 1 };
+        Image::new(size, TextureDimension::D2, vec![200, 100, 50, 7, 10, 20, 30, 0], TextureFormat::Rgba8UnormSrgb, RenderAssetUsages::default())
+    }
+
+    fn written(name: &str, transparent: bool) -> (png::ColorType, Vec<u8>) {
+        let path = std::env::temp_dir().join(format!("brs_iso-{}-{name}.png", std::process::id()));
+        let output = ShotOutput { path: path.clone(), format: ShotFormat::Png, transparent, metadata: None };
+        output.write(shot()).unwrap();
+        let mut reader = png::Decoder::new(BufReader::new(File::open(&path).unwrap())).read_info().unwrap();
+        let mut pixels = vec![0; 64];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        pixels.truncate(info.buffer_size());
+        let _ = fs::remove_file(&path);
+        (info.color_type, pixels)
+    }
+
+    #[test]
+    fn opaque_shots_are_fully_opaque() {
+        let (color, pixels) = written("opaque", false);
+        assert_eq!(color, png::ColorType::Rgb);
+        assert_eq!(pixels, [200, 100, 50, 10, 20, 30]);
+    }
+
+    #[test]
+    fn transparent_shots_keep_alpha() {
+        let (color, pixels) = written("transparent", true);
+        assert_eq!(color, png::ColorType::Rgba);
+        assert_eq!(pixels, [200, 100, 50, 7, 10, 20, 30, 0]);
+    }
+
+    #[test]
+    fn jpeg_is_never_transparent() {
+        let settings = ScreenshotSettings { format: ShotFormat::Jpeg, transparent: true, ..default() };
+        assert!(!settings.output("screenshot", 1, None).transparent);
+        assert!(matches!(settings.clear_color(), ClearColorConfig::Default));
+    }
+
+    #[test]
+    fn taken_names_get_a_counter() {
+        let directory = std::env::temp_dir().join(format!("brs_iso-{}-names", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let settings = ScreenshotSettings { directory: directory.clone(), template: "shot".into(), ..default() };
+        assert_eq!(settings.output("screenshot", 1, None).path, directory.join("shot.png"));
+        File::create(directory.join("shot.png")).unwrap();
+        File::create(directory.join("shot-2.png")).unwrap();
+        assert_eq!(settings.output("screenshot", 1, None).path, directory.join("shot-3.png"));
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn settings_round_trip_through_toml() {
+        let settings = ScreenshotSettings { directory: PathBuf::from("shots"), template: "{save}-{n}".into(), format: ShotFormat::WebP, transparent: true };
+        let text = toml::to_string(&settings).unwrap();
+        assert!(text.contains("format = \"webp\""));
+        assert_eq!(toml::from_str::<ScreenshotSettings>(&text).unwrap(), settings);
+    }
+}
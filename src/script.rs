@@ -0,0 +1,225 @@
+use std::{collections::VecDeque, fs, path::{Path, PathBuf}};
+
+use bevy::prelude::*;
+
+use crate::{
+    chat::{print_echo, print_result, ECHOED},
+    console::{run_command, AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand, ConsoleVariables},
+    state::Loading,
+    config_directory, LoadFailed,
+};
+
+// Console scripts: text files of console commands, one per line, run in
+// order. `/exec` runs one; `autoexec.cfg` in the config directory runs at
+// startup, then any `--exec` files from the command line. Lines starting
+// with `#` or `//` are comments and the leading slash is optional.
+// `wait <frames>` holds the script, and it holds by itself while a build
+// loads, so `load` followed by a short wait is enough for the framing to
+// settle before a screenshot. A load that fails stops the script, and
+// scripts can only exec each other so deep.
+
+const AUTOEXEC: &str = "autoexec.cfg";
+// Bounds one frame's work, so a script that execs itself can't hang the app.
+const MAX_LINES_PER_FRAME: usize = 256;
+// How deep `exec`s can nest, so a script that execs itself stops.
+const MAX_EXEC_DEPTH: usize = 16;
+
+pub struct ScriptPlugin;
+
+// Scripts from `--exec`, run after autoexec.
+#[derive(Resource, Default)]
+pub struct StartupScripts(pub Vec<PathBuf>);
+
+struct ScriptLine {
+    line: String,
+    // `file:line`, for errors
+    origin: String,
+    // how many `exec`s deep its file is; 0 for one run from outside a script
+    depth: usize,
+}
+
+#[derive(Resource, Default)]
+struct Script {
+    lines: VecDeque<ScriptLine>,
+    // frames left to hold
+    wait: u32,
+    // the depth of the line being run, while one is
+    running: Option<usize>,
+    // why the script was stopped, to report on its next turn
+    stopped: Option<String>,
+}
+
+impl Script {
+    // Queue `path`'s commands ahead of whatever is left of the running
+    // script, so an `exec` inside a script runs in place. Returns how many.
+    fn exec(&mut self, path: &Path) -> Result<usize, String> {
+        let depth = self.running.map_or(0, |depth| depth + 1);
+        if depth >= MAX_EXEC_DEPTH {
+            return Err(format!("scripts nest more than {MAX_EXEC_DEPTH} deep; does one exec itself?"));
+        }
+        let text = fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+        let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+        let lines: Vec<ScriptLine> = text.lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#') && !line.starts_with("//"))
+            .map(|(i, line)| ScriptLine {
+                line: if line.starts_with('/') { line.to_string() } else { format!("/{line}") },
+                origin: format!("{name}:{}", i + 1),
+                depth,
+            })
+            .collect();
+        let count = lines.len();
+        for line in lines.into_iter().rev() {
+            self.lines.push_front(line);
+        }
+        Ok(count)
+    }
+
+    // Drop the rest of the script.
+    fn stop(&mut self, reason: String) {
+        if self.lines.is_empty() && self.wait == 0 {
+            return;
+        }
+        self.lines.clear();
+        self.wait = 0;
+        self.stopped = Some(reason);
+    }
+}
+
+impl Plugin for ScriptPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Script>()
+            .init_resource::<ConsoleVariables>()
+            .add_systems(PostStartup, queue_startup_scripts)
+            .add_systems(Update, (stop_on_failed_load, run_scripts).chain())
+            .add_console_command(ConsoleCommand::new("/exec", "run a file of console commands").arg(Arg::path("path")).local_only(), exec_command)
+            .add_console_command(ConsoleCommand::new("/wait", "hold the running script for some frames").arg(Arg::int("frames")), wait_command)
+            .add_console_command(
+                ConsoleCommand::new("/set", "list variables, show one, or set one for $name")
+                    .arg(Arg::word("name", &[]).optional())
+                    .arg(Arg::words("value", &[]).optional()),
+                set_command,
+            )
+            .add_console_command(ConsoleCommand::new("/unset", "forget a variable").arg(Arg::word("name", &[])), unset_command)
+            .add_console_command(ConsoleCommand::new("/echo", "print a line").arg(Arg::words("text", &[]).optional()), echo_command);
+    }
+}
+
+fn queue_startup_scripts(
+    mut script: ResMut<Script>,
+    startup: Option<Res<StartupScripts>>,
+) {
+    let autoexec = config_directory().map(|directory| directory.join(AUTOEXEC)).filter(|path| path.is_file());
+    let scripts: Vec<PathBuf> = autoexec.into_iter().chain(startup.iter().flat_map(|startup| startup.0.iter().cloned())).collect();
+    // each one goes in front of the last
+    for path in scripts.iter().rev() {
+        match script.exec(path) {
+            Ok(count) => info!("Running {} ({count} commands)", path.display()),
+            Err(e) => error!("{e}"),
+        }
+    }
+}
+
+// The rest of a script would run against the wrong build, or none.
+fn stop_on_failed_load(
+    mut failed: MessageReader<LoadFailed>,
+    mut script: ResMut<Script>,
+) {
+    if let Some(LoadFailed { path, error }) = failed.read().last() {
+        script.stop(format!("script stopped: couldn't load {}: {error}", path.display()));
+    }
+}
+
+fn run_scripts(world: &mut World) {
+    if let Some(reason) = world.resource_mut::<Script>().stopped.take() {
+        error!(target: ECHOED, "{reason}");
+        print_result(world, Err(reason));
+    }
+    for _ in 0..MAX_LINES_PER_FRAME {
+        if world.resource::<Loading>().0 {
+            return;
+        }
+        let mut script = world.resource_mut::<Script>();
+        if script.wait > 0 {
+            script.wait -= 1;
+            return;
+        }
+        let Some(ScriptLine { line, origin, depth }) = script.lines.pop_front() else { return; };
+        script.running = Some(depth);
+
+        print_echo(world, &line);
+        let result = run_command(world, &line).map_err(|e| format!("{origin}: {e}"));
+        world.resource_mut::<Script>().running = None;
+        if let Err(e) = &result {
+            error!(target: ECHOED, "{e}");
+        }
+        print_result(world, result);
+    }
+}
+
+// A relative path that isn't there is looked for in the config directory,
+// next to autoexec.
+fn exec_command(
+    In(args): In<CommandArgs>,
+    mut script: ResMut<Script>,
+) -> CommandResult {
+    let mut path = args.path("path").map(Path::to_path_buf).unwrap_or_default();
+    if !path.exists() && path.is_relative() {
+        if let Some(config) = config_directory().map(|directory| directory.join(&path)).filter(|path| path.exists()) {
+            path = config;
+        }
+    }
+    let count = script.exec(&path)?;
+    Ok(format!("running {} ({count} commands)", path.display()))
+}
+
+fn wait_command(
+    In(args): In<CommandArgs>,
+    mut script: ResMut<Script>,
+) -> CommandResult {
+    let frames = args.int("frames").and_then(|frames| u32::try_from(frames).ok()).ok_or("frames can't be negative")?;
+    script.wait = frames;
+    Ok(String::new())
+}
+
+fn set_command(
+    In(args): In<CommandArgs>,
+    mut variables: ResMut<ConsoleVariables>,
+) -> CommandResult {
+    let Some(name) = args.word("name") else {
+        if variables.0.is_empty() {
+            return Ok("no variables (/set <name> <value>)".into());
+        }
+        let lines: Vec<String> = variables.0.iter().map(|(name, value)| format!("{name} = {value}")).collect();
+        return Ok(lines.join("\n"));
+    };
+    let value = args.words("value");
+    if value.is_empty() {
+        let value = variables.0.get(name).ok_or_else(|| format!("no variable named {name}"))?;
+        return Ok(format!("{name} = {value}"));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err("variable names are letters, digits and _".into());
+    }
+    let value = value.join(" ");
+    let message = format!("{name} = {value}");
+    variables.0.insert(name.to_string(), value);
+    Ok(message)
+}
+
+fn unset_command(
+    In(args): In<CommandArgs>,
+    mut variables: ResMut<ConsoleVariables>,
+) -> CommandResult {
+    let name = args.word("name").unwrap_or_default();
+    variables.0.remove(name).ok_or_else(|| format!("no variable named {name}"))?;
+    Ok(format!("forgot {name}"))
+}
+
+fn echo_command(
+    In(args): In<CommandArgs>,
+) -> CommandResult {
+    Ok(args.words("text").join(" "))
+}
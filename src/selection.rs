@@ -0,0 +1,430 @@
+use bevy::{platform::collections::{HashMap, HashSet}, prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use brickadia::save::BrickColor;
+
+use crate::{
+    aabb::{Frustum, AABB},
+    cam::IsoCamera,
+    clip::Clip,
+    controls::{Action, Actions},
+    edit::{BrickEdit, BrickEdits, EditHistory, HistoryStep},
+    state::{GameState, Screenshotting},
+    utils::{asset_name, brick_color, hex_color, material_name, owner_name},
+    viewcube::ViewCubeHover,
+    SaveBVH,
+};
+
+// Brick selection. Clicking picks the brick under the cursor (shift toggles
+// it in or out of the selection), ctrl-drag box-selects every brick whose
+// bounds land entirely inside the rectangle (shift adds to the selection).
+// Plain left-drag still pans the camera. Those are the default bindings of
+// `Select`, `BoxSelect` and `PanDrag`.
+
+const SELECT_COLOR: Color = Color::srgb(0.2, 0.75, 1.0);
+const BOX_FILL: egui::Color32 = egui::Color32::from_rgba_premultiplied(10, 38, 51, 51);
+const BOX_STROKE: egui::Color32 = egui::Color32::from_rgb(51, 191, 255);
+// A press that moves less than this many logical pixels is a click, not a drag.
+const CLICK_SLOP: f32 = 4.0;
+// Past this many bricks only the selection's bounds are outlined; thousands of
+// gizmo boxes cost more to draw than the build itself.
+const MAX_OUTLINES: usize = 2000;
+// Rows shown per breakdown in the selection panel.
+const SUMMARY_ROWS: usize = 5;
+// Save materials offered by the panel, one per chunk material slot.
+const MATERIALS: [(&str, &str); 4] = [
+    ("Plastic", "BMC_Plastic"),
+    ("Glow", "BMC_Glow"),
+    ("Glass", "BMC_Glass"),
+    ("Metal", "BMC_Metallic"),
+];
+
+pub struct SelectionPlugin;
+
+#[derive(Resource, Default)]
+pub struct Selection {
+    pub bricks: HashSet<usize>,
+}
+
+impl Selection {
+    pub fn is_empty(&self) -> bool {
+        self.bricks.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bricks.len()
+    }
+
+    // Brick indices in save order, for anything that wants stable output.
+    pub fn sorted(&self) -> Vec<usize> {
+        let mut bricks: Vec<usize> = self.bricks.iter().copied().collect();
+        bricks.sort_unstable();
+        bricks
+    }
+}
+
+// Where the left button went down, while it's held. `None` when the press
+// started over UI and belongs to someone else.
+#[derive(Default)]
+struct PointerDrag {
+    start: Option<Vec2>,
+    boxing: bool,
+}
+
+// Picker state for the panel's edit tools.
+struct EditTools {
+    color: [u8; 3],
+    material: usize,
+}
+
+impl Default for EditTools {
+    fn default() -> Self {
+        Self { color: [255, 255, 255], material: 0 }
+    }
+}
+
+// Cached panel contents, rebuilt when the selection or the build changes.
+#[derive(Default)]
+struct SelectionSummary {
+    bounds: Option<AABB>,
+    assets: Vec<(String, usize)>,
+    materials: Vec<(String, usize)>,
+    owners: Vec<(String, usize)>,
+    colors: Vec<(String, usize)>,
+}
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Selection>()
+            .add_systems(Update, (prune_selection, draw_selection).chain())
+            .add_systems(EguiPrimaryContextPass, (select_bricks, selection_panel).chain());
+    }
+}
+
+fn select_bricks(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<IsoCamera>>,
+    bvh_query: Query<&SaveBVH>,
+    actions: Actions,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    game_state: Res<GameState>,
+    viewcube_hover: Res<ViewCubeHover>,
+    screenshotting: Res<Screenshotting>,
+    clip: Res<Clip>,
+    mut drag: Local<PointerDrag>,
+    mut selection: ResMut<Selection>,
+    mut contexts: EguiContexts,
+) {
+    if screenshotting.0 {
+        return;
+    }
+    let Ok(window) = window_query.single() else { return; };
+    let Some(cursor) = window.cursor_position() else { return; };
+    let Ok(ctx) = contexts.ctx_mut() else { return; };
+
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if game_state.input_listening() && actions.just_pressed(Action::ClearSelection) && !selection.is_empty() {
+        selection.bricks.clear();
+    }
+
+    let boxing = actions.just_pressed(Action::BoxSelect);
+    if boxing || actions.just_pressed(Action::Select) {
+        let blocked = viewcube_hover.0 || ctx.is_pointer_over_area() || !game_state.input_listening();
+        drag.start = (!blocked).then_some(cursor);
+        drag.boxing = boxing;
+    }
+    let Some(start) = drag.start else { return; };
+    let dragged = start.distance(cursor) > CLICK_SLOP;
+    let released = actions.just_released(if drag.boxing { Action::BoxSelect } else { Action::Select });
+
+    if drag.boxing && dragged && !released {
+        let rect = egui::Rect::from_two_pos(egui::pos2(start.x, start.y), egui::pos2(cursor.x, cursor.y));
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("box_select")));
+        painter.rect_filled(rect, 0.0, BOX_FILL);
+        painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, BOX_STROKE), egui::StrokeKind::Inside);
+    }
+
+    if !released {
+        return;
+    }
+    drag.start = None;
+
+    let Ok(save_bvh) = bvh_query.single() else { return; };
+    let Ok((camera, camera_transform)) = cameras.single() else { return; };
+    let build = &save_bvh.generator;
+    // bricks cut away entirely by a section can't be picked
+    let section = clip.frustum();
+
+    if drag.boxing && dragged {
+        let Some(frustum) = screen_rect_frustum(camera, camera_transform, start, cursor) else { return; };
+        let mut found = Vec::new();
+        build.bvh.query(&build.aabbs, |aabb| frustum.intersects_aabb(aabb), |i| {
+            // hidden bricks have no faces and nothing to see
+            if !build.faces[i].is_empty() && frustum.contains_aabb(&build.aabbs[i]) && section.intersects_aabb(&build.aabbs[i]) {
+                found.push(i);
+            }
+        });
+        if !shift {
+            selection.bricks.clear();
+        }
+        selection.bricks.extend(found);
+    } else if !dragged {
+        let hit = camera.viewport_to_world(camera_transform, cursor).ok()
+            .and_then(|ray| build.bvh.raycast(ray, &build.aabbs, &build.faces, |point| section.contains_point(point)));
+        match hit {
+            Some(hit) if shift => {
+                if !selection.bricks.remove(&hit.brick) {
+                    selection.bricks.insert(hit.brick);
+                }
+            },
+            Some(hit) => {
+                selection.bricks.clear();
+                selection.bricks.insert(hit.brick);
+            },
+            None if !shift && !selection.is_empty() => selection.bricks.clear(),
+            None => {}
+        }
+    }
+}
+
+// The four side planes of the region under a screen rectangle, from the
+// camera rays through its corners. Each plane holds two neighbouring corner
+// rays, so this works for parallel (orthographic) and converging rays alike.
+fn screen_rect_frustum(camera: &Camera, camera_transform: &GlobalTransform, a: Vec2, b: Vec2) -> Option<Frustum> {
+    let min = a.min(b);
+    let max = a.max(b);
+    let corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+    let rays = corners.map(|corner| camera.viewport_to_world(camera_transform, corner).ok());
+    let center_ray = camera.viewport_to_world(camera_transform, (min + max) / 2.0).ok()?;
+    let inside = center_ray.origin + *center_ray.direction;
+
+    let mut planes = Vec::with_capacity(4);
+    for k in 0..4 {
+        let (ray_a, ray_b) = (rays[k]?, rays[(k + 1) % 4]?);
+        let p0 = ray_a.origin;
+        let p1 = ray_a.origin + *ray_a.direction;
+        let p2 = ray_b.origin + *ray_b.direction;
+        let mut normal = (p1 - p0).cross(p2 - p0).try_normalize()?;
+        if normal.dot(inside - p0) < 0.0 {
+            normal = -normal;
+        }
+        planes.push(normal.extend(-normal.dot(p0)));
+    }
+    Some(Frustum::new(planes))
+}
+
+// Indices are only meaningful for the build they were picked from: clear on a
+// new load or `/clear`, and drop bricks that edits removed or filters hid.
+fn prune_selection(
+    bvh_query: Query<Ref<SaveBVH>>,
+    mut selection: ResMut<Selection>,
+) {
+    if selection.is_empty() {
+        return;
+    }
+    let Ok(save_bvh) = bvh_query.single() else {
+        selection.bricks.clear();
+        return;
+    };
+    if save_bvh.is_added() {
+        selection.bricks.clear();
+    } else if save_bvh.is_changed() {
+        let build = &save_bvh.generator;
+        let brick_count = build.save_data.bricks.len();
+        let stale = selection.bricks.iter().any(|&i| i >= brick_count || !build.is_present(i));
+        if stale {
+            selection.bricks.retain(|&i| i < brick_count && build.is_present(i));
+        }
+    }
+}
+
+fn draw_selection(
+    selection: Res<Selection>,
+    bvh_query: Query<&SaveBVH>,
+    screenshotting: Res<Screenshotting>,
+    mut gizmos: Gizmos,
+) {
+    if selection.is_empty() || screenshotting.0 {
+        return;
+    }
+    let Ok(save_bvh) = bvh_query.single() else { return; };
+    let aabbs = &save_bvh.generator.aabbs;
+
+    if selection.len() <= MAX_OUTLINES {
+        for &i in &selection.bricks {
+            let aabb = &aabbs[i];
+            gizmos.primitive_3d(
+                &Cuboid { half_size: aabb.halfwidths.as_vec3() },
+                aabb.center.as_vec3(),
+                SELECT_COLOR,
+            );
+        }
+    } else {
+        let selected: Vec<AABB> = selection.bricks.iter().map(|&i| aabbs[i]).collect();
+        if let Some(bounds) = AABB::bounds(&selected) {
+            gizmos.primitive_3d(
+                &Cuboid { half_size: bounds.halfwidths.as_vec3() },
+                bounds.center.as_vec3(),
+                SELECT_COLOR,
+            );
+        }
+    }
+}
+
+fn selection_panel(
+    mut bvh_query: Query<&mut SaveBVH>,
+    screenshotting: Res<Screenshotting>,
+    history: Res<EditHistory>,
+    mut selection: ResMut<Selection>,
+    mut summary: Local<SelectionSummary>,
+    mut tools: Local<EditTools>,
+    mut edits: MessageWriter<BrickEdits>,
+    mut steps: MessageWriter<HistoryStep>,
+    mut contexts: EguiContexts,
+) {
+    if selection.is_empty() || screenshotting.0 {
+        return;
+    }
+    let Ok(mut save_bvh) = bvh_query.single_mut() else { return; };
+    if selection.is_changed() || save_bvh.is_changed() {
+        *summary = summarize(&save_bvh, &selection);
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return; };
+
+    let mut clear = false;
+    let mut action = None;
+    egui::Window::new("Selection")
+        .default_pos(egui::pos2(12.0, 60.0))
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label(format!("{} bricks", selection.len()));
+            if let Some(bounds) = summary.bounds {
+                // save axes (Z up), to match brick positions
+                let (min, max) = (bounds.min(), bounds.max());
+                ui.label(format!("Bounds: ({}, {}, {}) to ({}, {}, {})", min.x, min.z, min.y, max.x, max.z, max.y));
+            }
+            for (title, rows) in [
+                ("Assets", &summary.assets),
+                ("Materials", &summary.materials),
+                ("Owners", &summary.owners),
+                ("Colors", &summary.colors),
+            ] {
+                ui.collapsing(title, |ui| {
+                    for (name, count) in rows {
+                        ui.label(format!("{count:>6}  {name}"));
+                    }
+                });
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Delete").clicked() {
+                    action = Some(EditAction::Delete);
+                }
+                if ui.button("Hide").clicked() {
+                    action = Some(EditAction::Visible(false));
+                }
+                if ui.button("Show").clicked() {
+                    action = Some(EditAction::Visible(true));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgb(&mut tools.color);
+                if ui.button("Recolor").clicked() {
+                    action = Some(EditAction::Color);
+                }
+            });
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("selection_material")
+                    .selected_text(MATERIALS[tools.material].0)
+                    .show_ui(ui, |ui| {
+                        for (i, (label, _)) in MATERIALS.iter().enumerate() {
+                            ui.selectable_value(&mut tools.material, i, *label);
+                        }
+                    });
+                if ui.button("Set material").clicked() {
+                    action = Some(EditAction::Material);
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.add_enabled(history.can_undo(), egui::Button::new("Undo")).clicked() {
+                    steps.write(HistoryStep::Undo);
+                }
+                if ui.add_enabled(history.can_redo(), egui::Button::new("Redo")).clicked() {
+                    steps.write(HistoryStep::Redo);
+                }
+                if ui.button("Clear").clicked() {
+                    clear = true;
+                }
+            });
+            ui.weak("shift-click toggles, ctrl-drag box-selects, Esc clears");
+        });
+
+    if let Some(action) = action {
+        let bricks = selection.sorted();
+        let batch: Vec<BrickEdit> = match action {
+            EditAction::Delete => bricks.into_iter().map(BrickEdit::Remove).collect(),
+            EditAction::Visible(visible) => bricks.into_iter().map(|i| BrickEdit::SetVisible(i, visible)).collect(),
+            EditAction::Color => {
+                let [r, g, b] = tools.color;
+                let color = BrickColor::Unique(brickadia::save::Color { r, g, b, a: 255 });
+                bricks.into_iter().map(|i| BrickEdit::SetColor(i, color.clone())).collect()
+            },
+            EditAction::Material => {
+                // only touches the header when the save didn't use this material yet
+                let material = save_bvh.generator.material_index(MATERIALS[tools.material].1);
+                bricks.into_iter().map(|i| BrickEdit::SetMaterial(i, material)).collect()
+            },
+        };
+        edits.write(BrickEdits(batch));
+    }
+    if clear {
+        selection.bricks.clear();
+    }
+}
+
+enum EditAction {
+    Delete,
+    Visible(bool),
+    Color,
+    Material,
+}
+
+fn summarize(save_bvh: &SaveBVH, selection: &Selection) -> SelectionSummary {
+    let build = &save_bvh.generator;
+    let save_data = &build.save_data;
+
+    let mut assets: HashMap<&str, usize> = HashMap::default();
+    let mut materials: HashMap<&str, usize> = HashMap::default();
+    let mut owners: HashMap<&str, usize> = HashMap::default();
+    let mut colors: HashMap<String, usize> = HashMap::default();
+    let mut selected = Vec::with_capacity(selection.len());
+    for &i in &selection.bricks {
+        let brick = &save_data.bricks[i];
+        *assets.entry(asset_name(save_data, brick)).or_default() += 1;
+        *materials.entry(material_name(save_data, brick)).or_default() += 1;
+        *owners.entry(owner_name(save_data, brick)).or_default() += 1;
+        *colors.entry(hex_color(brick_color(save_data, brick))).or_default() += 1;
+        selected.push(build.aabbs[i]);
+    }
+
+    SelectionSummary {
+        bounds: AABB::bounds(&selected),
+        assets: top_counts(assets.into_iter().map(|(name, count)| (name.to_string(), count))),
+        materials: top_counts(materials.into_iter().map(|(name, count)| (name.to_string(), count))),
+        owners: top_counts(owners.into_iter().map(|(name, count)| (name.to_string(), count))),
+        colors: top_counts(colors.into_iter()),
+    }
+}
+
+// Most common first; the tail is folded into one "others" row.
+fn top_counts(counts: impl Iterator<Item = (String, usize)>) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = counts.collect();
+    counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    if counts.len() > SUMMARY_ROWS {
+        let rest = counts.split_off(SUMMARY_ROWS);
+        counts.push((format!("{} others", rest.len()), rest.iter().map(|(_, count)| count).sum()));
+    }
+    counts
+}
@@ -1,47 +1,426 @@
-use bevy::prelude::*;
+use std::{fs, ops::RangeInclusive, path::PathBuf};
+
+use bevy::{audio::Volume, prelude::*};
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config_directory,
+    console::{on_off, AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand},
+    controls::{captured_binding, Action, Binding, Bindings, Rebinding},
+    screenshot::{ScreenshotSettings, ShotFormat},
+    sky::Lighting,
+    state::Screenshotting,
+};
+
+// The viewer's tunables, kept in `settings.toml` in the config directory
+// and edited in the settings window (`/settings`). They apply as they're
+// changed, except the light limit, which applies to the next build loaded.
+// Where screenshots go and how they're named is here too, under
+// `[screenshots]`, the sun & sky lighting under `[lighting]`, and the key
+// and mouse bindings under `[bindings]`.
+// A field missing from the file keeps its default, so old files still load.
+
+const SETTINGS_FILE: &str = "settings.toml";
+const SETTINGS_HEADER: &str = "# brs_iso settings; delete a line to go back to its default\n\n";
+
+// Up to wgpu's default `max_texture_dimension_2d`, like `cam::HIRES_MAX_DIM`;
+// a bigger shadow map fails to allocate.
+const SHADOW_MAP_SIZES: [usize; 4] = [1024, 2048, 4096, 8192];
+const SOFT_SHADOW_RANGE: RangeInclusive<f32> = 0.0..=100.0;
+const ZOOM_SPEED_RANGE: RangeInclusive<f32> = 1.0..=50.0;
+const HIRES_SCALE_RANGE: RangeInclusive<f32> = 1.0..=8.0;
+const LIGHT_LIMIT_RANGE: RangeInclusive<usize> = 0..=20_000;
+const VOLUME_RANGE: RangeInclusive<f32> = 0.0..=1.0;
 
 pub struct SettingsPlugin;
 
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Sun shadow map resolution per side.
+    pub shadow_map_size: usize,
+    // PCSS blocker-search radius in world units; bigger is softer.
+    pub sun_soft_shadow_size: f32,
+    // Scroll zoom rate, in log scale per wheel line per second.
+    pub zoom_speed: f32,
+    // Supersample factor for F3 high-res captures. The output is up to this
+    // many times the window's pixel dimensions, so 4x of a 1080p window is
+    // ~7680x4320 (8K), clamped to the GPU's largest texture.
+    pub hires_scale: f32,
+    // Builds with more brick lights than this load with them hidden, since
+    // thousands of lights tank the framerate; `/lights` turns them on.
+    pub light_limit: usize,
+    // Master volume for sounds started from now on.
+    pub volume: f32,
+    // `/screenshot`'s folder, name template, format and background; tables
+    // go last.
+    pub screenshots: ScreenshotSettings,
+    // The sun & sky window's lighting, as last set.
+    pub lighting: Lighting,
+    // Keys and mouse buttons for each action.
+    pub bindings: Bindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            shadow_map_size: 8192,
+            sun_soft_shadow_size: 20.0,
+            zoom_speed: 12.0,
+            hires_scale: 4.0,
+            light_limit: 1000,
+            volume: 0.2,
+            screenshots: ScreenshotSettings::default(),
+            lighting: Lighting::default(),
+            bindings: Bindings::default(),
+        }
+    }
+}
+
+impl Settings {
+    // Within what the window allows, whatever the file said.
+    fn clamped(self) -> Self {
+        let clamp = |value: f32, range: RangeInclusive<f32>| value.clamp(*range.start(), *range.end());
+        Self {
+            shadow_map_size: SHADOW_MAP_SIZES.into_iter().min_by_key(|size| size.abs_diff(self.shadow_map_size)).unwrap_or(8192),
+            sun_soft_shadow_size: clamp(self.sun_soft_shadow_size, SOFT_SHADOW_RANGE),
+            zoom_speed: clamp(self.zoom_speed, ZOOM_SPEED_RANGE),
+            hires_scale: clamp(self.hires_scale, HIRES_SCALE_RANGE),
+            light_limit: self.light_limit.min(*LIGHT_LIMIT_RANGE.end()),
+            volume: clamp(self.volume, VOLUME_RANGE),
+            screenshots: self.screenshots.clamped(),
+            lighting: self.lighting,
+            bindings: self.bindings,
+        }
+    }
+}
+
+// The settings as last read from or written to the file, to tell whether
+// there's anything to save.
+#[derive(Resource)]
+pub struct StoredSettings(Settings);
+
+impl StoredSettings {
+    // Writes `settings` to the file if they differ from what's there.
+    pub fn save_changes(&mut self, settings: &Settings) {
+        if *settings == self.0 {
+            return;
+        }
+        match store_settings(settings) {
+            Ok(path) => {
+                info!("Saved settings to {}", path.display());
+                self.0 = settings.clone();
+            },
+            Err(e) => error!("Cannot save settings: {e}"),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct SettingsWindowOpen(pub bool);
+
+// A binding being picked in the window: which action, and which of its
+// bindings it replaces (`None` adds one).
+#[derive(Clone, Copy)]
+struct Capture {
+    action: Action,
+    slot: Option<usize>,
+}
+
+const CONFLICT_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 90, 90);
+
 impl Plugin for SettingsPlugin {
-    fn build(&self, _app: &mut App) {
-        //app.add_systems(Startup, settings_ui);
+    fn build(&self, app: &mut App) {
+        // read now, so every plugin's startup systems see them
+        let settings = load_settings();
+        app
+            .insert_resource(GlobalVolume::new(Volume::Linear(settings.volume)))
+            .insert_resource(StoredSettings(settings.clone()))
+            .insert_resource(settings)
+            .init_resource::<SettingsWindowOpen>()
+            .init_resource::<Rebinding>()
+            .add_systems(Update, apply_volume)
+            .add_systems(EguiPrimaryContextPass, settings_window)
+            .add_console_command(ConsoleCommand::new("/settings", "toggle the settings window").arg(Arg::switch()), settings_command)
+            .add_console_command(ConsoleCommand::new("/settings save", "write the settings to settings.toml").local_only(), settings_save_command)
+            .add_console_command(ConsoleCommand::new("/settings reset", "put every setting back to its default"), settings_reset_command)
+            .add_console_command(
+                ConsoleCommand::new("/bind", "show an action's keys, or set them (`none` clears)")
+                    .arg(Arg::word("action", &Action::NAMES))
+                    .arg(Arg::words("keys", &["none"]).optional()),
+                bind_command,
+            );
     }
 }
 
-fn _settings_ui(
-    mut commands: Commands,
-) {
-    commands.spawn((
-        Node {
-            width: Val::Percent(100.0),
-            height: Val::Percent(100.0),
-            align_items: AlignItems::Center,
-            justify_content: JustifyContent::Center,
-            ..default()
+fn settings_path() -> Option<PathBuf> {
+    config_directory().map(|directory| directory.join(SETTINGS_FILE))
+}
+
+// Defaults when there's no file yet, or it can't be read.
+fn load_settings() -> Settings {
+    let Some(path) = settings_path() else { return Settings::default(); };
+    let Ok(text) = fs::read_to_string(&path) else { return Settings::default(); };
+    match toml::from_str::<Settings>(&text) {
+        Ok(settings) => settings.clamped(),
+        Err(e) => {
+            warn!("Ignoring {}: {e}", path.display());
+            Settings::default()
         },
-    )).with_children(|parent| {
-        parent.spawn((
-            Node {
-                width: Val::Px(800.0),
-                height: Val::Px(600.0),
-                align_items: AlignItems::Start,
-                justify_content: JustifyContent::Start,
-                ..default()
-            },
-            BorderColor::all(Color::BLACK),
-            BackgroundColor(Color::srgba(0.2, 0.2, 0.2, 0.7)),
-        )).with_children(|parent| {
-            parent.spawn((
-                Text::new("Settings"),
-                TextFont { font_size: FontSize::Px(16.0), ..default() },
-                TextColor(Color::BLACK),
-                Node {
-                    position_type: PositionType::Absolute,
-                    top: Val::Px(10.),
-                    left: Val::Px(10.),
-                    ..default()
-                },
-            ));
+    }
+}
+
+fn store_settings(settings: &Settings) -> Result<PathBuf, String> {
+    let path = settings_path().ok_or("no config directory")?;
+    let text = toml::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(&path, format!("{SETTINGS_HEADER}{text}")).map_err(|e| format!("couldn't write {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+fn apply_volume(
+    settings: Res<Settings>,
+    mut volume: ResMut<GlobalVolume>,
+) {
+    if settings.is_changed() {
+        volume.volume = Volume::Linear(settings.volume);
+    }
+}
+
+fn settings_command(
+    In(args): In<CommandArgs>,
+    mut open: ResMut<SettingsWindowOpen>,
+) -> CommandResult {
+    open.0 = args.switch(open.0);
+    Ok(format!("settings window {}", on_off(open.0)))
+}
+
+fn settings_save_command(
+    In(_): In<CommandArgs>,
+    settings: Res<Settings>,
+    mut stored: ResMut<StoredSettings>,
+) -> CommandResult {
+    let path = store_settings(&settings)?;
+    stored.0 = settings.clone();
+    Ok(format!("saved settings to {}", path.display()))
+}
+
+// Not saved until `/settings save` or the window closes.
+fn settings_reset_command(
+    In(_): In<CommandArgs>,
+    mut settings: ResMut<Settings>,
+) -> CommandResult {
+    *settings = Settings::default();
+    Ok("settings back to defaults (/settings save keeps them)".into())
+}
+
+// `/bind undo Ctrl+KeyZ MouseBack`, in the settings file's spelling. Not
+// saved until `/settings save` or the settings window closes.
+fn bind_command(
+    In(args): In<CommandArgs>,
+    mut settings: ResMut<Settings>,
+) -> CommandResult {
+    let name = args.word("action").unwrap_or_default();
+    let action = Action::parse(name).ok_or_else(|| format!("no action {name}"))?;
+    let keys = args.words("keys");
+    if !keys.is_empty() {
+        let bindings = match keys.as_slice() {
+            ["none"] => Vec::new(),
+            _ => keys.iter().map(|key| Binding::parse(key).ok_or_else(|| format!("can't bind {key}"))).collect::<Result<_, _>>()?,
+        };
+        settings.bindings.set(action, bindings);
+    }
+    let bound: Vec<String> = settings.bindings.get(action).iter().map(Binding::to_string).collect();
+    let mut reply = format!("{name}: {}", if bound.is_empty() { "none".to_string() } else { bound.join(" ") });
+    for (binding, a, b) in settings.bindings.conflicts() {
+        if a == action || b == action {
+            reply += &format!("\n{binding} is also bound to {}", if a == action { b.name() } else { a.name() });
+        }
+    }
+    Ok(reply)
+}
+
+// Changes apply as they're made; closing the window saves them.
+fn settings_window(
+    mut open: ResMut<SettingsWindowOpen>,
+    mut settings: ResMut<Settings>,
+    mut stored: ResMut<StoredSettings>,
+    screenshotting: Res<Screenshotting>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut capture: Local<Option<Capture>>,
+    mut contexts: EguiContexts,
+) {
+    if !open.0 || screenshotting.0 {
+        if !open.0 && capture.take().is_some() {
+            rebinding.0 = false;
+        }
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return; };
+
+    let mut edited = settings.clone();
+    if let Some(Capture { action, slot }) = *capture {
+        if let Some(picked) = captured_binding(&keys, &mouse) {
+            if let Some(binding) = picked {
+                let mut bindings = edited.bindings.get(action).to_vec();
+                match slot {
+                    Some(i) if i < bindings.len() => bindings[i] = binding,
+                    _ if !bindings.contains(&binding) => bindings.push(binding),
+                    _ => {},
+                }
+                edited.bindings.set(action, bindings);
+            }
+            *capture = None;
+        }
+    }
+    let conflicts = edited.bindings.conflicts();
+    let unsaved = edited != stored.0;
+    let mut still_open = true;
+    let mut save = false;
+    let mut revert = false;
+    let mut start_capture = None;
+
+    egui::Window::new("Settings")
+        .open(&mut still_open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("settings").num_columns(2).show(ui, |ui| {
+                ui.label("Shadow map");
+                egui::ComboBox::from_id_salt("shadow_map_size")
+                    .selected_text(format!("{0}x{0}", edited.shadow_map_size))
+                    .show_ui(ui, |ui| {
+                        for size in SHADOW_MAP_SIZES {
+                            ui.selectable_value(&mut edited.shadow_map_size, size, format!("{size}x{size}"));
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Shadow softness");
+                ui.add(egui::Slider::new(&mut edited.sun_soft_shadow_size, SOFT_SHADOW_RANGE));
+                ui.end_row();
+
+                ui.label("Zoom speed");
+                ui.add(egui::Slider::new(&mut edited.zoom_speed, ZOOM_SPEED_RANGE));
+                ui.end_row();
+
+                ui.label("F3 scale");
+                ui.add(egui::Slider::new(&mut edited.hires_scale, HIRES_SCALE_RANGE).suffix("x"));
+                ui.end_row();
+
+                ui.label("Light limit")
+                    .on_hover_text("builds with more lights load with them hidden; applies to the next build");
+                ui.add(egui::Slider::new(&mut edited.light_limit, LIGHT_LIMIT_RANGE).logarithmic(true));
+                ui.end_row();
+
+                ui.label("Volume");
+                ui.add(egui::Slider::new(&mut edited.volume, VOLUME_RANGE));
+                ui.end_row();
+
+                ui.label("Screenshot folder");
+                let mut directory = edited.screenshots.directory.display().to_string();
+                if ui.text_edit_singleline(&mut directory).changed() {
+                    edited.screenshots.directory = PathBuf::from(directory);
+                }
+                ui.end_row();
+
+                ui.label("Screenshot name")
+                    .on_hover_text("{save}, {time}, {n} and {kind} are filled in");
+                let mut template = edited.screenshots.template.clone();
+                if ui.text_edit_singleline(&mut template).changed() && !template.contains(['/', '\\']) {
+                    edited.screenshots.template = template;
+                }
+                ui.end_row();
+
+                ui.label("Screenshot format");
+                egui::ComboBox::from_id_salt("screenshot_format")
+                    .selected_text(edited.screenshots.format.name())
+                    .show_ui(ui, |ui| {
+                        for format in [ShotFormat::Png, ShotFormat::Jpeg, ShotFormat::WebP] {
+                            ui.selectable_value(&mut edited.screenshots.format, format, format.name());
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Transparent background")
+                    .on_hover_text("PNG and WebP only");
+                ui.checkbox(&mut edited.screenshots.transparent, "");
+                ui.end_row();
+            });
+            egui::CollapsingHeader::new("Controls").show(ui, |ui| {
+                ui.weak("click a binding, then press a key or mouse button (Esc cancels)");
+                egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                    egui::Grid::new("controls").num_columns(2).show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.label(action.label());
+                            ui.horizontal(|ui| {
+                                let mut bindings = edited.bindings.get(action).to_vec();
+                                let mut removed = None;
+                                for (i, binding) in bindings.iter().enumerate() {
+                                    let picking = capture.as_ref().is_some_and(|c| c.action == action && c.slot == Some(i));
+                                    let conflicted = conflicts.iter().any(|&(other, a, b)| other == *binding && (a == action || b == action));
+                                    let mut text = egui::RichText::new(if picking { "press a key...".to_string() } else { binding.to_string() });
+                                    if conflicted {
+                                        text = text.color(CONFLICT_COLOR);
+                                    }
+                                    if ui.button(text).clicked() {
+                                        start_capture = Some((action, Some(i)));
+                                    }
+                                    if ui.small_button("x").on_hover_text("remove").clicked() {
+                                        removed = Some(i);
+                                    }
+                                }
+                                if let Some(i) = removed {
+                                    bindings.remove(i);
+                                    edited.bindings.set(action, bindings);
+                                }
+                                if capture.as_ref().is_some_and(|c| c.action == action && c.slot.is_none()) {
+                                    ui.label("press a key...");
+                                } else if ui.small_button("+").on_hover_text("add a binding").clicked() {
+                                    start_capture = Some((action, None));
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                });
+                for (binding, a, b) in &conflicts {
+                    ui.colored_label(CONFLICT_COLOR, format!("{binding} is bound to both {} and {}", a.label(), b.label()));
+                }
+                if ui.button("Reset controls").clicked() {
+                    edited.bindings = Bindings::default();
+                }
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                save = ui.add_enabled(unsaved, egui::Button::new("Save")).clicked();
+                revert = ui.add_enabled(unsaved, egui::Button::new("Revert")).clicked();
+                if ui.button("Defaults").clicked() {
+                    edited = Settings::default();
+                }
+            });
         });
-    });
+
+    if let Some((action, slot)) = start_capture {
+        *capture = Some(Capture { action, slot });
+    }
+    // only flag a change when there is one
+    if rebinding.0 != capture.is_some() {
+        rebinding.0 = capture.is_some();
+    }
+    if revert {
+        edited = stored.0.clone();
+    }
+    // only flag a change when there is one, so nothing reapplies every frame
+    if edited != *settings {
+        *settings = edited;
+    }
+    if save || !still_open {
+        stored.save_changes(&settings);
+    }
+    open.0 = still_open;
 }
@@ -0,0 +1,426 @@
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::Light,
+    console::{on_off, AddConsoleCommand, Arg, CommandArgs, CommandResult, ConsoleCommand},
+    lit::{sun_angles, sun_direction, Sun},
+    settings::{Settings, StoredSettings},
+    state::Screenshotting,
+};
+
+// Sun and sky lighting: the sun's color temperature and brightness and the
+// ambient fill, set in the "Sun & sky" window (`/sky`) or from a named
+// preset. The sun's direction stays on its transform, where the arrow keys,
+// `/sun` and bookmarks move it; the window's sliders move it too. Bookmarks
+// save the lighting along with the view. Night lighting turns on the brick
+// lights a big build loads with hidden. The lighting is kept in the settings
+// under `[lighting]`; closing the window saves it for next launch.
+
+const TEMPERATURE_RANGE: RangeInclusive<f32> = 1500.0..=12000.0;
+const ILLUMINANCE_RANGE: RangeInclusive<f32> = 0.0..=100_000.0;
+const AMBIENT_RANGE: RangeInclusive<f32> = 0.0..=5000.0;
+
+pub struct SkyPlugin;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightingPreset {
+    Noon,
+    GoldenHour,
+    // a dim blue moon, so brick lights carry the scene
+    Night,
+    // bright flat fill and a weak sun, for faint shadows
+    Overcast,
+}
+
+impl LightingPreset {
+    pub const ALL: [LightingPreset; 4] = [LightingPreset::Noon, LightingPreset::GoldenHour, LightingPreset::Night, LightingPreset::Overcast];
+    pub const NAMES: [&'static str; 4] = ["noon", "golden_hour", "night", "overcast"];
+
+    pub fn name(self) -> &'static str {
+        LightingPreset::NAMES[self as usize]
+    }
+
+    pub fn parse(name: &str) -> Option<LightingPreset> {
+        LightingPreset::ALL.into_iter().find(|preset| preset.name() == name)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            LightingPreset::Noon => "Noon",
+            LightingPreset::GoldenHour => "Golden hour",
+            LightingPreset::Night => "Night",
+            LightingPreset::Overcast => "Overcast",
+        }
+    }
+
+    // The sun's elevation in degrees; presets keep its heading.
+    pub fn elevation(self) -> f32 {
+        match self {
+            LightingPreset::Noon => 70.0,
+            LightingPreset::GoldenHour => 8.0,
+            LightingPreset::Night => 35.0,
+            LightingPreset::Overcast => 60.0,
+        }
+    }
+
+    pub fn lighting(self) -> Lighting {
+        let (temperature, illuminance, ambient_color, ambient_brightness) = match self {
+            LightingPreset::Noon => (6500.0, 20000.0, Color::WHITE, 600.0),
+            LightingPreset::GoldenHour => (3000.0, 8000.0, Color::srgb(1.0, 0.8, 0.62), 400.0),
+            LightingPreset::Night => (9000.0, 2.0, Color::srgb(0.35, 0.45, 0.8), 40.0),
+            LightingPreset::Overcast => (7000.0, 3000.0, Color::srgb(0.9, 0.93, 1.0), 1800.0),
+        };
+        Lighting {
+            preset: Some(self),
+            temperature,
+            illuminance,
+            ambient_color,
+            ambient_brightness,
+            night: self == LightingPreset::Night,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredLighting", into = "StoredLighting")]
+pub struct Lighting {
+    // The preset this came from, until something's changed by hand.
+    pub preset: Option<LightingPreset>,
+    // Sun color temperature in Kelvin.
+    pub temperature: f32,
+    // Sun brightness in lux.
+    pub illuminance: f32,
+    // Ambient fill on the cameras; it lifts shadows off pure black.
+    pub ambient_color: Color,
+    pub ambient_brightness: f32,
+    // Brick lights on, however many the build has.
+    pub night: bool,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        LightingPreset::Noon.lighting()
+    }
+}
+
+impl Lighting {
+    pub fn sun_color(&self) -> Color {
+        kelvin_color(self.temperature)
+    }
+
+    fn ambient(&self) -> AmbientLight {
+        AmbientLight {
+            color: self.ambient_color,
+            brightness: self.ambient_brightness,
+            ..default()
+        }
+    }
+}
+
+// `[lighting]` as settings.toml spells it: the preset by name and the
+// ambient color as sRGB. A missing field keeps noon's value.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct StoredLighting {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preset: Option<String>,
+    temperature: f32,
+    illuminance: f32,
+    ambient_color: [f32; 3],
+    ambient_brightness: f32,
+    night: bool,
+}
+
+impl Default for StoredLighting {
+    fn default() -> Self {
+        Lighting::default().into()
+    }
+}
+
+impl From<Lighting> for StoredLighting {
+    fn from(lighting: Lighting) -> Self {
+        let ambient = lighting.ambient_color.to_srgba();
+        Self {
+            preset: lighting.preset.map(|preset| preset.name().to_string()),
+            temperature: lighting.temperature,
+            illuminance: lighting.illuminance,
+            ambient_color: [ambient.red, ambient.green, ambient.blue],
+            ambient_brightness: lighting.ambient_brightness,
+            night: lighting.night,
+        }
+    }
+}
+
+// Within what the window allows, whatever the file said.
+impl From<StoredLighting> for Lighting {
+    fn from(stored: StoredLighting) -> Self {
+        let clamp = |value: f32, range: RangeInclusive<f32>| value.clamp(*range.start(), *range.end());
+        let [r, g, b] = stored.ambient_color.map(|channel| channel.clamp(0.0, 1.0));
+        Self {
+            preset: stored.preset.as_deref().and_then(LightingPreset::parse),
+            temperature: clamp(stored.temperature, TEMPERATURE_RANGE),
+            illuminance: clamp(stored.illuminance, ILLUMINANCE_RANGE),
+            ambient_color: Color::srgb(r, g, b),
+            ambient_brightness: clamp(stored.ambient_brightness, AMBIENT_RANGE),
+            night: stored.night,
+        }
+    }
+}
+
+// Blackbody color, after Tanner Helland's fit; good from 1000K to 40000K.
+fn kelvin_color(kelvin: f32) -> Color {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let red = if t <= 66.0 { 255.0 } else { 329.69873 * (t - 60.0).powf(-0.13320476) };
+    let green = if t <= 66.0 { 99.4708 * t.ln() - 161.11957 } else { 288.12217 * (t - 60.0).powf(-0.07551485) };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.04479
+    };
+    let channel = |value: f32| value.clamp(0.0, 255.0) / 255.0;
+    Color::srgb(channel(red), channel(green), channel(blue))
+}
+
+#[derive(Resource, Default)]
+pub struct SkyWindowOpen(pub bool);
+
+impl Plugin for SkyPlugin {
+    fn build(&self, app: &mut App) {
+        // SettingsPlugin has read the file by now
+        let lighting = app.world().get_resource::<Settings>().map(|settings| settings.lighting).unwrap_or_default();
+        app
+            .insert_resource(lighting)
+            .init_resource::<SkyWindowOpen>()
+            .add_systems(Update, (sync_lighting, apply_lighting).chain())
+            .add_systems(EguiPrimaryContextPass, sky_window)
+            .add_console_command(ConsoleCommand::new("/sky", "toggle the sun & sky window").arg(Arg::switch()), sky_command)
+            .add_console_command(
+                ConsoleCommand::new("/sky preset", "light the scene for noon, golden hour, night or overcast")
+                    .arg(Arg::choice("preset", &LightingPreset::NAMES)),
+                sky_preset_command,
+            );
+    }
+}
+
+// Sets the lighting and the sun's elevation for `preset`, keeping its heading.
+pub fn apply_preset(preset: LightingPreset, lighting: &mut Lighting, sun: &mut Transform) {
+    *lighting = preset.lighting();
+    let (heading, _) = sun_angles(*sun.forward());
+    sun.look_to(sun_direction(heading, preset.elevation()), Vec3::Y);
+}
+
+// Keeps the settings' copy of the lighting current, so saving the settings
+// saves it; resetting or reverting the settings puts it back.
+fn sync_lighting(
+    mut settings: ResMut<Settings>,
+    mut lighting: ResMut<Lighting>,
+) {
+    if settings.lighting == *lighting {
+        return;
+    }
+    if lighting.is_changed() {
+        settings.lighting = *lighting;
+    } else if settings.is_changed() {
+        *lighting = settings.lighting;
+    }
+}
+
+// The sun and every camera's ambient fill follow `Lighting`. Going to night
+// turns on the brick lights, including those hidden by the light limit;
+// they're left on after.
+fn apply_lighting(
+    lighting: Res<Lighting>,
+    mut was_night: Local<bool>,
+    mut sun_query: Query<&mut DirectionalLight, With<Sun>>,
+    mut ambient_query: Query<&mut AmbientLight>,
+    mut light_query: Query<&mut Visibility, With<Light>>,
+) {
+    if !lighting.is_changed() {
+        return;
+    }
+    for mut sun in sun_query.iter_mut() {
+        sun.color = lighting.sun_color();
+        sun.illuminance = lighting.illuminance;
+    }
+    for mut ambient in ambient_query.iter_mut() {
+        *ambient = lighting.ambient();
+    }
+    if lighting.night && !*was_night {
+        for mut visibility in light_query.iter_mut() {
+            *visibility = Visibility::Visible;
+        }
+    }
+    *was_night = lighting.night;
+}
+
+fn sky_command(
+    In(args): In<CommandArgs>,
+    mut open: ResMut<SkyWindowOpen>,
+) -> CommandResult {
+    open.0 = args.switch(open.0);
+    Ok(format!("sun & sky window {}", on_off(open.0)))
+}
+
+fn sky_preset_command(
+    In(args): In<CommandArgs>,
+    mut lighting: ResMut<Lighting>,
+    mut sun_query: Query<&mut Transform, With<Sun>>,
+) -> CommandResult {
+    let name = args.word("preset").unwrap_or_default();
+    let preset = LightingPreset::parse(name).ok_or_else(|| format!("no preset {name}"))?;
+    let mut sun = sun_query.single_mut().map_err(|_| "no sun")?;
+    apply_preset(preset, &mut lighting, &mut sun);
+    Ok(format!("{} lighting", preset.label().to_lowercase()))
+}
+
+fn sky_window(
+    mut open: ResMut<SkyWindowOpen>,
+    mut lighting: ResMut<Lighting>,
+    mut settings: ResMut<Settings>,
+    mut stored: ResMut<StoredSettings>,
+    mut sun_query: Query<&mut Transform, With<Sun>>,
+    screenshotting: Res<Screenshotting>,
+    mut contexts: EguiContexts,
+) {
+    if !open.0 || screenshotting.0 {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return; };
+    let Ok(mut sun) = sun_query.single_mut() else { return; };
+
+    let mut edited = *lighting;
+    let (heading, elevation) = sun_angles(*sun.forward());
+    let (mut new_heading, mut new_elevation) = (heading, elevation);
+    let mut preset = None;
+
+    let mut still_open = true;
+    egui::Window::new("Sun & sky")
+        .open(&mut still_open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for choice in LightingPreset::ALL {
+                    if ui.selectable_label(edited.preset == Some(choice), choice.label()).clicked() {
+                        preset = Some(choice);
+                    }
+                }
+            });
+            ui.separator();
+            egui::Grid::new("sky").num_columns(2).show(ui, |ui| {
+                ui.label("Sun heading");
+                ui.add(egui::Slider::new(&mut new_heading, 0.0..=360.0).suffix("°"));
+                ui.end_row();
+
+                ui.label("Sun elevation");
+                ui.add(egui::Slider::new(&mut new_elevation, -90.0..=90.0).suffix("°"));
+                ui.end_row();
+
+                ui.label("Sun color");
+                ui.add(egui::Slider::new(&mut edited.temperature, TEMPERATURE_RANGE).suffix(" K"));
+                ui.end_row();
+
+                ui.label("Sun brightness");
+                ui.add(egui::Slider::new(&mut edited.illuminance, ILLUMINANCE_RANGE).logarithmic(true).suffix(" lx"));
+                ui.end_row();
+
+                ui.label("Ambient color");
+                let ambient = edited.ambient_color.to_srgba();
+                let mut rgb = [ambient.red, ambient.green, ambient.blue];
+                if ui.color_edit_button_rgb(&mut rgb).changed() {
+                    edited.ambient_color = Color::srgb(rgb[0], rgb[1], rgb[2]);
+                }
+                ui.end_row();
+
+                ui.label("Ambient brightness");
+                ui.add(egui::Slider::new(&mut edited.ambient_brightness, AMBIENT_RANGE).logarithmic(true));
+                ui.end_row();
+
+                ui.label("Night")
+                    .on_hover_text("turns on brick lights, even past the light limit");
+                ui.checkbox(&mut edited.night, "brick lights on");
+                ui.end_row();
+            });
+        });
+
+    if let Some(preset) = preset {
+        apply_preset(preset, &mut lighting, &mut sun);
+    } else {
+        // only flag a change when there is one, so nothing reapplies every frame
+        if edited != *lighting {
+            edited.preset = None;
+            *lighting = edited;
+        }
+        if new_heading != heading || new_elevation != elevation {
+            sun.look_to(sun_direction(new_heading, new_elevation), Vec3::Y);
+        }
+    }
+    if !still_open {
+        open.0 = false;
+        if settings.lighting != *lighting {
+            settings.lighting = *lighting;
+        }
+        stored.save_changes(&settings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kelvin_color_runs_from_warm_to_cool() {
+        let warm = kelvin_color(2000.0).to_srgba();
+        let cool = kelvin_color(12000.0).to_srgba();
+        assert_eq!(warm.red, 1.0);
+        assert!(warm.blue < warm.green && warm.green < warm.red);
+        assert_eq!(cool.blue, 1.0);
+        assert!(cool.red < cool.blue);
+    }
+
+    #[test]
+    fn kelvin_color_is_about_white_at_6600k() {
+        let white = kelvin_color(6600.0).to_srgba();
+        for channel in [white.red, white.green, white.blue] {
+            assert!(channel > 0.95, "{white:?}");
+        }
+    }
+
+    #[test]
+    fn kelvin_color_clamps_out_of_range_temperatures() {
+        assert_eq!(kelvin_color(0.0), kelvin_color(1000.0));
+        assert_eq!(kelvin_color(1.0e6), kelvin_color(40000.0));
+        let candle = kelvin_color(1000.0).to_srgba();
+        assert_eq!(candle.blue, 0.0);
+    }
+
+    #[test]
+    fn lighting_round_trips_through_toml() {
+        for preset in LightingPreset::ALL {
+            let lighting = preset.lighting();
+            let text = toml::to_string(&lighting).unwrap();
+            let read: Lighting = toml::from_str(&text).unwrap();
+            assert_eq!(read.preset, Some(preset));
+            assert_eq!(read.temperature, lighting.temperature);
+            assert_eq!(read.illuminance, lighting.illuminance);
+            assert_eq!(read.night, lighting.night);
+        }
+        let custom = Lighting { preset: None, illuminance: 1234.0, ..default() };
+        let read: Lighting = toml::from_str(&toml::to_string(&custom).unwrap()).unwrap();
+        assert_eq!(read.preset, None);
+        assert_eq!(read.illuminance, 1234.0);
+    }
+
+    #[test]
+    fn lighting_fills_in_and_clamps_what_the_file_says() {
+        let read: Lighting = toml::from_str("illuminance = 1e9\npreset = \"dusk\"").unwrap();
+        assert_eq!(read.illuminance, *ILLUMINANCE_RANGE.end());
+        assert_eq!(read.preset, None);
+        assert_eq!(read.temperature, Lighting::default().temperature);
+    }
+}
@@ -1,4 +1,4 @@
-use brickadia::save::Color;
+use brickadia::save::{Brick, BrickColor, Color, SaveData};
 
 pub fn cc(c: &Color) -> [f32; 4] {
     [
@@ -14,3 +14,43 @@ pub fn cc(c: &Color) -> [f32; 4] {
 pub fn cu8(c: &Color) -> [u8; 4] {
     [c.r, c.g, c.b, 0]
 }
+
+// White, for palette indices past the end of the save's palette.
+static MISSING_COLOR: Color = Color { r: 255, g: 255, b: 255, a: 255 };
+
+// Resolve a brick's color through the save's palette when it's indexed.
+pub fn brick_color<'a>(save_data: &'a SaveData, brick: &'a Brick) -> &'a Color {
+    match &brick.color {
+        BrickColor::Index(c) => save_data.header2.colors.get(*c as usize).unwrap_or(&MISSING_COLOR),
+        BrickColor::Unique(color) => color,
+    }
+}
+
+// Owner index 0 is the public owner; the rest are 1-based into `brick_owners`.
+pub fn owner_name<'a>(save_data: &'a SaveData, brick: &Brick) -> &'a str {
+    if brick.owner_index == 0 {
+        return "PUBLIC";
+    }
+    save_data.header2.brick_owners
+        .get(brick.owner_index as usize - 1)
+        .map(|owner| owner.name.as_str())
+        .unwrap_or("UNKNOWN")
+}
+
+pub fn asset_name<'a>(save_data: &'a SaveData, brick: &Brick) -> &'a str {
+    save_data.header2.brick_assets
+        .get(brick.asset_name_index as usize)
+        .map(|name| name.as_str())
+        .unwrap_or("UNKNOWN")
+}
+
+pub fn material_name<'a>(save_data: &'a SaveData, brick: &Brick) -> &'a str {
+    save_data.header2.materials
+        .get(brick.material_index as usize)
+        .map(|name| name.as_str())
+        .unwrap_or("UNKNOWN")
+}
+
+pub fn hex_color(c: &Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+}
@@ -7,13 +7,15 @@ use bevy::{
     render::render_resource::PrimitiveTopology,
     window::PrimaryWindow,
 };
+use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
 
-use crate::cam::IsoCamera;
+use crate::cam::{CameraPose, CameraTween, IsoCamera, ViewPreset, ViewSettings};
 use crate::state::{BuildLoaded, Screenshotting};
 
 // CAD-style view cube: a chamfered cube rendered by a second camera into a
 // small corner viewport. Faces, edge bevels, and corners are separate meshes;
 // hovering highlights the region and clicking snaps the camera to that view.
+// A preset picker underneath sets the projection the top corners snap to.
 
 const CUBE_LAYER: usize = 1;
 // Chamfer width; faces span [-FACE_EXTENT, FACE_EXTENT] on a unit half-size cube.
@@ -53,7 +55,8 @@ impl Plugin for ViewCubePlugin {
         app
             .init_resource::<ViewCubeHover>()
             .add_systems(Startup, spawn_viewcube)
-            .add_systems(Update, (toggle_viewcube, fit_viewcube_viewport, sync_viewcube_cam, pick_viewcube));
+            .add_systems(Update, (toggle_viewcube, fit_viewcube_viewport, sync_viewcube_cam, pick_viewcube))
+            .add_systems(EguiPrimaryContextPass, preset_picker);
     }
 }
 
@@ -270,12 +273,14 @@ fn sync_viewcube_cam(
 fn pick_viewcube(
     window_query: Query<&Window, With<PrimaryWindow>>,
     cube_cam_query: Query<(&Camera, &GlobalTransform), With<ViewCubeCam>>,
-    mut iso_cam_query: Query<&mut IsoCamera>,
+    mut commands: Commands,
+    iso_cam_query: Query<(Entity, &IsoCamera, &Projection)>,
     pieces: Query<(&CubePiece, &MeshMaterial3d<StandardMaterial>)>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mouse: Res<ButtonInput<MouseButton>>,
     mut hover_state: ResMut<ViewCubeHover>,
     build_loaded: Res<BuildLoaded>,
+    settings: Res<ViewSettings>,
     mut hovered: Local<Option<IVec3>>,
 ) {
     if !build_loaded.0 {
@@ -300,10 +305,10 @@ fn pick_viewcube(
 
     if let Some(dir) = region {
         if mouse.just_pressed(MouseButton::Left) {
-            if let Ok(mut cam) = iso_cam_query.single_mut() {
-                if let Some((horizontal, vertical)) = region_angles(dir, cam.horizontal_angle) {
-                    cam.horizontal_angle = horizontal;
-                    cam.vertical_angle = vertical;
+            if let Ok((entity, cam, projection)) = iso_cam_query.single() {
+                if let Some((horizontal_angle, vertical_angle)) = region_angles(dir, cam.horizontal_angle, settings.preset) {
+                    let pose = CameraPose { horizontal_angle, vertical_angle, ..CameraPose::current(cam, projection) };
+                    commands.entity(entity).insert(CameraTween::to(pose));
                 }
             }
         }
@@ -363,15 +368,70 @@ fn classify(point: Vec3) -> IVec3 {
 }
 
 // Region direction → camera angles. Horizontal angle comes from the region's
-// XZ heading; regions on the top ring pitch to 45°, side regions to 90°.
-// Bottom regions map to their side equivalent (the camera can't go below the
-// horizon), except the bottom face itself, which has no valid view.
-fn region_angles(dir: IVec3, current_horizontal: f32) -> Option<(f32, f32)> {
+// XZ heading; regions on the top ring pitch to the preset's elevation, side
+// regions to 90°. Top corners also take the preset's heading within their
+// quadrant, so a trimetric preset stays trimetric. Bottom regions map to their
+// side equivalent (the camera can't go below the horizon), except the bottom
+// face itself, which has no valid view.
+fn region_angles(dir: IVec3, current_horizontal: f32, preset: ViewPreset) -> Option<(f32, f32)> {
     if dir.x == 0 && dir.z == 0 {
         // Top-down view keeps the current heading.
         return (dir.y > 0).then_some((current_horizontal, 0.0));
     }
-    let horizontal = (dir.z as f32).atan2(dir.x as f32).to_degrees().rem_euclid(360.0);
-    let vertical = if dir.y > 0 { 45.0 } else { 90.0 };
+    let mut horizontal = (dir.z as f32).atan2(dir.x as f32).to_degrees().rem_euclid(360.0);
+    if dir.y > 0 && dir.x != 0 && dir.z != 0 {
+        horizontal += preset.heading() - 45.0;
+    }
+    let vertical = if dir.y > 0 { preset.vertical_angle() } else { 90.0 };
     Some((horizontal, vertical))
 }
+
+// Projection presets and pixel-perfect snapping, just under the cube.
+fn preset_picker(
+    build_loaded: Res<BuildLoaded>,
+    screenshotting: Res<Screenshotting>,
+    mut settings: ResMut<ViewSettings>,
+    cam_query: Query<(Entity, &IsoCamera, &Projection)>,
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+) {
+    if !build_loaded.0 || screenshotting.0 {
+        return;
+    }
+    let Ok(ctx) = contexts.ctx_mut() else { return; };
+
+    let mut picked = None;
+    let mut pixel_perfect = settings.pixel_perfect;
+    egui::Area::new(egui::Id::new("view_presets"))
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-VIEWPORT_MARGIN, VIEWPORT_TOP + VIEWPORT_SIZE + 4.0))
+        .show(ctx, |ui| {
+            ui.set_width(VIEWPORT_SIZE);
+            egui::ComboBox::from_id_salt("view_preset")
+                .width(VIEWPORT_SIZE)
+                .selected_text(settings.preset.name())
+                .show_ui(ui, |ui| {
+                    for preset in ViewPreset::ALL {
+                        let selected = settings.preset.name() == preset.name();
+                        if ui.selectable_label(selected, preset.name()).clicked() {
+                            picked = Some(preset);
+                        }
+                    }
+                });
+            ui.checkbox(&mut pixel_perfect, "pixel perfect");
+        });
+
+    if let Some(preset) = picked {
+        // re-picking trimetric keeps custom angles from the console
+        let preset = match (preset, settings.preset) {
+            (ViewPreset::Trimetric { .. }, current @ ViewPreset::Trimetric { .. }) => current,
+            _ => preset,
+        };
+        settings.preset = preset;
+        if let Ok((entity, cam, projection)) = cam_query.single() {
+            commands.entity(entity).insert(CameraTween::to(preset.posed(CameraPose::current(cam, projection))));
+        }
+    }
+    if pixel_perfect != settings.pixel_perfect {
+        settings.pixel_perfect = pixel_perfect;
+    }
+}